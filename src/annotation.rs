@@ -0,0 +1,444 @@
+use std::io::Write;
+use std::slice;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use encoding::ErrorPolicy;
+use error::{Error,Result};
+use structure::Segmentation;
+use text_source;
+use word_sequence::Span;
+
+// Standoff annotations: labels kept beside a text rather than in it, each
+// over a span of its tokens or of its bytes. A layer holds the annotations
+// of one kind, such as sentences, part-of-speech tags or entities, in order
+// of position, and the annotations of a text are a list of named layers.
+
+/// What the spans of a layer count: the tokens of a sequence, or the bytes
+/// of a text.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Unit {
+    Tokens,
+    Bytes,
+}
+
+impl Unit {
+    /// The unit with a name, as written by `name`.
+    pub fn named(name: &str) -> Option<Unit> {
+        match name {
+            "tokens" => Some(Unit::Tokens),
+            "bytes" => Some(Unit::Bytes),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Tokens => "tokens",
+            Unit::Bytes => "bytes",
+        }
+    }
+}
+
+/// A label over a span of tokens or bytes. Labels are single lines, and may
+/// be empty where the span says all there is to say, as for sentences.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Annotation {
+    pub span: Span,
+    pub label: String,
+}
+
+impl Annotation {
+    pub fn new(span: Span, label: &str) -> Annotation {
+        assert!(span.start <= span.end, "annotation ends before it starts");
+        assert!(!label.contains(['\n', '\r']), "annotation label spans lines");
+        Annotation { span, label: label.to_string() }
+    }
+
+    /// Whether the annotation shares a position with a span; an empty one
+    /// overlaps the spans it lies strictly inside.
+    pub fn overlaps(&self, span: Span) -> bool {
+        self.span.start < span.end && span.start < self.span.end
+    }
+
+    /// Whether the annotation lies within a span.
+    pub fn within(&self, span: Span) -> bool {
+        span.start <= self.span.start && self.span.end <= span.end
+    }
+}
+
+// ----------------------------------------
+
+/// The annotations of one kind, ordered by start and then by end.
+#[derive(Clone,Debug)]
+pub struct Layer {
+    pub name: String,
+    pub unit: Unit,
+    annotations: Vec<Annotation>,
+    // The annotations read as a binary tree, the middle of each range the
+    // parent of its two halves, with the greatest end in each subtree, so
+    // that overlap queries can pass over subtrees ending too soon. It is
+    // built by the first query after the annotations change.
+    max_ends: OnceLock<Vec<usize>>,
+}
+
+impl PartialEq for Layer {
+    fn eq(&self, other: &Layer) -> bool {
+        self.name == other.name && self.unit == other.unit && self.annotations == other.annotations
+    }
+}
+
+impl Eq for Layer {}
+
+impl Layer {
+    pub fn new(name: &str, unit: Unit) -> Layer {
+        assert!(!name.is_empty() && !name.contains(char::is_whitespace), "layer name {:?} is not a single word", name);
+        Layer { name: name.to_string(), unit, annotations: Vec::new(), max_ends: OnceLock::new() }
+    }
+
+    /// A layer of token spans, one for each unit of a segmentation, with
+    /// empty labels.
+    pub fn from_segmentation(name: &str, segmentation: &Segmentation) -> Layer {
+        let mut layer = Layer::new(name, Unit::Tokens);
+        for unit in 0..segmentation.len() {
+            let range = segmentation.range(unit);
+            layer.annotations.push(Annotation::new(Span::new(range.start, range.end), ""));
+        }
+        layer.sort();
+        layer
+    }
+
+    /// Add an annotation, keeping the layer in order; annotations with the
+    /// same span stay in the order they were added. Adding annotations in
+    /// order, as a tagger does, takes constant time for each.
+    pub fn add(&mut self, span: Span, label: &str) {
+        let key = (span.start, span.end);
+        let i = self.annotations.partition_point(|a| (a.span.start, a.span.end) <= key);
+        self.annotations.insert(i, Annotation::new(span, label));
+        self.max_ends = OnceLock::new();
+    }
+
+    // Put annotations pushed onto the end in order, as `add` would have.
+    fn sort(&mut self) {
+        self.annotations.sort_by_key(|a| (a.span.start, a.span.end));
+        self.max_ends = OnceLock::new();
+    }
+
+    fn max_ends(&self) -> &[usize] {
+        fn max_end(annotations: &[Annotation], max_ends: &mut [usize], lo: usize, hi: usize) -> usize {
+            if lo == hi { return 0; }
+            let mid = lo + (hi - lo) / 2;
+            let left = max_end(annotations, max_ends, lo, mid);
+            let right = max_end(annotations, max_ends, mid + 1, hi);
+            max_ends[mid] = annotations[mid].span.end.max(left).max(right);
+            max_ends[mid]
+        }
+        self.max_ends.get_or_init(|| {
+            let mut max_ends = vec!(0; self.annotations.len());
+            max_end(&self.annotations, &mut max_ends, 0, self.annotations.len());
+            max_ends
+        })
+    }
+
+    // Add the annotations between lo and hi that overlap a span, in order.
+    fn find_overlapping<'a>(&'a self, max_ends: &[usize], span: Span, lo: usize, hi: usize, found: &mut Vec<&'a Annotation>) {
+        if lo == hi { return; }
+        let mid = lo + (hi - lo) / 2;
+        if max_ends[mid] <= span.start { return; }
+        self.find_overlapping(max_ends, span, lo, mid, found);
+        let a = &self.annotations[mid];
+        if a.span.start < span.end {
+            if a.overlaps(span) {
+                found.push(a);
+            }
+            self.find_overlapping(max_ends, span, mid + 1, hi, found);
+        }
+    }
+
+    pub fn len(&self) -> usize { self.annotations.len() }
+    pub fn is_empty(&self) -> bool { self.annotations.is_empty() }
+
+    pub fn annotations(&self) -> &[Annotation] { &self.annotations }
+
+    pub fn iter(&self) -> slice::Iter<'_, Annotation> { self.annotations.iter() }
+
+    /// The annotations overlapping a span.
+    pub fn overlapping(&self, span: Span) -> impl Iterator<Item=&Annotation> {
+        let mut found = Vec::new();
+        self.find_overlapping(self.max_ends(), span, 0, self.annotations.len(), &mut found);
+        found.into_iter()
+    }
+
+    /// The annotations lying within a span, including empty ones.
+    pub fn within(&self, span: Span) -> impl Iterator<Item=&Annotation> {
+        let start = self.annotations.partition_point(|a| a.span.start < span.start);
+        let end = self.annotations.partition_point(|a| a.span.start <= span.end);
+        self.annotations[start..end].iter().filter(move |a| a.within(span))
+    }
+
+    /// The annotations covering a position.
+    pub fn at(&self, position: usize) -> impl Iterator<Item=&Annotation> {
+        self.overlapping(Span::new(position, position + 1))
+    }
+
+    /// The layer over token spans, given the byte spans of the tokens: each
+    /// annotation covers the tokens it overlaps, and an empty one is placed
+    /// before the first token not ending before it.
+    pub fn to_tokens(&self, spans: &[Span]) -> Layer {
+        assert_eq!(self.unit, Unit::Bytes, "layer {} is not over bytes", self.name);
+        let mut layer = Layer::new(&self.name, Unit::Tokens);
+        for a in &self.annotations {
+            let start = spans.partition_point(|t| t.end <= a.span.start);
+            let end = if a.span.is_empty() { start } else { spans.partition_point(|t| t.start < a.span.end) };
+            layer.annotations.push(Annotation::new(Span::new(start, end.max(start)), &a.label));
+        }
+        layer.sort();
+        layer
+    }
+
+    /// The layer over byte spans, given the byte spans of the tokens: each
+    /// annotation runs from the start of its first token to the end of its
+    /// last. An annotation reaching past the tokens is an error.
+    pub fn to_bytes(&self, spans: &[Span]) -> Result<Layer> {
+        assert_eq!(self.unit, Unit::Tokens, "layer {} is not over tokens", self.name);
+        let position = |i: usize| spans.get(i).map_or_else(|| spans.last().map_or(0, |t| t.end), |t| t.start);
+        let mut layer = Layer::new(&self.name, Unit::Bytes);
+        for a in &self.annotations {
+            if a.span.end > spans.len() {
+                return Err(Error::MalformedAnnotations(format!("annotation at {}..{} in {} is beyond the {} tokens",
+                                                               a.span.start, a.span.end, self.name, spans.len())));
+            }
+            let span = if a.span.is_empty() {
+                Span::new(position(a.span.start), position(a.span.start))
+            } else {
+                Span::new(spans[a.span.start].start, spans[a.span.end-1].end)
+            };
+            layer.annotations.push(Annotation::new(span, &a.label));
+        }
+        layer.sort();
+        Ok(layer)
+    }
+}
+
+impl<'a> IntoIterator for &'a Layer {
+    type Item = &'a Annotation;
+    type IntoIter = slice::Iter<'a, Annotation>;
+    fn into_iter(self) -> slice::Iter<'a, Annotation> { self.iter() }
+}
+
+#[test]
+fn test_layer() {
+    let mut entities = Layer::new("entities", Unit::Tokens);
+    entities.add(Span::new(5, 7), "PERSON");
+    entities.add(Span::new(0, 2), "PERSON");
+    entities.add(Span::new(0, 1), "TITLE");
+    entities.add(Span::new(3, 3), "GAP");
+    let labels = |it: &mut dyn Iterator<Item=&Annotation>| it.map(|a| a.label.clone()).collect::<Vec<_>>().join(" ");
+    assert_eq!(labels(&mut entities.iter()), "TITLE PERSON GAP PERSON");
+    assert_eq!(labels(&mut entities.overlapping(Span::new(1, 6))), "PERSON GAP PERSON");
+    assert_eq!(labels(&mut entities.overlapping(Span::new(2, 3))), "");
+    assert_eq!(labels(&mut entities.within(Span::new(0, 3))), "TITLE PERSON GAP");
+    assert_eq!(labels(&mut entities.at(0)), "TITLE PERSON");
+    assert_eq!(labels(&mut entities.at(4)), "");
+}
+
+#[test]
+fn test_overlapping() {
+    // Against a scan, over spans of all lengths including empty ones.
+    let mut layer = Layer::new("spans", Unit::Bytes);
+    let mut seed: usize = 7;
+    let mut next = |n: usize| { seed = (seed * 1103515245 + 12345) % (1 << 31); seed % n };
+    // Queries between additions see the annotations added since.
+    for _ in 0..3 {
+        for _ in 0..100 {
+            let start = next(200);
+            let len = if next(10) == 0 { next(200) } else { next(5) };
+            layer.add(Span::new(start, start + len), "");
+        }
+        for start in 0..210 {
+            for len in 0..4 {
+                let span = Span::new(start, start + len);
+                let expected: Vec<&Annotation> = layer.iter().filter(|a| a.overlaps(span)).collect();
+                assert_eq!(layer.overlapping(span).collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_conversion() {
+    use word_sequence::WordSequence;
+    let text = "Aunt Polly looked over her spectacles.";
+    let ws = WordSequence::new(text, |s| s, |_| true);
+    let mut bytes = Layer::new("entities", Unit::Bytes);
+    bytes.add(Span::new(0, 10), "PERSON");
+    bytes.add(Span::new(19, 20), "PART");
+    bytes.add(Span::new(37, 37), "END");
+    let tokens = bytes.to_tokens(&ws.spans);
+    let spans: Vec<Span> = tokens.iter().map(|a| a.span).collect();
+    assert_eq!(spans, vec!(Span::new(0, 2), Span::new(3, 4), Span::new(6, 6)));
+    let back = unwrap!( tokens.to_bytes(&ws.spans).ok() );
+    let spans: Vec<Span> = back.iter().map(|a| a.span).collect();
+    assert_eq!(spans, vec!(Span::new(0, 10), Span::new(18, 22), Span::new(37, 37)));
+    let mut beyond = Layer::new("entities", Unit::Tokens);
+    beyond.add(Span::new(7, 8), "PAST");
+    match beyond.to_bytes(&ws.spans) {
+        Err(Error::MalformedAnnotations(ref m)) if m.contains("7..8") => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+    let sentences = Layer::from_segmentation("sentences", &Segmentation::new(vec!(0, 4, 7)));
+    assert_eq!(sentences.len(), 2);
+    assert_eq!(sentences.annotations()[1].span, Span::new(4, 7));
+}
+
+// ----------------------------------------
+
+/// The annotations of a text, as layers with distinct names.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct Annotations {
+    layers: Vec<Layer>,
+}
+
+impl Annotations {
+    pub fn new() -> Annotations { Annotations::default() }
+
+    /// The annotations in a file, as read by `parse`, decoded from
+    /// whatever encoding it appears to be in.
+    pub fn from_file(filename: &str) -> Result<Annotations> {
+        let source = text_source::open(filename)?;
+        Annotations::parse(&source.decode(ErrorPolicy::Strict)?.text)
+    }
+
+    /// Read annotations in the format written by `write`: for each layer,
+    /// a line `layer NAME UNIT`, where the unit is `tokens` or `bytes`,
+    /// followed by a line `START END LABEL` for each annotation. Blank
+    /// lines and lines starting with # are ignored.
+    pub fn parse(source: &str) -> Result<Annotations> {
+        let mut annotations = Annotations::new();
+        let mut current: Option<usize> = None;
+        for (n,line) in source.lines().enumerate() {
+            let malformed = |m: String| Error::MalformedAnnotations(format!("line {}: {}", n + 1, m));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (first, rest) = split_field(line);
+            if first == "layer" {
+                let fields: Vec<&str> = rest.split_whitespace().collect();
+                let (name, unit) = match fields[..] {
+                    [name, unit] => (name, Unit::named(unit).ok_or_else(|| malformed(format!("unknown unit {}", unit)))?),
+                    _ => return Err(malformed("expected layer NAME UNIT".to_string())),
+                };
+                if annotations.layer(name).is_some() {
+                    return Err(malformed(format!("layer {} repeated", name)));
+                }
+                annotations.layers.push(Layer::new(name, unit));
+                current = Some(annotations.layers.len() - 1);
+                continue;
+            }
+            let layer = match current {
+                Some(i) => &mut annotations.layers[i],
+                None => return Err(malformed("annotation before any layer".to_string())),
+            };
+            let (second, label) = split_field(rest);
+            match (usize::from_str(first), usize::from_str(second)) {
+                (Ok(start), Ok(end)) if start <= end => layer.annotations.push(Annotation::new(Span::new(start, end), label)),
+                _ => return Err(malformed(format!("bad span {} {}", first, second))),
+            }
+        }
+        for layer in &mut annotations.layers {
+            layer.sort();
+        }
+        Ok(annotations)
+    }
+
+    /// Write the annotations in the format read by `parse`.
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        for layer in &self.layers {
+            writeln!(out, "layer {} {}", layer.name, layer.unit.name()).map_err(Error::Write)?;
+            for a in layer {
+                let line = format!("{} {} {}", a.span.start, a.span.end, a.label);
+                writeln!(out, "{}", line.trim_end()).map_err(Error::Write)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a layer, replacing any with the same name.
+    pub fn insert(&mut self, layer: Layer) {
+        match self.layers.iter().position(|l| l.name == layer.name) {
+            Some(i) => self.layers[i] = layer,
+            None => self.layers.push(layer),
+        }
+    }
+
+    /// The layer with a name, added empty if there is none.
+    pub fn layer_mut(&mut self, name: &str, unit: Unit) -> &mut Layer {
+        let i = match self.layers.iter().position(|l| l.name == name) {
+            Some(i) => i,
+            None => {
+                self.layers.push(Layer::new(name, unit));
+                self.layers.len() - 1
+            }
+        };
+        &mut self.layers[i]
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> { self.layers.iter().find(|l| l.name == name) }
+
+    pub fn remove(&mut self, name: &str) -> Option<Layer> {
+        self.layers.iter().position(|l| l.name == name).map(|i| self.layers.remove(i))
+    }
+
+    /// The number of layers.
+    pub fn len(&self) -> usize { self.layers.len() }
+    pub fn is_empty(&self) -> bool { self.layers.is_empty() }
+
+    /// The layers, in the order they were added.
+    pub fn iter(&self) -> slice::Iter<'_, Layer> { self.layers.iter() }
+}
+
+impl<'a> IntoIterator for &'a Annotations {
+    type Item = &'a Layer;
+    type IntoIter = slice::Iter<'a, Layer>;
+    fn into_iter(self) -> slice::Iter<'a, Layer> { self.iter() }
+}
+
+// The first whitespace-separated field of a line and the rest, trimmed.
+fn split_field(line: &str) -> (&str,&str) {
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+#[test]
+fn test_format() {
+    let source = "# gold\nlayer pos tokens\n0 1 NNP\n1 2 VBD\n\nlayer senses bytes\n4 9 polly%1:05:00::  pet\n10 10\n";
+    let annotations = unwrap!( Annotations::parse(source).ok() );
+    assert_eq!(annotations.len(), 2);
+    let senses = unwrap!( annotations.layer("senses") );
+    assert_eq!(senses.unit, Unit::Bytes);
+    assert_eq!(senses.annotations()[0].label, "polly%1:05:00::  pet");
+    assert_eq!(senses.annotations()[1], Annotation::new(Span::new(10, 10), ""));
+    let mut out = Vec::new();
+    unwrap!( annotations.write(&mut out).ok() );
+    let written = unwrap!( String::from_utf8(out).ok() );
+    assert_eq!(written, "layer pos tokens\n0 1 NNP\n1 2 VBD\nlayer senses bytes\n4 9 polly%1:05:00::  pet\n10 10\n");
+    assert_eq!(unwrap!( Annotations::parse(&written).ok() ), annotations);
+    for bad in &["0 1 NNP\n", "layer pos\n", "layer pos lines\n", "layer pos tokens\n2 1 X\n", "layer a bytes\nlayer a bytes\n"] {
+        match Annotations::parse(bad) {
+            Err(Error::MalformedAnnotations(ref m)) if m.starts_with("line ") => { }
+            r => panic!("{:?} accepted: {:?}", bad, r),
+        }
+    }
+}
+
+#[test]
+fn test_from_file() {
+    let file = std::env::temp_dir().join("nl_foundations-annotations.txt");
+    unwrap!( std::fs::write(&file, b"\xef\xbb\xbflayer pos tokens\n0 1 NNP\n").ok() );
+    let annotations = unwrap!( Annotations::from_file(&file.to_string_lossy()).ok() );
+    assert_eq!(unwrap!( annotations.layer("pos") ).annotations()[0].label, "NNP");
+    let _ = std::fs::remove_file(&file);
+}
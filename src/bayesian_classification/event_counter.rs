@@ -7,6 +7,10 @@ use std::ops::Index;
 #[derive(Debug)]
 pub struct EventCtr<T: Eq + Hash>{ m: HashMap<T,usize>}
 
+impl<T: Eq + Hash> Default for EventCtr<T> {
+    fn default() -> EventCtr<T> { EventCtr::new() }
+}
+
 impl<T> EventCtr<T>
     where T: Eq + Hash {
     pub fn new() -> EventCtr<T> { EventCtr{ m: HashMap::new() } }
@@ -15,13 +19,20 @@ impl<T> EventCtr<T>
         *self.m.entry(k).or_insert(0) += 1;
     }
 
-    delegate!( m: pub mut entry(key:T) -> hash_map::Entry<T,usize> );
+    /// Add the counts of another counter to this one.
+    pub fn merge(&mut self, other: EventCtr<T>) {
+        for (k,n) in other.m {
+            *self.m.entry(k).or_insert(0) += n;
+        }
+    }
+
+    delegate!( m: pub mut entry(key:T) -> hash_map::Entry<'_,T,usize> );
     delegate!( m: pub get(k:&T) -> Option<&usize>,
-                  pub values() -> hash_map::Values<T,usize>,
-                  pub iter() -> hash_map::Iter<T,usize> );
+                  pub values() -> hash_map::Values<'_,T,usize>,
+                  pub iter() -> hash_map::Iter<'_,T,usize> );
 }
 
-impl<'l, T: Eq + Hash> Index<&'l T> for EventCtr<T> {
+impl<T: Eq + Hash> Index<&T> for EventCtr<T> {
     type Output = usize;
     delegate!( m: index(idx:&T) -> &usize );
 }
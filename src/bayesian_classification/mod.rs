@@ -1,3 +1,4 @@
 pub mod event_counter;
 pub mod training;
 pub mod model;
+pub mod readers;
@@ -0,0 +1,204 @@
+use annotation::{Layer,Unit};
+use error::{Error,Result};
+use word_sequence::WordSequence;
+
+// Readers of annotated training text. Each tokenizes a text and labels its
+// tokens for `Trainer::train_labeled`: tokens satisfying the reader's
+// candidate predicate are examples, positive where the annotation marks
+// them and negative elsewhere, and other tokens are context only.
+
+/// A format of annotated training text.
+pub trait Reader {
+    /// Whether a token, without annotation, is a candidate: an event that
+    /// may be a positive example.
+    fn is_candidate(&self, token: &str) -> bool;
+
+    /// Append the tokens of an annotated text to a sequence, returning for
+    /// each whether it is a positive example, a negative one, or no
+    /// example at all.
+    fn append<'a>(&self, ws: &mut WordSequence<&'a str>, text: &'a str) -> Result<Vec<Option<bool>>>;
+}
+
+// Append a text to a sequence, labelling its candidates negative.
+fn append_candidates<'a,R>(reader: &R, ws: &mut WordSequence<&'a str>, text: &'a str) -> Vec<Option<bool>>
+    where R: Reader + ?Sized {
+    let start = ws.len();
+    ws.append(text, |s| s, |_| true);
+    ws.spans[start..].iter()
+        .map(|span| reader.is_candidate(&text[span.start..span.end]).then_some(false))
+        .collect()
+}
+
+// ----------------------------------------
+
+/// Positive examples marked in the text by a character ending the token,
+/// as in `No answer.+`. The marker is removed from the sequence, so that
+/// marked and unmarked candidates are the same event.
+pub struct InlineMarker<P> {
+    marker: char,
+    is_candidate: P,
+}
+
+impl<P> InlineMarker<P> where P: Fn(&str) -> bool {
+    pub fn new(marker: char, is_candidate: P) -> InlineMarker<P> {
+        InlineMarker { marker, is_candidate }
+    }
+}
+
+impl<P> Reader for InlineMarker<P> where P: Fn(&str) -> bool {
+    fn is_candidate(&self, token: &str) -> bool { (self.is_candidate)(token) }
+
+    fn append<'a>(&self, ws: &mut WordSequence<&'a str>, text: &'a str) -> Result<Vec<Option<bool>>> {
+        let start = ws.len();
+        ws.append(text, |s| s, |_| true);
+        let mut labels = Vec::with_capacity(ws.len() - start);
+        for i in start..ws.len() {
+            let token: &'a str = &text[ws.spans[i].start..ws.spans[i].end];
+            labels.push(match token.strip_suffix(self.marker) {
+                Some(unmarked) if !unmarked.is_empty() && self.is_candidate(unmarked) => {
+                    let class = unwrap!( ws.class_of(ws.words[i]) );
+                    ws.words[i] = ws.insert_word(unmarked, class);
+                    Some(true)
+                }
+                _ => self.is_candidate(token).then_some(false),
+            });
+        }
+        Ok(labels)
+    }
+}
+
+// ----------------------------------------
+
+/// Positive examples given by a layer of standoff annotations, over the
+/// bytes of the text or its tokens. Each annotation marks the candidates it
+/// covers, or if it is empty, the candidate just before it, as for the
+/// boundary at the end of a sentence.
+pub struct Standoff<P> {
+    layer: Layer,
+    is_candidate: P,
+}
+
+impl<P> Standoff<P> where P: Fn(&str) -> bool {
+    pub fn new(layer: Layer, is_candidate: P) -> Standoff<P> {
+        Standoff { layer, is_candidate }
+    }
+}
+
+impl<P> Reader for Standoff<P> where P: Fn(&str) -> bool {
+    fn is_candidate(&self, token: &str) -> bool { (self.is_candidate)(token) }
+
+    fn append<'a>(&self, ws: &mut WordSequence<&'a str>, text: &'a str) -> Result<Vec<Option<bool>>> {
+        let start = ws.len();
+        let mut labels = append_candidates(self, ws, text);
+        let converted;
+        let layer = match self.layer.unit {
+            Unit::Tokens => &self.layer,
+            Unit::Bytes => {
+                converted = self.layer.to_tokens(&ws.spans[start..]);
+                &converted
+            }
+        };
+        // Conversion keeps the annotations in order.
+        for (original,a) in self.layer.iter().zip(layer) {
+            let tokens = if a.span.is_empty() { a.span.start.saturating_sub(1)..a.span.start } else { a.span.start..a.span.end };
+            let mut marked = false;
+            for label in labels.get_mut(tokens).into_iter().flatten().filter(|label| label.is_some()) {
+                *label = Some(true);
+                marked = true;
+            }
+            if !marked {
+                let span = original.span;
+                return Err(Error::MalformedTraining(format!("annotation at {}..{} in {} marks no candidate", span.start, span.end, self.layer.name)));
+            }
+        }
+        Ok(labels)
+    }
+}
+
+// ----------------------------------------
+
+/// Positive examples at the ends of lines, in text with one sentence on
+/// each line: the first of the candidates ending a line, as the ? of
+/// `I wonder?"`.
+pub struct SentencePerLine<P> {
+    is_candidate: P,
+}
+
+impl<P> SentencePerLine<P> where P: Fn(&str) -> bool {
+    pub fn new(is_candidate: P) -> SentencePerLine<P> {
+        SentencePerLine { is_candidate }
+    }
+}
+
+impl<P> Reader for SentencePerLine<P> where P: Fn(&str) -> bool {
+    fn is_candidate(&self, token: &str) -> bool { (self.is_candidate)(token) }
+
+    fn append<'a>(&self, ws: &mut WordSequence<&'a str>, text: &'a str) -> Result<Vec<Option<bool>>> {
+        let start = ws.len();
+        let mut labels = append_candidates(self, ws, text);
+        let spans = &ws.spans[start..];
+        let breaks = |i: usize| text[spans[i].end..spans[i+1].start].contains('\n');
+        for last in (0..spans.len()).filter(|&i| i + 1 == spans.len() || breaks(i)) {
+            let mut first = last + 1;
+            while first > 0 && labels[first-1].is_some() && (first == last + 1 || !breaks(first - 1)) {
+                first -= 1;
+            }
+            if first <= last {
+                labels[first] = Some(true);
+            }
+        }
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+fn marked(text: &str, reader: &dyn Reader) -> Result<String> {
+    let mut ws = WordSequence::empty();
+    let labels = reader.append(&mut ws, text)?;
+    Ok(ws.words.iter().zip(labels).map(|(w,label)| match label {
+        Some(true) => format!("{}+", ws[*w]),
+        Some(false) => format!("{}-", ws[*w]),
+        None => ws[*w].to_string(),
+    }).collect::<Vec<_>>().join(" "))
+}
+
+#[test]
+fn test_inline_marker() {
+    use filters::is_punctuation;
+    let text = "\"TOM!+\"\n\nNo answer.+ Mr. Jones, 3+ times.+";
+    assert_eq!(unwrap!( marked(text, &InlineMarker::new('+', is_punctuation)).ok() ),
+               "\"- TOM !+ \"- No answer .+ Mr .- Jones ,- 3 +- times .+");
+    let text = "One.| Two.";
+    assert_eq!(unwrap!( marked(text, &InlineMarker::new('|', |s: &str| s == ".")).ok() ), "One .+ Two .-");
+}
+
+#[test]
+fn test_standoff() {
+    use annotation::Annotations;
+    use word_sequence::Span;
+    use filters::is_punctuation;
+    let text = "\"Tom!\" No answer. Mr. Jones.";
+    let annotations = unwrap!( Annotations::parse("layer ends bytes\n4 5\n16 17\n28 28\n").ok() );
+    let layer = unwrap!( annotations.layer("ends") ).clone();
+    assert_eq!(unwrap!( marked(text, &Standoff::new(layer, is_punctuation)).ok() ),
+               "\"- Tom !+ \"- No answer .+ Mr .- Jones .+");
+    let mut layer = Layer::new("ends", Unit::Tokens);
+    layer.add(Span::new(3, 4), "");
+    layer.add(Span::new(3, 3), "");
+    assert_eq!(unwrap!( marked(text, &Standoff::new(layer, is_punctuation)).ok() ),
+               "\"- Tom !+ \"+ No answer .- Mr .- Jones .-");
+    let mut layer = Layer::new("ends", Unit::Bytes);
+    layer.add(Span::new(8, 9), "");
+    match marked(text, &Standoff::new(layer, is_punctuation)) {
+        Err(Error::MalformedTraining(ref m)) if m.contains("8..9") => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn test_sentence_per_line() {
+    use filters::is_punctuation;
+    let text = "\"What's gone with that boy, I wonder?\"\nNo answer\n--\r\n\nHe left.\n";
+    assert_eq!(unwrap!( marked(text, &SentencePerLine::new(is_punctuation)).ok() ),
+               "\"- What '- s gone with that boy ,- I wonder ?+ \"- No answer --+ He left .+");
+}
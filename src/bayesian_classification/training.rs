@@ -2,8 +2,11 @@ use std::cmp::max;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::iter::FromIterator;
+use std::ops::Range;
 
 use bayesian_classification::event_counter::EventCtr;
+use error::{Error,Result};
+use parallel;
 
 /// Collection of statistics about a binary event.
 #[derive(Debug)]
@@ -14,6 +17,10 @@ pub struct CtxCounter<T: Eq + Hash> {
     neg_context: EventCtr<T>,
 }
 
+impl<T: Eq + Hash> Default for CtxCounter<T> {
+    fn default() -> CtxCounter<T> { CtxCounter::new() }
+}
+
 impl<T: Eq + Hash> CtxCounter<T> {
 
     pub fn new() -> CtxCounter<T> {
@@ -38,6 +45,14 @@ impl<T: Eq + Hash> CtxCounter<T> {
         }
     }
 
+    /// Add the statistics of another counter to this one.
+    pub fn merge(&mut self, other: CtxCounter<T>) {
+        self.positive += other.positive;
+        self.total += other.total;
+        self.pos_context.merge(other.pos_context);
+        self.neg_context.merge(other.neg_context);
+    }
+
     // Calculate the raw probability of an event.
     pub fn base_probability(&self) -> f64 { (self.positive as f64) / (self.total as f64) }
 
@@ -69,7 +84,7 @@ impl<T: Eq + Hash> CtxCounter<T> {
         // to the denominator:
         //
         // (v + 1) / (word_counts[k] + Sum(word_counts))
-        let sum = word_counts.values().fold(0, |a,c| a+c);
+        let sum: usize = word_counts.values().sum();
         context.iter()
             .map(move |(k,&v)| {
                 let numer = (v+1) as f64;
@@ -102,30 +117,177 @@ impl<T: Eq + Hash + Clone> Trainer<T> {
         }
     }
 
-    pub fn train<'l,IsExample,Untag,IsTag>(&mut self,
-                                           events: &'l[T],
-                                           is_example: IsExample,
-                                           untag: Untag,
-                                           is_tag: IsTag)
+    /// Record the examples in a sequence of events. Training data without
+    /// any events, or without any positive examples, is rejected.
+    pub fn train<IsExample,Untag,IsTag>(&mut self,
+                                        events: &[T],
+                                        is_example: IsExample,
+                                        untag: Untag,
+                                        is_tag: IsTag) -> Result<()>
         where IsExample: Fn(&T) -> bool,
               Untag: Fn(&T) -> T,
               IsTag: Fn(&T) -> bool {
-        for (i,evt) in events.iter().enumerate() {
+        if events.is_empty() { return Err(Error::EmptyInput); }
+        let label = |i: usize| is_example(&events[i]).then(|| is_tag(&events[i]));
+        let positive = self.record(events, 0..events.len(), &untag, &label);
+        if positive == 0 {
+            return Err(Error::MalformedTraining("no positive examples".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Record the examples in a sequence of events labelled apart from
+    /// them, as by an annotation reader: each label says whether its event
+    /// is a positive example, a negative one, or no example at all.
+    pub fn train_labeled(&mut self, events: &[T], labels: &[Option<bool>]) -> Result<()> {
+        assert_eq!(events.len(), labels.len(), "events and labels differ in length");
+        if events.is_empty() { return Err(Error::EmptyInput); }
+        let positive = self.record(events, 0..events.len(), &|e: &T| e.clone(), &|i| labels[i]);
+        if positive == 0 {
+            return Err(Error::MalformedTraining("no positive examples".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Like `train`, dividing the events among several threads. The
+    /// statistics are the same as those of a sequential run.
+    pub fn train_parallel<IsExample,Untag,IsTag>(&mut self,
+                                                 events: &[T],
+                                                 is_example: IsExample,
+                                                 untag: Untag,
+                                                 is_tag: IsTag,
+                                                 threads: usize) -> Result<()>
+        where T: Send + Sync,
+              IsExample: Fn(&T) -> bool + Sync,
+              Untag: Fn(&T) -> T + Sync,
+              IsTag: Fn(&T) -> bool + Sync {
+        if events.is_empty() { return Err(Error::EmptyInput); }
+        let size = self.size;
+        let parts = parallel::map_ranges(events.len(), threads, |range| {
+            let mut trainer = Trainer::new(size);
+            let label = |i: usize| is_example(&events[i]).then(|| is_tag(&events[i]));
+            let positive = trainer.record(events, range, &untag, &label);
+            (trainer, positive)
+        });
+        let mut positive = 0;
+        for (trainer, p) in parts {
+            self.merge(trainer);
+            positive += p;
+        }
+        if positive == 0 {
+            return Err(Error::MalformedTraining("no positive examples".to_string()));
+        }
+        Ok(())
+    }
+
+    // Record the examples at a range of positions in a sequence of events,
+    // where `label` says whether the event at a position is a positive
+    // example, a negative one, or none, returning the number of positive
+    // examples. Contexts may reach back before the start of the range.
+    fn record<Untag,Label>(&mut self,
+                           events: &[T],
+                           range: Range<usize>,
+                           untag: &Untag,
+                           label: &Label) -> usize
+        where Untag: Fn(&T) -> T,
+              Label: Fn(usize) -> Option<bool> {
+        let mut positive = 0;
+        for i in range {
+            let evt = &events[i];
             self.seen.inc(untag(evt));
-            if is_example(evt) {
+            if let Some(tagged) = label(i) {
                 let start = max(i, self.size) - self.size;
                 let context = &events[start..i];
+                if tagged { positive += 1; }
                 self.counter(&untag(evt))
-                    .seen(context.iter().map(|w| untag(w)), is_tag(evt));
+                    .seen(context.iter().map(untag), tagged);
             }
         }
+        positive
+    }
+
+    /// Add the statistics of another trainer, with the same context size,
+    /// to this one.
+    pub fn merge(&mut self, other: Trainer<T>) {
+        assert_eq!(self.size, other.size, "context sizes differ");
+        self.seen.merge(other.seen);
+        for (event, counter) in other.contexts {
+            self.counter(&event).merge(counter);
+        }
     }
 
     pub fn size(&self) -> usize { self.size }
     pub fn seen(&self) -> &EventCtr<T> { &self.seen }
-    pub fn p_unseen(&self) -> f64 { 1.0 / (self.seen.values().fold(0, |a,c| a+c) as f64) }
+    pub fn p_unseen(&self) -> f64 { 1.0 / (self.seen.values().sum::<usize>() as f64) }
+
+    fn counter(&mut self, m: &T) -> &mut CtxCounter<T> {
+        self.contexts.entry(m.clone()).or_default()
+    }
+}
+
+#[test]
+fn test_train() {
+    let events = ["a", ".", "b", ".+", "c"];
+    let mut trainer = Trainer::new(1);
+    let res = trainer.train(&events,
+                            |e| e.starts_with('.'),
+                            |e| e.trim_end_matches('+'),
+                            |e| e.ends_with('+'));
+    assert!(res.is_ok());
+    assert_eq!(trainer.contexts[&"."].base_probability(), 0.5);
+    assert_eq!(trainer.seen()[&"."], 2);
+}
 
-    fn counter<'l>(&'l mut self, m: &T) -> &'l mut CtxCounter<T> {
-        self.contexts.entry(m.clone()).or_insert_with(|| CtxCounter::new())
+#[test]
+fn test_train_labeled() {
+    let events = ["a", ".", "b", ".", "c"];
+    let mut trainer = Trainer::new(1);
+    let res = trainer.train_labeled(&events, &[None, Some(false), None, Some(true), None]);
+    assert!(res.is_ok());
+    assert_eq!(trainer.contexts[&"."].base_probability(), 0.5);
+    assert_eq!(trainer.seen()[&"."], 2);
+    let mut trainer = Trainer::new(1);
+    match trainer.train_labeled(&events, &[None, Some(false), None, None, None]) {
+        Err(Error::MalformedTraining(_)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn test_train_parallel() {
+    let events = ["a", ".", "b", ".+", "c", "d", ".+", "e", "."];
+    let train = |threads| {
+        let mut trainer = Trainer::new(2);
+        unwrap!( trainer.train_parallel(&events,
+                                        |e| e.starts_with('.'),
+                                        |e| e.trim_end_matches('+'),
+                                        |e| e.ends_with('+'),
+                                        threads).ok() );
+        trainer
+    };
+    let sequential = train(1);
+    for threads in 2..5 {
+        let trainer = train(threads);
+        assert_eq!(trainer.seen().iter().collect::<HashMap<_,_>>(), sequential.seen().iter().collect());
+        let counter = &trainer.contexts[&"."];
+        let expected = &sequential.contexts[&"."];
+        assert_eq!((counter.positive, counter.total), (expected.positive, expected.total));
+        assert_eq!(counter.pos_context.iter().collect::<HashMap<_,_>>(), expected.pos_context.iter().collect());
+        assert_eq!(counter.neg_context.iter().collect::<HashMap<_,_>>(), expected.neg_context.iter().collect());
+    }
+}
+
+#[test]
+fn test_train_malformed() {
+    let events = ["a", ".", "b"];
+    let mut trainer = Trainer::new(1);
+    match trainer.train(&events, |e| *e == ".", |e| *e, |_| false) {
+        Err(Error::MalformedTraining(_)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+    let mut trainer: Trainer<&str> = Trainer::new(1);
+    match trainer.train(&[], |_| true, |e| *e, |_| true) {
+        Err(Error::EmptyInput) => { }
+        r => panic!("unexpected result: {:?}", r),
     }
 }
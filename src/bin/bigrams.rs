@@ -2,32 +2,136 @@ extern crate getopts;
 #[macro_use] extern crate nl_foundations;
 
 use std::cmp::Ordering;
+use std::fmt::Display;
+use std::hash::Hash;
 
 use getopts::{Options,ParsingStyle};
 
+use nl_foundations::Result;
 use nl_foundations::case_string::CaseStr;
-use nl_foundations::mmap::MappedRegion;
-use nl_foundations::word_sequence::WordSequence;
+use nl_foundations::corpus::{Corpus,CorpusSequence};
+use nl_foundations::filters::{self,BoxedFilter,Stopwords};
+use nl_foundations::options::{self,Decoding};
+use nl_foundations::parallel;
+use nl_foundations::preprocess::Preprocessor;
+use nl_foundations::text_source;
+use nl_foundations::tokenized::{self,TokenizedCorpus};
+use nl_foundations::tokenizer::RunTokenizer;
+use nl_foundations::word_sequence::{SurfaceForms,WordSequence};
 use nl_foundations::sample::Sample;
+use nl_foundations::stemmer::{Stemmer,StemStr};
+use nl_foundations::structure::{Level,Structure};
 
-fn t_significant_bigrams(file: &str) {
-    MappedRegion::mmap(file)
-        .and_then(|contents| contents.get_str().map(process_text) )
-        .expect(&format!("cannot read {}", file))
+// The symbol replacing words pruned by --min-count.
+const UNKNOWN: &str = "<unk>";
+
+// How text files are read, and how their words are keyed and counted.
+struct Counting<'a> {
+    decoding: Decoding,
+    preprocess: bool,
+    stem: Option<Stemmer>,
+    is_word: &'a BoxedFilter<'a>,
+    unit: Level,
+    min_count: Option<usize>,
+    jobs: usize,
+}
+
+fn t_significant_bigrams(file: &str, counting: &Counting) -> Result<()> {
+    let Counting { decoding, preprocess, stem, is_word, unit, min_count, jobs } = *counting;
+    let contents = text_source::open(file)?;
+    if tokenized::is_tokenized(contents.get_slice()) {
+        let tc = TokenizedCorpus::from_source(contents)?;
+        match stem {
+            None => process_sequence(tc.corpus_sequence(CaseStr::from, is_word), CaseStr::from(UNKNOWN), |doc| tc.document_text(doc), unit, min_count, jobs),
+            Some(stemmer) => process_sequence(tc.corpus_sequence(stemmer.key(), is_word), StemStr::new(UNKNOWN, stemmer), |doc| tc.document_text(doc), unit, min_count, jobs),
+        }
+    } else {
+        let decoded = contents.decode_as(decoding.encoding, decoding.policy)?;
+        let cleaned;
+        let text: &str = if preprocess {
+            cleaned = Preprocessor::new().clean(&decoded.text);
+            &cleaned.text
+        } else {
+            &decoded.text
+        };
+        match stem {
+            None => process_text(text, CaseStr::from, is_word, unit, min_count, jobs),
+            Some(stemmer) => process_text(text, stemmer.key(), is_word, unit, min_count, jobs),
+        }
+    }
+    Ok(())
+}
+
+fn t_significant_corpus_bigrams(paths: &[String], counting: &Counting) -> Result<()> {
+    let Counting { decoding, preprocess, stem, is_word, unit, min_count, jobs } = *counting;
+    let mut corpus = Corpus::from_paths_as(paths, decoding.encoding, decoding.policy)?;
+    if preprocess {
+        corpus = Preprocessor::new().clean_corpus(&corpus).0;
+    }
+    match stem {
+        None => process_corpus(&corpus, CaseStr::from, is_word, unit, min_count, jobs),
+        Some(stemmer) => process_corpus(&corpus, stemmer.key(), is_word, unit, min_count, jobs),
+    }
+    Ok(())
 }
 
-// Filter for words containing any alphabetic characters.
-fn any_alphabetic(s: &str) -> bool { s.chars().any(|c| c.is_alphabetic()) }
+// Count the words and the bigrams of a sequence, without pairing the last
+// word of one unit (a document or a sentence) with the first of the next,
+// spreading the work over `jobs` threads.
+fn count(words: &[usize], offsets: &[usize], jobs: usize) -> (Sample<usize>,Sample<(usize,usize)>) {
+    let parts = parallel::map_ranges(words.len(), jobs, |range| {
+        let word_samples: Sample<usize> = range.clone().map(|i| words[i]).collect();
+        let bigram_samples: Sample<(usize,usize)> = range
+            .filter(|&i| i + 1 < words.len() && offsets.binary_search(&(i + 1)).is_err())
+            .map(|i| (words[i], words[i+1]))
+            .collect();
+        (word_samples, bigram_samples)
+    });
+    let mut word_samples = Sample::new();
+    let mut bigram_samples = Sample::new();
+    for (w,b) in parts {
+        word_samples.merge(w);
+        bigram_samples.merge(b);
+    }
+    (word_samples, bigram_samples)
+}
+
+fn process_text<'a,T,F,P>(text: &'a str, to_t: F, is_word: P, unit: Level, min_count: Option<usize>, jobs: usize)
+    where T: Hash + Eq + Clone + Display + Send,
+          F: Fn(&'a str) -> T + Sync + Copy,
+          P: Fn(&'a str) -> bool + Sync {
+    let mut ws = parallel::tokenize(&RunTokenizer, text, to_t, is_word, jobs);
+    if let Some(min_count) = min_count {
+        ws.prune_min_count(min_count, to_t(UNKNOWN));
+    }
+    let offsets = Structure::unit_offsets(&[0, ws.len()], &ws.spans, |_| text, unit);
+    let (word_samples, bigram_samples) = count(&ws.words, &offsets, jobs);
+    print_bigrams(&ws, &ws.surface_forms(|_| text), &word_samples, &bigram_samples);
+}
+
+fn process_corpus<'a,T,F,P>(corpus: &'a Corpus, to_t: F, is_word: P, unit: Level, min_count: Option<usize>, jobs: usize)
+    where T: Hash + Eq + Clone + Display + Send,
+          F: Fn(&'a str) -> T + Sync + Copy,
+          P: Fn(&'a str) -> bool + Sync {
+    let cs = corpus.tokenize_parallel(&RunTokenizer, to_t, is_word, jobs);
+    process_sequence(cs, to_t(UNKNOWN), |doc| &corpus.documents[doc].text, unit, min_count, jobs);
+}
+
+fn process_sequence<'a,T,D>(mut cs: CorpusSequence<T>, unknown: T, text: D, unit: Level, min_count: Option<usize>, jobs: usize)
+    where T: Hash + Eq + Clone + Display,
+          D: Fn(usize) -> &'a str {
+    let offsets = Structure::unit_offsets(&cs.offsets, &cs.sequence.spans, &text, unit);
+    if let Some(min_count) = min_count {
+        cs.sequence.prune_min_count(min_count, unknown);
+    }
+    let (word_samples, bigram_samples) = count(cs.words(), &offsets, jobs);
+    print_bigrams(&cs.sequence, &cs.sequence.surface_forms(&text), &word_samples, &bigram_samples);
+}
 
-fn process_text<'a>(text: &'a str) {
-    let ws: WordSequence<CaseStr<'a>> = WordSequence::new(text, CaseStr::from, any_alphabetic);
-    // Collect word samples and bigram samples.
-    let word_samples: Sample<usize> = ws.words.iter().cloned().collect();
-    let f = ws.words.iter().cloned();
-    let s = ws.words.iter().skip(1).cloned();
-    let bigram_samples: Sample<(usize,usize)> = f.zip(s).collect();
+fn print_bigrams<T>(ws: &WordSequence<T>, forms: &SurfaceForms, word_samples: &Sample<usize>, bigram_samples: &Sample<(usize,usize)>)
+    where T: Hash + Eq + Clone + Display {
     // Compute the t-value for each bigram.
-    let mut scored: Vec<(f64,(usize,usize))> = compute_t(&word_samples, &bigram_samples);
+    let mut scored: Vec<(f64,(usize,usize))> = compute_t(word_samples, bigram_samples);
     // Sort by t-value in decreasing order, assuming f64 is ordered.
     scored.sort_by(|l,r| l.partial_cmp(r).unwrap_or(Ordering::Equal).reverse());
     // Print each bigram in order, along with statistical information.
@@ -35,12 +139,21 @@ fn process_text<'a>(text: &'a str) {
         let c_obs = unwrap!( bigram_samples.counts.get(&pair) );
         let c_l = unwrap!( word_samples.counts.get(&pair.0) );
         let c_r = unwrap!( word_samples.counts.get(&pair.1) );
-        let first = ws[pair.0].to_string();
-        let second = ws[pair.1].to_string();
+        let first = surface(ws, forms, pair.0);
+        let second = surface(ws, forms, pair.1);
         println!("{:2.2}\t{:6}\t{:6}\t{:6}\t{} {}", t, c_l, c_r, c_obs, first, second);
     }
 }
 
+// A word as it most often appears in the text; the unknown word has no
+// surface forms and appears as its symbol.
+fn surface<T: Hash + Eq + Clone + Display>(ws: &WordSequence<T>, forms: &SurfaceForms, w: usize) -> String {
+    match forms.canonical(w) {
+        Some(form) => form.to_string(),
+        None => ws[w].to_string(),
+    }
+}
+
 fn t(mean: f64, variance: f64, size: f64, distribution_mean: f64) -> f64 {
     let n = mean - distribution_mean;
     let d = (variance / size).sqrt();
@@ -50,7 +163,7 @@ fn t(mean: f64, variance: f64, size: f64, distribution_mean: f64) -> f64 {
 fn compute_t(words: &Sample<usize>, bigrams: &Sample<(usize,usize)>)
       -> Vec<(f64,(usize,usize))> {
     bigrams.counts.keys().map(|pair| {
-        let p_obs = bigrams.p(&pair);
+        let p_obs = bigrams.p(pair);
         let p_indep = words.p(&pair.0) * words.p(&pair.1);
         let t = t(p_obs, p_indep, bigrams.total as f64, p_indep);
         (t, *pair)
@@ -61,7 +174,7 @@ fn print_usage(program: &str, opts: &Options, short: bool) {
     if short {
         println!("{}", opts.short_usage(program));
     } else {
-        let brief = format!("Usage: {} [options] file...", program);
+        let brief = format!("Usage: {} [options] file...\n\nA file name of - reads standard input. A file written by tokenize is read\nas a corpus of its documents.\n\nOnly words with a letter in them are counted, unless --filter names others:\nfilters separated by commas, all of which must hold, each one or more of\nany, alphabetic, any-alphabetic, numeric, punctuation, stopword and\nmin-length=N separated by |, and each perhaps negated by !. The stopword\nfilter uses the --stopwords list, or else a built-in English one. For\nexample: --filter 'alphabetic,!stopword,min-length=3'.", program);
         print!("{}", opts.usage(&brief));
     }
 }
@@ -73,23 +186,77 @@ fn main() {
     let mut opts = Options::new();
     opts.parsing_style(ParsingStyle::StopAtFirstFree);
     opts.optflag("c", "case", "use case-sensitive comparisons");
+    opts.optflag("C", "corpus", "compute statistics over all files and directories together");
+    opts.optflag("s", "stem", "group words by stem");
+    opts.optopt("", "stemmer", "stemmer for --stem: porter2 (the default) or porter", "NAME");
+    opts.optopt("f", "filter", "count only the words passing FILTER (see below)", "FILTER");
+    opts.optopt("", "stopwords", "leave out the words listed in FILE", "FILE");
+    opts.optopt("e", "encoding", "read text in encoding NAME: utf-8, utf-16le, utf-16be, latin1 or windows-1252 (default: guessed)", "NAME");
+    opts.optflag("", "lossy", "replace bytes invalid in the encoding with U+FFFD rather than failing, or for text that is nearly all UTF-8, rather than reading it as latin1 or windows-1252");
+    opts.optflag("p", "preprocess", "leave out Project Gutenberg boilerplate and markup");
+    opts.optflag("S", "sentences", "count only bigrams within a sentence");
+    opts.optopt("m", "min-count", "replace words seen fewer than N times with <unk>", "N");
+    opts.optopt("j", "jobs", "use N threads (default: one per core)", "N");
     opts.optflag("h", "help", "print detailed help");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(e) => {
             println!("{}", e);
             print_usage(&program, &opts, true);
-            return;
+            std::process::exit(2);
         }
     };
-    if matches.opt_present("h") || matches.free.len() < 1 {
+    if matches.opt_present("h") || matches.free.is_empty() {
         print_usage(&program, &opts, false);
         return;
     }
 
+    let decoding = options::decoding(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let stem = options::stemmer(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+
+    let min_count = match matches.opt_get::<usize>("m") {
+        Ok(min_count) => min_count,
+        Err(e) => {
+            println!("min-count must be a number: {}", e);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
+
+    let jobs = match matches.opt_get::<usize>("j") {
+        Ok(jobs) => jobs.unwrap_or_else(parallel::default_threads),
+        Err(e) => {
+            println!("jobs must be a number: {}", e);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
+
+    let stopwords = options::stopwords(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let english = Stopwords::english();
+    let filter = options::filter(&matches, "f", stopwords.as_ref().unwrap_or(&english))
+        .unwrap_or_else(|e| options::exit(&program, &opts, e))
+        .unwrap_or_else(|| Box::new(filters::any_alphabetic));
+    let is_word = options::and_stopwords(filter, stopwords.as_ref());
+
+    let unit = if matches.opt_present("S") { Level::Sentence } else { Level::Document };
+    let counting = Counting { decoding, preprocess: matches.opt_present("p"), stem, is_word: &is_word, unit, min_count, jobs };
+    if matches.opt_present("C") {
+        if let Err(e) = t_significant_corpus_bigrams(&matches.free, &counting) {
+            eprintln!("{}: {}", program, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut status = 0;
     for file in &matches.free[0..] {
-        t_significant_bigrams(file);
+        if let Err(e) = t_significant_bigrams(file, &counting) {
+            eprintln!("{}: {}: {}", program, file, e);
+            status = 1;
+        }
     }
+    std::process::exit(status);
 }
 
 //  LocalWords:  bigram
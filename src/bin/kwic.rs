@@ -1,73 +1,253 @@
 extern crate getopts;
 extern crate nl_foundations;
 
+use std::cmp::{max,min};
 use std::hash::Hash;
-use std::borrow::Borrow;
+use std::path::Path;
 
 use getopts::{Options,ParsingStyle};
 
-use nl_foundations::mmap::MappedRegion;
+use nl_foundations::{Error,Result};
+use nl_foundations::text_source;
 use nl_foundations::case_string::CaseStr;
-use nl_foundations::word_sequence::WordSequence;
+use nl_foundations::filters::{BoxedFilter,Stopwords};
+use nl_foundations::inverted_index::InvertedIndex;
+use nl_foundations::options::{self,Decoding};
+use nl_foundations::preprocess::{OffsetMap,Preprocessor};
+use nl_foundations::stemmer::Stemmer;
+use nl_foundations::structure::{Level,Structure};
+use nl_foundations::suffix_array::SuffixArray;
+use nl_foundations::tokenized::{self,TokenizedCorpus};
+use nl_foundations::tokenizer::{self,RunTokenizer,Tokenizer};
+use nl_foundations::vocabulary::Vocabulary;
+use nl_foundations::word_sequence::{Span,Word,WordSequence};
 
 type Context = (String,String,String);
 type Contexts = Vec<Context>;
 
-fn kwic(word: &str, window: usize, file: &str, case: bool) -> Contexts {
-    MappedRegion::mmap(file).and_then(|contents| {
-        contents.get_str().map(|text| {
-            if case {
-                segments(&WordSequence::new(text, |s| s, |_| true), word, window)
-            } else {
-                segments(&WordSequence::new(text, CaseStr::from, |_| true), CaseStr::from(word), window)
-            }
-        })
-    }).expect(&format!("cannot read {}", file))
-}
-
-fn segments<T>(ws: &WordSequence<T>, word: T, window: usize) -> Contexts
-    where T: Hash+Eq+ToString+Clone+Borrow<str> {
-    match ws.to_word(&word) {
-        Some(word) => {
-            let lines = ws.words.iter()
-                .enumerate()
-                .filter_map(|(i,&n)| {
-                    if n == word {
-                        Some(get_segment(i, window, &ws))
-                    } else {
-                        None
-                    }
-                }).collect();
-            lines
+// How text files are read, and how the words of the query and the text are
+// found and compared.
+struct Matching<'a> {
+    case: bool,
+    stem: Option<Stemmer>,
+    filter: Option<&'a BoxedFilter<'a>>,
+    preprocess: bool,
+    decoding: Decoding,
+}
+
+// How far the context of a match reaches: `width` words either side, and
+// no further than its `unit`, a document or a sentence.
+#[derive(Clone,Copy,Debug)]
+struct Window {
+    width: usize,
+    unit: Level,
+}
+
+fn kwic(query: &str, window: Window, file: &str, matching: &Matching, near: Option<usize>) -> Result<Contexts> {
+    let Matching { case, stem, filter, preprocess, decoding } = *matching;
+    let is_word = |s: &str| filter.is_none_or(|filter| filter(s));
+    let contents = text_source::open(file)?;
+    if tokenized::is_tokenized(contents.get_slice()) {
+        let tc = TokenizedCorpus::from_source(contents)?;
+        // Without a filter, the positions of the file's words are those of
+        // the words matched, so that an index or suffix array saved by
+        // tokenize can be used in place of building an index.
+        return match (stem, filter) {
+            (Some(stemmer), None) => saved_kwic(&tc, file, query, stemmer.key(), window, near),
+            (None, None) if case => saved_kwic(&tc, file, query, |s| s, window, near),
+            (None, None) => saved_kwic(&tc, file, query, CaseStr::from, window, near),
+            (Some(stemmer), Some(_)) => tokenized_kwic(&tc, query, stemmer.key(), is_word, window, near),
+            (None, Some(_)) if case => tokenized_kwic(&tc, query, |s| s, is_word, window, near),
+            (None, Some(_)) => tokenized_kwic(&tc, query, CaseStr::from, is_word, window, near),
+        };
+    }
+    let decoded = contents.decode_as(decoding.encoding, decoding.policy)?;
+    let original: &str = &decoded.text;
+    let cleaned = if preprocess { Some(Preprocessor::new().clean(original)) } else { None };
+    let (text, map) = match cleaned {
+        Some(ref cleaned) => (&cleaned.text[..], Some(&cleaned.map)),
+        None => (original, None),
+    };
+    let source = (original, map);
+    match stem {
+        Some(stemmer) => text_kwic(text, source, query, stemmer.key(), is_word, window, near),
+        None if case => text_kwic(text, source, query, |s| s, is_word, window, near),
+        None => text_kwic(text, source, query, CaseStr::from, is_word, window, near),
+    }
+}
+
+// The segments of a tokenized file, found through the index or suffix
+// array saved beside it, if any, with each word of the query standing for
+// the file's words with the same key under `to_t`.
+fn saved_kwic<'a,T,F>(tc: &'a TokenizedCorpus, file: &str, query: &'a str, to_t: F, window: Window, near: Option<usize>) -> Result<Contexts>
+    where T: Hash+Eq+Clone,
+          F: Fn(&'a str) -> T + Copy {
+    let pattern = query_alternatives(query, &*file_tokenizer(tc), to_t, &tc.vocabulary());
+    let matches = find(Some(file), tc.words(), tc.offsets(), pattern, near)?;
+    let bounds = Structure::unit_offsets(tc.offsets(), tc.spans(), |doc| tc.document_text(doc), window.unit);
+    Ok( segments(&matches, tc.offsets(), &bounds, |doc| (tc.document_text(doc), tc.document_spans(doc)), window.width) )
+}
+
+// The segments of a tokenized file, with words keyed by `to_t` and those
+// not satisfying `is_word` passed over.
+fn tokenized_kwic<'a,T,F,P>(tc: &'a TokenizedCorpus, query: &'a str, to_t: F, is_word: P, window: Window, near: Option<usize>) -> Result<Contexts>
+    where T: Hash+Eq+Clone,
+          F: Fn(&'a str) -> T + Copy,
+          P: Fn(&'a str) -> bool + Copy {
+    let cs = tc.corpus_sequence(to_t, is_word);
+    let pattern = query_words(query, &*file_tokenizer(tc), to_t, is_word, &cs.sequence.vocabulary);
+    let matches = find(None, &cs.sequence.words, &cs.offsets, pattern, near)?;
+    let bounds = Structure::unit_offsets(&cs.offsets, &cs.sequence.spans, |doc| tc.document_text(doc), window.unit);
+    let spans = |doc: usize| &cs.sequence.spans[cs.offsets[doc]..cs.offsets[doc+1]];
+    Ok( segments(&matches, &cs.offsets, &bounds, |doc| (tc.document_text(doc), spans(doc)), window.width) )
+}
+
+// The segments of a text, with words keyed by `to_t` and those not
+// satisfying `is_word` passed over. The segments are taken from the
+// original of a cleaned text, given its offset map.
+fn text_kwic<'a,T,F,P>(text: &'a str, source: (&str,Option<&OffsetMap>), query: &'a str, to_t: F, is_word: P, window: Window, near: Option<usize>) -> Result<Contexts>
+    where T: Hash+Eq+Clone,
+          F: Fn(&'a str) -> T + Copy,
+          P: Fn(&'a str) -> bool + Copy {
+    let ws = WordSequence::new(text, to_t, is_word);
+    let pattern = query_words(query, &RunTokenizer, to_t, is_word, &ws.vocabulary);
+    let offsets = [0, ws.words.len()];
+    let matches = find(None, &ws.words, &offsets, pattern, near)?;
+    let bounds = Structure::unit_offsets(&offsets, &ws.spans, |_| text, window.unit);
+    let (original, map) = source;
+    let mapped: Vec<Span>;
+    let spans: &[Span] = match map {
+        Some(map) => {
+            mapped = ws.spans.iter().map(|&span| map.original_span(span)).collect();
+            &mapped
+        }
+        None => &ws.spans,
+    };
+    Ok( segments(&matches, &offsets, &bounds, |_| (original, spans), window.width) )
+}
+
+// The tokenizer a tokenized file was written with, or the default if it
+// is not recorded.
+fn file_tokenizer(tc: &TokenizedCorpus) -> Box<dyn Tokenizer + Sync> {
+    tc.tokenizer().and_then(tokenizer::named).unwrap_or_else(|| Box::new(RunTokenizer))
+}
+
+// The words of a query satisfying `is_word`, which may be a phrase of
+// several words, tokenized as the text was, each the only alternative for
+// its position; None if any of them does not occur.
+fn query_words<'a,T,F,P>(query: &'a str, tokenizer: &dyn Tokenizer, to_t: F, is_word: P, vocabulary: &Vocabulary<T>) -> Option<Vec<Vec<Word>>>
+    where T: Hash+Eq+Clone,
+          F: Fn(&'a str) -> T,
+          P: Fn(&'a str) -> bool {
+    let qs = WordSequence::with_tokenizer(tokenizer, query, to_t, is_word);
+    if qs.words.is_empty() { return None; }
+    qs.words.iter().map(|w| qs.from_word(w).and_then(|t| vocabulary.to_word(t)).map(|w| vec!(w))).collect()
+}
+
+// The words of a query, tokenized as the text was, each standing for the
+// words of a vocabulary with the same key under `to_t`; None if any of them
+// stands for none.
+fn query_alternatives<'a,T,F>(query: &'a str, tokenizer: &dyn Tokenizer, to_t: F, vocabulary: &Vocabulary<&'a str>) -> Option<Vec<Vec<Word>>>
+    where T: Hash+Eq+Clone,
+          F: Fn(&'a str) -> T {
+    let qs = WordSequence::with_tokenizer(tokenizer, query, &to_t, |_| true);
+    if qs.words.is_empty() { return None; }
+    let mut alternatives = vec!(Vec::new(); qs.vocabulary.len());
+    for (w,&word) in vocabulary.iter() {
+        if let Some(q) = qs.to_word(&to_t(word)) {
+            alternatives[q].push(w);
         }
-        None => { vec!() }
     }
+    qs.words.iter().map(|&q| Some(alternatives[q].clone()).filter(|a| !a.is_empty())).collect()
 }
 
-// Find the KWIC segments (left, the word, and right) in the word sequence.
-fn get_segment<T>(word: usize, window: usize, ws: &WordSequence<T>) -> Context
-    where T: Eq+Hash+ToString+Clone+Borrow<str> {
-    let mut start = (word as isize) - (window as isize);
-    start = if start < 0 { 0 } else { start };
-    let mut end = word + window + 1;
-    end = if end > ws.words.len() { ws.words.len() } else { end };
-    // Join the word ids, converted to their words and separated by spaces.
-    let left = ws.words[start as usize..word].iter()
-        .map(|&w| ws[w].clone())
-        .collect::<Vec<_>>()
-        .join(" ");
-    let right = ws.words[word+1..end].iter()
-        .map(|&w| ws[w].clone())
-        .collect::<Vec<_>>()
-        .join(" ");
-    // Get the middle word out of the dictionary.
-    (left, ws[ws.words[word]].to_string(), right)
+// Every phrase made by choosing one of the alternatives for each word.
+fn choices(alternatives: &[Vec<Word>]) -> Vec<Vec<Word>> {
+    alternatives.iter().fold(vec!(vec!()), |phrases, words| {
+        phrases.iter()
+            .flat_map(|phrase| words.iter().map(move |&w| { let mut p = phrase.clone(); p.push(w); p }))
+            .collect()
+    })
+}
+
+// The first and last positions of each match of a query, given the
+// alternatives for each of its words: the phrase, or, given a distance, its
+// two words within that distance of each other. The index or suffix array
+// saved beside a file is used if there is one.
+fn find(saved: Option<&str>, words: &[Word], offsets: &[usize], pattern: Option<Vec<Vec<Word>>>, near: Option<usize>) -> Result<Vec<(usize,usize)>> {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return Ok(vec!()),
+    };
+    let index_file = saved.map(|file| format!("{}.idx", file)).filter(|f| Path::new(f).exists());
+    let sa_file = saved.map(|file| format!("{}.sa", file)).filter(|f| Path::new(f).exists());
+    if let (None, Some(sa_file), None) = (&index_file, &sa_file, near) {
+        let sa = SuffixArray::open(sa_file, words)?;
+        let mut positions: Vec<usize> = choices(&pattern).iter().flat_map(|phrase| sa.positions(phrase)).collect();
+        positions.sort_unstable();
+        return Ok(positions.into_iter().map(|i| (i, i + pattern.len() - 1)).collect());
+    }
+    let index = match index_file {
+        Some(index_file) => {
+            let index = InvertedIndex::open(&index_file)?;
+            if !index.matches(words, offsets) {
+                return Err(Error::InvalidFormat(format!("{} does not match the corpus", index_file)));
+            }
+            index
+        }
+        None => InvertedIndex::new(words, offsets),
+    };
+    Ok(match (near, &pattern[..]) {
+        (Some(distance), [first, second]) => {
+            let mut matches: Vec<(usize,usize)> = index.near_any(first, second, distance).into_iter()
+                .map(|(i,j)| (min(i,j), max(i,j)))
+                .collect();
+            matches.sort();
+            matches
+        }
+        (Some(_), _) => vec!(),
+        (None, _) => {
+            index.phrase_any(&pattern).into_iter().map(|i| (i, i + pattern.len() - 1)).collect()
+        }
+    })
+}
+
+// The segments of matches in documents, divided by offsets; matches and
+// windows do not cross the boundaries of units, which lie within documents.
+fn segments<'a,F>(matches: &[(usize,usize)], offsets: &[usize], bounds: &[usize], document: F, window: usize) -> Contexts
+    where F: Fn(usize) -> (&'a str,&'a [Span]) {
+    let mut doc = 0;
+    let mut unit = 0;
+    matches.iter().filter_map(|&(first,last)| {
+        while offsets[doc+1] <= first { doc += 1; }
+        while bounds[unit+1] <= first { unit += 1; }
+        if last >= bounds[unit+1] { return None; }
+        let (text, spans) = document(doc);
+        let (start, end) = (bounds[unit] - offsets[doc], bounds[unit+1] - offsets[doc]);
+        Some(get_segment(text, first - bounds[unit], last - bounds[unit], window, &spans[start..end]))
+    }).collect()
+}
+
+// Find the KWIC segments (left, the words from first to last, and right) in
+// the original text.
+fn get_segment(text: &str, first: usize, last: usize, window: usize, spans: &[Span]) -> Context {
+    let start = max(first, window) - window;
+    let end = min(last + window + 1, spans.len());
+    let (here, there) = (spans[first], spans[last]);
+    let left = &text[spans[start].start..here.start];
+    let right = &text[there.end..spans[end-1].end];
+    (one_line(left), one_line(&text[here.start..there.end]), one_line(right))
+}
+
+// Collapse line breaks and runs of whitespace into single spaces.
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 // Print a KWIC segment: left window (right justified), the word, and right window.
 fn print_segments(segments: &[(String,String,String)]) {
-    let lmax = segments.iter().map(|&(ref l,_,_)| l.len()).max().unwrap_or(0);
-    for &(ref l, ref w, ref r) in segments.iter() {
+    let lmax = segments.iter().map(|(l,_,_)| l.len()).max().unwrap_or(0);
+    for (l, w, r) in segments.iter() {
         println!("{0:>1$}  {2}  {3}", l, lmax, w, r);
     }
 }
@@ -76,7 +256,7 @@ fn print_usage(program: &str, opts: &Options, short: bool) {
     if short {
         println!("{}", opts.short_usage(program));
     } else {
-        let brief = format!("Usage: {} [options] word file...", program);
+        let brief = format!("Usage: {} [options] word file...\n\nThe word may be a phrase of several words, quoted. With --near, it is two\nwords, found within DISTANCE words of each other in either order. Stems are\nin lower case, so --stem ignores --case.\n\nA file name of - reads standard input. A file written by tokenize is searched\ndocument by document, using the index or suffix array saved beside it, if\nany, unless words are filtered. Use tokenize --preprocess to leave out\nboilerplate and markup from a tokenized file.\n\nWith --filter or --stopwords, words not passing the filter are passed over:\nthey are not found, and do not count towards the window, phrases or\ndistances. A filter is one or more filters separated by commas, all of which\nmust hold, each one or more of any, alphabetic, any-alphabetic, numeric,\npunctuation, stopword and min-length=N separated by |, and each perhaps\nnegated by !. The stopword filter uses the --stopwords list, or else a\nbuilt-in English one.", program);
         print!("{}", opts.usage(&brief));
     }
 }
@@ -88,14 +268,23 @@ fn main() {
     let mut opts = Options::new();
     opts.parsing_style(ParsingStyle::StopAtFirstFree);
     opts.optflag("c", "case", "use case-sensitive comparisons");
+    opts.optflag("s", "stem", "match words by stem");
+    opts.optopt("", "stemmer", "stemmer for --stem: porter2 (the default) or porter", "NAME");
     opts.optflag("h", "help", "print detailed help");
     opts.optopt("w", "window", "context window width", "WIDTH");
+    opts.optopt("f", "filter", "pass over words not passing FILTER (see below)", "FILTER");
+    opts.optopt("", "stopwords", "pass over the words listed in FILE", "FILE");
+    opts.optopt("e", "encoding", "read text in encoding NAME: utf-8, utf-16le, utf-16be, latin1 or windows-1252 (default: guessed)", "NAME");
+    opts.optflag("", "lossy", "replace bytes invalid in the encoding with U+FFFD rather than failing, or for text that is nearly all UTF-8, rather than reading it as latin1 or windows-1252");
+    opts.optflag("p", "preprocess", "pass over Project Gutenberg boilerplate and markup in text files");
+    opts.optflag("S", "sentence", "keep matches and their context within a sentence");
+    opts.optopt("n", "near", "find two words within DISTANCE words of each other", "DISTANCE");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(e) => {
             println!("{}", e);
             print_usage(&program, &opts, true);
-            return;
+            std::process::exit(2);
         }
     };
     if matches.opt_present("h") || matches.free.len() < 2 {
@@ -103,6 +292,8 @@ fn main() {
         return;
     }
     let case = matches.opt_present("c");
+    let decoding = options::decoding(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let stem = options::stemmer(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
     let width = match matches.opt_str("w") {
         Some(width) => {
             match width.parse::<usize>() {
@@ -110,7 +301,7 @@ fn main() {
                 Err(e) => {
                     println!("width must be a number: {}", e);
                     print_usage(&program, &opts, true);
-                    return;
+                    std::process::exit(2);
                 }
             }
         }
@@ -118,8 +309,39 @@ fn main() {
             8usize
         }
     };
+    let near = match matches.opt_get::<usize>("n") {
+        Ok(near) => near,
+        Err(e) => {
+            println!("distance must be a number: {}", e);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
     let word: String = matches.free[0].clone();
+    if near.is_some() && word.split_whitespace().count() != 2 {
+        println!("--near needs two words");
+        print_usage(&program, &opts, true);
+        std::process::exit(2);
+    }
+    let stopwords = options::stopwords(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let english = Stopwords::english();
+    let filter = options::filter(&matches, "f", stopwords.as_ref().unwrap_or(&english))
+        .unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let filter: Option<BoxedFilter> = match (filter, &stopwords) {
+        (None, None) => None,
+        (filter, _) => Some(options::and_stopwords(filter.unwrap_or_else(|| Box::new(|_: &str| true)), stopwords.as_ref())),
+    };
+    let matching = Matching { case, stem, filter: filter.as_ref(), preprocess: matches.opt_present("p"), decoding };
+    let window = Window { width, unit: if matches.opt_present("S") { Level::Sentence } else { Level::Document } };
+    let mut status = 0;
     for file in &matches.free[1..] {
-        print_segments(&kwic(&word, width, file, case));
+        match kwic(&word, window, file, &matching, near) {
+            Ok(segments) => print_segments(&segments),
+            Err(e) => {
+                eprintln!("{}: {}: {}", program, file, e);
+                status = 1;
+            }
+        }
     }
+    std::process::exit(status);
 }
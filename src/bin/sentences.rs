@@ -2,49 +2,98 @@
 extern crate getopts;
 extern crate nl_foundations;
 
-use std::collections::{HashMap,HashSet};
-
 use getopts::{Options,ParsingStyle};
 
-use nl_foundations::mmap::MappedRegion;
-use nl_foundations::word_sequence::{CharClass,Word,WordSequence};
+use nl_foundations::Result;
+use nl_foundations::annotation::Annotations;
+use nl_foundations::filters::{self,BoxedFilter,Stopwords};
+use nl_foundations::options::{self,Decoding};
+use nl_foundations::text_source;
+use nl_foundations::tokenized::{self,TokenizedCorpus};
+use nl_foundations::vocabulary::Vocabulary;
+use nl_foundations::word_sequence::{Span,Word,WordSequence};
 use nl_foundations::bayesian_classification::training::Trainer;
 use nl_foundations::bayesian_classification::model::Model;
+use nl_foundations::bayesian_classification::readers::{InlineMarker,Reader,SentencePerLine,Standoff};
 
-fn train_model<'a>(text: &'a str) -> Model<String> {
-    let mut ws: WordSequence<&str> = WordSequence::new(text, |s| s, |_| true);
-
-    let training_marks: HashSet<Word> = ws.words.iter()
-        .filter(|&word| ws.class_of_word[word] == CharClass::Other && ws[*word].contains("+"))
-        .cloned()
-        .collect();
-    let word_map: HashMap<Word,Word> = training_marks.iter()
-        .map(|&word| {
-            let s = ws[word];
-            let s_p = &s[..s.len()-1];
-            let word_p = ws.insert_word(s_p, CharClass::Other);
-            (word, word_p)
-        }).collect();
-
+// Train a model on annotated text, read by `reader`. The model's ids are
+// those of the returned vocabulary, which is frozen so that other texts can
+// be tokenized against it.
+fn train_model<'a>(reader: &dyn Reader, training: &'a Input) -> Result<(Model<Word>,Vocabulary<&'a str>)> {
+    let mut ws = WordSequence::empty();
+    let mut labels = Vec::new();
+    for doc in 0..training.len() {
+        labels.extend(reader.append(&mut ws, training.text(doc))?);
+    }
     let mut trainer = Trainer::new(2);
-    trainer.train(&ws.words,
-                  |w| ws.class_of_word[w] == CharClass::Other,
-                  |w| *word_map.get(w).unwrap_or(w),
-                  |w| ws[*w].contains("+")
-                  );
-    let model: Model<String> = Model::new(&trainer, |&w| ws[w].to_string());
-    model
+    trainer.train_labeled(&ws.words, &labels)?;
+    let model: Model<Word> = Model::new(&trainer, |&w| w);
+    let mut vocabulary = ws.vocabulary;
+    vocabulary.freeze();
+    Ok((model, vocabulary))
+}
+
+// A text, or a file written by tokenize.
+enum Input {
+    Text(String),
+    Tokenized(TokenizedCorpus),
 }
 
-fn process_text<'a,'m>(model: &'m Model<String>, text: &'a str) {
-    let ws: WordSequence<&str> = WordSequence::new(text, |s| s, |_| true);
-    let m: Model<Word> = model.localize(|w: &String| {
-        ws.to_word(&w.as_ref()).unwrap_or(ws.len())
-    });
+impl Input {
+    fn read(file: &str, decoding: Decoding) -> Result<Input> {
+        let contents = text_source::open(file)?;
+        if tokenized::is_tokenized(contents.get_slice()) {
+            Ok(Input::Tokenized(TokenizedCorpus::from_source(contents)?))
+        } else {
+            Ok(Input::Text(contents.decode_as(decoding.encoding, decoding.policy)?.text.into_owned()))
+        }
+    }
+
+    // The tokens of the input, against a vocabulary, along with the
+    // boundaries of its documents.
+    fn tokenize<'a>(&'a self, vocabulary: Vocabulary<&'a str>) -> (WordSequence<&'a str>,Vec<usize>) {
+        let mut ws = WordSequence::from_vocabulary(vocabulary);
+        match *self {
+            Input::Text(ref text) => {
+                ws.append(text, |s| s, |_| true);
+                let offsets = vec!(0, ws.len());
+                (ws, offsets)
+            }
+            Input::Tokenized(ref tc) => {
+                let offsets = tc.append_to(&mut ws, |s| s, |_| true);
+                (ws, offsets)
+            }
+        }
+    }
+
+    // The number of documents.
+    fn len(&self) -> usize {
+        match *self {
+            Input::Text(_) => 1,
+            Input::Tokenized(ref tc) => tc.len(),
+        }
+    }
+
+    fn text(&self, doc: usize) -> &str {
+        match *self {
+            Input::Text(ref text) => text,
+            Input::Tokenized(ref tc) => tc.document_text(doc),
+        }
+    }
+}
 
+fn process_input<'a>(model: &Model<Word>, vocabulary: &Vocabulary<&'a str>, reader: &dyn Reader, input: &'a Input) {
+    let (ws, offsets) = input.tokenize(vocabulary.clone());
+    for doc in 0..offsets.len()-1 {
+        let range = offsets[doc]..offsets[doc+1];
+        print_sentences(model, reader, &ws.words[range.clone()], &ws.spans[range], input.text(doc));
+    }
+}
+
+fn print_sentences(model: &Model<Word>, reader: &dyn Reader, words: &[Word], spans: &[Span], text: &str) {
     let mut len = 0;
-    for (i,&w) in ws.words.iter().enumerate() {
-        let word = ws[w];
+    for (i,&w) in words.iter().enumerate() {
+        let word = &text[spans[i].start..spans[i].end];
         len += word.len() + 1;
         if len > 80 {
             len = 0;
@@ -52,23 +101,25 @@ fn process_text<'a,'m>(model: &'m Model<String>, text: &'a str) {
         } else {
             print!("{} ", word);
         }
-        if ws.class_of_word[&w] == CharClass::Other {
-            let (p,n) = m.log_likelihood(&w, m.context(i, &ws.words));
+        // Words not in the training vocabulary share its unknown id, so
+        // candidates are found by their text.
+        if reader.is_candidate(word) {
+            let (p,n) = model.log_likelihood(&w, model.context(i, words));
             print!("({},{}) ", p, n);
-            if m.is_instance(&w, m.context(i, &ws.words)) {
+            if model.is_instance(&w, model.context(i, words)) {
                 len = 0;
                 println!("\n");
             }
         }
     }
-    println!("");
+    println!();
 }
 
 fn print_usage(program: &str, opts: &Options, short: bool) {
     if short {
         println!("{}", opts.short_usage(program));
     } else {
-        let brief = format!("Usage: {} [options] training-file file...", program);
+        let brief = format!("Usage: {} [options] training-file file...\n\nA file name of - reads standard input. Files written by tokenize may be\nused in place of text.\n\nThe candidates for sentence ends are the tokens of punctuation, unless\n--candidates names others with a filter as in bigrams. In the training file,\nthose ending sentences are marked by a + after them, as in `No answer.+`,\nor by another --marker. With --lines, the training file has a sentence on\neach line, ended by the first of the candidates at the end of the line.\nWith --standoff, the training text is unmarked, and the ends are given by\nthe first layer of FILE, or by --layer: each annotation marks the\ncandidates it covers, or if it is empty, the candidate just before it.", program);
         print!("{}", opts.usage(&brief));
     }
 }
@@ -79,26 +130,107 @@ fn main() {
 
     let mut opts = Options::new();
     opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optopt("f", "candidates", "consider the tokens passing FILTER as sentence ends", "FILTER");
+    opts.optopt("m", "marker", "sentence ends are marked by CHAR (default: +)", "CHAR");
+    opts.optflag("l", "lines", "the training file has a sentence on each line");
+    opts.optopt("", "standoff", "read the sentence ends in the training file from FILE", "FILE");
+    opts.optopt("", "layer", "the layer of annotations in the --standoff file", "NAME");
+    opts.optopt("e", "encoding", "read text in encoding NAME: utf-8, utf-16le, utf-16be, latin1 or windows-1252 (default: guessed)", "NAME");
+    opts.optflag("", "lossy", "replace bytes invalid in the encoding with U+FFFD rather than failing, or for text that is nearly all UTF-8, rather than reading it as latin1 or windows-1252");
     opts.optflag("h", "help", "print detailed help");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(e) => {
             println!("{}", e);
             print_usage(&program, &opts, true);
-            return;
+            std::process::exit(2);
         }
     };
-    if matches.opt_present("h") || matches.free.len() < 1 {
+    if matches.opt_present("h") || matches.free.is_empty() {
         print_usage(&program, &opts, false);
         return;
     }
 
-    let model = MappedRegion::mmap(&matches.free[0])
-        .and_then(|contents| contents.get_str().map(train_model))
-        .expect(&format!("cannot read {}", matches.free[0]));
+    let decoding = options::decoding(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let english = Stopwords::english();
+    let candidates: BoxedFilter = options::filter(&matches, "f", &english)
+        .unwrap_or_else(|e| options::exit(&program, &opts, e))
+        .unwrap_or_else(|| Box::new(filters::is_punctuation));
+    let marker = matches.opt_str("m");
+    let standoff = matches.opt_str("standoff");
+    let lines = matches.opt_present("l");
+    if [marker.is_some(), standoff.is_some(), lines].iter().filter(|&&given| given).count() > 1 {
+        println!("--marker, --lines and --standoff cannot be used together");
+        print_usage(&program, &opts, true);
+        std::process::exit(2);
+    }
+    let marker = match marker {
+        Some(marker) => {
+            let mut chars = marker.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    println!("marker must be a single character: {}", marker);
+                    print_usage(&program, &opts, true);
+                    std::process::exit(2);
+                }
+            }
+        }
+        None => '+',
+    };
+
+    let training = match Input::read(&matches.free[0], decoding) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}: {}: {}", program, matches.free[0], e);
+            std::process::exit(1);
+        }
+    };
+    let reader: Box<dyn Reader> = match standoff {
+        Some(file) => {
+            let annotations = match Annotations::from_file(&file) {
+                Ok(annotations) => annotations,
+                Err(e) => {
+                    eprintln!("{}: {}: {}", program, file, e);
+                    std::process::exit(1);
+                }
+            };
+            let layer = match matches.opt_str("layer") {
+                Some(name) => annotations.layer(&name),
+                None => annotations.iter().next(),
+            };
+            let layer = match layer {
+                Some(layer) => layer.clone(),
+                None => {
+                    eprintln!("{}: {}: no such layer", program, file);
+                    std::process::exit(1);
+                }
+            };
+            if training.len() != 1 {
+                eprintln!("{}: {}: standoff annotations need a single document", program, matches.free[0]);
+                std::process::exit(1);
+            }
+            Box::new(Standoff::new(layer, candidates))
+        }
+        None if lines => Box::new(SentencePerLine::new(candidates)),
+        None => Box::new(InlineMarker::new(marker, candidates)),
+    };
+    let (model, vocabulary) = match train_model(&*reader, &training) {
+        Ok(trained) => trained,
+        Err(e) => {
+            eprintln!("{}: {}: {}", program, matches.free[0], e);
+            std::process::exit(1);
+        }
+    };
+    let mut status = 0;
     for file in &matches.free[1..] {
-        MappedRegion::mmap(file)
-            .and_then(|contents| contents.get_str().map(|t| process_text(&model, t)))
-            .expect(&format!("cannot read {}", file));
+        match Input::read(file, decoding) {
+            Ok(input) => process_input(&model, &vocabulary, &*reader, &input),
+            Err(e) => {
+                eprintln!("{}: {}: {}", program, file, e);
+                status = 1;
+            }
+        }
     }
+    std::process::exit(status);
 }
@@ -0,0 +1,155 @@
+extern crate getopts;
+extern crate nl_foundations;
+
+use std::fs::File;
+use std::io::{self,BufWriter,Write};
+
+use getopts::{Options,ParsingStyle};
+
+use nl_foundations::{Error,Result};
+use nl_foundations::corpus::{Corpus,CorpusSequence};
+use nl_foundations::inverted_index::InvertedIndex;
+use nl_foundations::options::{self,Decoding};
+use nl_foundations::parallel;
+use nl_foundations::preprocess::Preprocessor;
+use nl_foundations::suffix_array::SuffixArray;
+use nl_foundations::tokenized;
+use nl_foundations::tokenizer::{self,Tokenizer};
+
+fn create(filename: &str) -> Result<BufWriter<File>> {
+    let file = File::create(filename).map_err(|e| Error::Open(filename.to_string(), e))?;
+    Ok(BufWriter::new(file))
+}
+
+// How files are read and tokenized, with the tokenizer's name for the
+// tokenized file to record.
+struct Tokenizing<'a> {
+    decoding: Decoding,
+    preprocess: bool,
+    name: &'a str,
+    tokenizer: &'a (dyn Tokenizer + Sync),
+    jobs: usize,
+}
+
+// Where the tokenized file goes, and whether a suffix array and an inverted
+// index are written beside it.
+struct Output {
+    file: Option<String>,
+    suffix_array: bool,
+    index: bool,
+}
+
+fn tokenize_files(paths: &[String], tokenizing: &Tokenizing, output: Output) -> Result<()> {
+    let Tokenizing { decoding, preprocess, name, tokenizer, jobs } = *tokenizing;
+    let corpus = Corpus::from_paths_as(paths, decoding.encoding, decoding.policy)?;
+    if preprocess {
+        // Tokenize the cleaned texts, but keep the originals, with the
+        // tokens' spans mapped back to them.
+        let (cleaned, maps) = Preprocessor::new().clean_corpus(&corpus);
+        let mut cs = cleaned.tokenize_parallel(tokenizer, |s| s, |_| true, jobs);
+        for (doc,map) in maps.iter().enumerate() {
+            for span in &mut cs.sequence.spans[cs.offsets[doc]..cs.offsets[doc+1]] {
+                *span = map.original_span(*span);
+            }
+        }
+        return write_files(&corpus, &cs, name, output);
+    }
+    let cs = corpus.tokenize_parallel(tokenizer, |s| s, |_| true, jobs);
+    write_files(&corpus, &cs, name, output)
+}
+
+fn write_files(corpus: &Corpus, cs: &CorpusSequence<&str>, tokenizer: &str, output: Output) -> Result<()> {
+    match output.file {
+        Some(filename) => {
+            tokenized::write(&mut create(&filename)?, corpus, cs, tokenizer)?;
+            if output.suffix_array {
+                let sa = SuffixArray::new(&cs.sequence.words);
+                sa.write(&mut create(&format!("{}.sa", filename))?)?;
+            }
+            if output.index {
+                let index = InvertedIndex::new(&cs.sequence.words, &cs.offsets);
+                index.write(&mut create(&format!("{}.idx", filename))?)?;
+            }
+            Ok(())
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            tokenized::write(&mut out, corpus, cs, tokenizer)?;
+            out.flush().map_err(Error::Write)
+        }
+    }
+}
+
+fn print_usage(program: &str, opts: &Options, short: bool) {
+    if short {
+        println!("{}", opts.short_usage(program));
+    } else {
+        let brief = format!("Usage: {} [options] file...\n\nTokenize files and directories into a single file that bigrams, kwic and\nsentences accept in place of text.\n\nA file name of - reads standard input.", program);
+        print!("{}", opts.usage(&brief));
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let program: String = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optopt("o", "output", "write to FILE rather than standard output", "FILE");
+    opts.optopt("t", "tokenizer", "tokenizer to use: run (the default), english or ptb", "NAME");
+    opts.optopt("j", "jobs", "use N threads (default: one per core)", "N");
+    opts.optopt("e", "encoding", "read text in encoding NAME: utf-8, utf-16le, utf-16be, latin1 or windows-1252 (default: guessed)", "NAME");
+    opts.optflag("", "lossy", "replace bytes invalid in the encoding with U+FFFD rather than failing, or for text that is nearly all UTF-8, rather than reading it as latin1 or windows-1252");
+    opts.optflag("p", "preprocess", "leave out Project Gutenberg boilerplate and markup, keeping the original text");
+    opts.optflag("s", "suffix-array", "also write a suffix array to FILE.sa, for kwic");
+    opts.optflag("i", "index", "also write an inverted index to FILE.idx, for kwic");
+    opts.optflag("h", "help", "print detailed help");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
+    if matches.opt_present("h") || matches.free.is_empty() {
+        print_usage(&program, &opts, false);
+        return;
+    }
+    let decoding = options::decoding(&matches).unwrap_or_else(|e| options::exit(&program, &opts, e));
+    let name = matches.opt_str("t").unwrap_or_else(|| "run".to_string());
+    let tokenizer = match tokenizer::named(&name) {
+        Some(tokenizer) => tokenizer,
+        None => {
+            println!("unknown tokenizer: {}", name);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
+
+    let jobs = match matches.opt_get::<usize>("j") {
+        Ok(jobs) => jobs.unwrap_or_else(parallel::default_threads),
+        Err(e) => {
+            println!("jobs must be a number: {}", e);
+            print_usage(&program, &opts, true);
+            std::process::exit(2);
+        }
+    };
+
+    let output = matches.opt_str("o");
+    let suffix_array = matches.opt_present("s");
+    let index = matches.opt_present("i");
+    if (suffix_array || index) && output.is_none() {
+        println!("a suffix array or index requires an output file");
+        print_usage(&program, &opts, true);
+        std::process::exit(2);
+    }
+
+    let tokenizing = Tokenizing { decoding, preprocess: matches.opt_present("p"), name: &name, tokenizer: &*tokenizer, jobs };
+    let output = Output { file: output, suffix_array, index };
+    if let Err(e) = tokenize_files(&matches.free, &tokenizing, output) {
+        eprintln!("{}: {}", program, e);
+        std::process::exit(1);
+    }
+}
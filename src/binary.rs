@@ -0,0 +1,82 @@
+// Helpers for the binary file formats. Numbers are stored as little-endian
+// u64s.
+
+use std;
+use std::io::Write;
+
+use error::{Error,Result};
+use text_source::TextSource;
+
+pub fn put<W: Write>(out: &mut W, bytes: &[u8]) -> Result<()> {
+    out.write_all(bytes).map_err(Error::Write)
+}
+
+pub fn put_u64s<W: Write, I: IntoIterator<Item=usize>>(out: &mut W, ns: I) -> Result<()> {
+    for n in ns {
+        put(out, &(n as u64).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&bytes[offset..offset+8]);
+    u64::from_le_bytes(b)
+}
+
+// The number of bytes needed to pad a section to an 8-byte boundary.
+pub fn padding(len: usize) -> usize { (8 - len % 8) % 8 }
+
+pub fn invalid<T>(message: &str) -> Result<T> { Err(Error::InvalidFormat(message.to_string())) }
+
+// A 64-bit FNV-1a hash of a sequence of numbers, as u64s, for files built
+// from a sequence to check that they are used with it.
+pub fn checksum(ns: &[usize]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &n in ns {
+        for b in (n as u64).to_le_bytes() {
+            hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+// ----------------------------------------
+
+// A copy of a source that is not suitably aligned to be used in place, or
+// a file built in memory.
+pub struct Aligned {
+    pub buf: Vec<u64>,
+    pub len: usize,
+}
+
+impl TextSource for Aligned {
+    fn get_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.len) }
+    }
+}
+
+// A source whose u64 sections can be used in place as usizes, copying it if
+// it is not aligned.
+pub fn aligned(source: Box<dyn TextSource>) -> Result<Box<dyn TextSource>> {
+    if !cfg!(all(target_endian = "little", target_pointer_width = "64")) {
+        return invalid("only supported on 64-bit little-endian machines");
+    }
+    if (source.get_slice().as_ptr() as usize).is_multiple_of(8) {
+        return Ok(source);
+    }
+    let bytes = source.get_slice();
+    let mut buf = vec!(0u64; bytes.len().div_ceil(8));
+    let len = bytes.len();
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf.as_mut_ptr() as *mut u8, len);
+    }
+    Ok(Box::new(Aligned { buf, len }))
+}
+
+// A section of an aligned source, as usizes.
+pub fn usizes(bytes: &[u8], offset: usize, n: usize) -> &[usize] {
+    let bytes = &bytes[offset..offset + 8 * n];
+    assert!((bytes.as_ptr() as usize).is_multiple_of(8), "section is not aligned");
+    unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const usize, n) }
+}
@@ -1,12 +1,69 @@
 use std::cmp::Ordering;
+use std::fmt;
 use std::hash::{Hash,Hasher};
 use std::borrow::Borrow;
 
-#[derive(Debug,Clone)]
+use caseless::Caseless;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+// Each of the string types here wraps a &str and compares, orders and
+// hashes it by a key: the characters of the string after case folding or
+// normalization. Keys are computed as they are compared rather than
+// stored, and any of the types can be used as the T of a WordSequence.
+
+macro_rules! key_str {
+    ($name:ident, $key:ident) => {
+        impl<'s> PartialEq for $name<'s> {
+            fn eq(&self, other: &Self) -> bool { $key(self.s).eq($key(other.s)) }
+        }
+
+        impl<'s> Eq for $name<'s> { }
+
+        impl<'s> PartialOrd for $name<'s> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+        }
+
+        impl<'s> Ord for $name<'s> {
+            fn cmp(&self, other: &Self) -> Ordering { $key(self.s).cmp($key(other.s)) }
+        }
+
+        impl<'s> Hash for $name<'s> {
+            fn hash<H:Hasher>(&self, state: &mut H) {
+                for ch in $key(self.s) {
+                    ch.hash(state);
+                }
+            }
+        }
+
+        impl<'s> AsStr for $name<'s> {
+            fn as_str(&self) -> &str { self.s }
+        }
+    }
+}
+
+// Unicode default case folding, under which "Straße", "STRASSE" and
+// "strasse" are the same.
+fn case_folded(s: &str) -> impl Iterator<Item=char> + '_ { s.chars().default_case_fold() }
+
+// Canonical composition (NFC).
+fn composed(s: &str) -> impl Iterator<Item=char> + '_ { s.nfc() }
+
+// Compatibility composition (NFKC), under which ligatures, full-width
+// forms, circled digits and the like are the same as the plain characters.
+fn compatible(s: &str) -> impl Iterator<Item=char> + '_ { s.nfkc() }
+
+// Canonical decomposition (NFD) without combining marks.
+fn unaccented(s: &str) -> impl Iterator<Item=char> + '_ { s.nfd().filter(|&c| !is_combining_mark(c)) }
+
+// ---------------------------
+
+/// A string compared without regard to case, by full Unicode case folding.
+#[derive(Debug,Clone,Copy)]
 pub struct CaseStr<'s>{ s: &'s str}
 
 impl<'s> CaseStr<'s> {
-    pub fn from(s: &'s str) -> CaseStr<'s> { CaseStr{ s: s } }
+    pub fn from(s: &'s str) -> CaseStr<'s> { CaseStr{ s } }
 
     delegate!{
         s:
@@ -25,27 +82,15 @@ fn test_case_str_1() {
     assert_eq!(s.split_at(1), ("a","bc"))
 }
 
-impl<'s> ToString for CaseStr<'s> {
-    fn to_string(&self) -> String {
-        self.s.to_lowercase()
-    }
-}
-
-impl<'s> PartialEq for CaseStr<'s> {
-    fn eq(&self, other: &Self) -> bool {
-        if self.len() == other.len() {
-            let it_l = self.s.chars().flat_map(|c| c.to_lowercase());
-            let it_r = other.s.chars().flat_map(|c| c.to_lowercase());
-            for (ch_l,ch_r) in it_l.zip( it_r ) {
-                if ch_l != ch_r { return false; }
-            }
-            return true;
-        }
-        return false;
+// Displayed in lower case, which is not quite the key: "Straße" is
+// displayed as "straße" rather than "strasse".
+impl<'s> fmt::Display for CaseStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.s.to_lowercase())
     }
 }
 
-impl<'s> Eq for CaseStr<'s> { }
+key_str!(CaseStr, case_folded);
 
 #[test]
 fn test_case_str_eq() {
@@ -56,20 +101,6 @@ fn test_case_str_eq() {
     assert_eq!(s,u);
 }
 
-impl<'s> PartialOrd for CaseStr<'s> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let it_l = self.s.chars().flat_map(|c| c.to_lowercase());
-        let it_r = other.s.chars().flat_map(|c| c.to_lowercase());
-        for (ch_l,ch_r) in it_l.zip( it_r ) {
-            match ch_l.partial_cmp( &ch_r ) {
-                Some(Ordering::Equal) => { }
-                ord => { return ord; }
-            }
-        }
-        return self.len().partial_cmp( &other.len() );
-    }
-}
-
 #[test]
 fn test_case_str_partialord() {
     let s = CaseStr::from("abc");
@@ -85,15 +116,6 @@ fn test_case_str_partialord() {
     assert_eq!(w.partial_cmp(&s), Some(Ordering::Greater));
 }
 
-impl<'s> Ord for CaseStr<'s> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self.partial_cmp(other) {
-            Some(ord) => ord,
-            None      => { panic!("incomparible strings?") }
-        }
-    }
-}
-
 #[test]
 fn test_case_str_ord() {
     let s = CaseStr::from("abc");
@@ -109,16 +131,9 @@ fn test_case_str_ord() {
     assert_eq!(w.cmp(&s), Ordering::Greater);
 }
 
-impl<'s> Hash for CaseStr<'s> {
-    fn hash<H:Hasher>(&self, state: &mut H) {
-        for ch in self.s.chars().flat_map(|c| c.to_lowercase()) {
-            ch.hash(state);
-        }
-    }
-}
-
 #[test]
 fn test_case_str_hash() {
+    #[allow(deprecated)]
     fn do_hash<'s>(s: &CaseStr<'s>) -> u64 {
         use std::hash::SipHasher;
         let mut hasher = SipHasher::new();
@@ -147,6 +162,47 @@ fn test_hashmap() {
     assert_eq!(hm.get(&CaseStr::from("owO")), None);
 }
 
+#[cfg(test)]
+fn hash_of<T: Hash>(t: &T) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    let mut hasher = DefaultHasher::new();
+    t.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn test_case_folding() {
+    // Pairs whose lower case forms have different lengths in UTF-8.
+    let pairs = [
+        ("\u{130}", "i\u{307}"),           // capital I with dot above
+        ("\u{212a}elvin", "kelvin"),        // Kelvin sign
+        ("Stra\u{df}e", "STRASSE"),         // sharp s
+        ("\u{1e9e}", "ss"),                 // capital sharp s
+        ("\u{3a3}\u{3c3}\u{3c2}", "\u{3c3}\u{3c3}\u{3c3}"), // sigma, including final sigma
+        ("\u{fb01}le", "FILE"),             // fi ligature
+    ];
+    for &(l, r) in pairs.iter() {
+        let (l, r) = (CaseStr::from(l), CaseStr::from(r));
+        assert_eq!(l, r);
+        assert_eq!(l.cmp(&r), Ordering::Equal);
+        assert_eq!(hash_of(&l), hash_of(&r));
+    }
+    assert!(CaseStr::from("stras") < CaseStr::from("Stra\u{df}e"));
+    assert_ne!(CaseStr::from("Stra\u{df}e"), CaseStr::from("strase"));
+}
+
+#[test]
+fn test_word_sequence_keys() {
+    use word_sequence::WordSequence;
+    let ws = WordSequence::new("Stra\u{df}e STRASSE strasse", CaseStr::from, |s| !s.trim().is_empty());
+    assert_eq!(ws.vocabulary.len(), 1);
+    assert_eq!(ws.words, vec!(0, 0, 0));
+    let ws = WordSequence::new("caf\u{e9} cafe\u{301} cafe", NfcStr::from, |s| !s.trim().is_empty());
+    assert_eq!(ws.words, vec!(0, 0, 1));
+    let ws = WordSequence::new("caf\u{e9} cafe\u{301} cafe", UnaccentedStr::from, |s| !s.trim().is_empty());
+    assert_eq!(ws.words, vec!(0, 0, 0));
+}
+
 impl<'s> Borrow<str> for CaseStr<'s> {
     fn borrow(&self) -> &str { self.s }
 }
@@ -158,13 +214,96 @@ pub trait AsStr {
 }
 
 impl AsStr for str {
-    fn as_str<'s>(&'s self) -> &'s str { self }
+    fn as_str(&self) -> &str { self }
+}
+
+impl AsStr for &str {
+    fn as_str(&self) -> &str { self }
+}
+
+// ---------------------------
+
+/// A string compared after canonical composition (NFC), so that precomposed
+/// and decomposed accented characters are the same.
+#[derive(Debug,Clone,Copy)]
+pub struct NfcStr<'s>{ s: &'s str }
+
+impl<'s> NfcStr<'s> {
+    pub fn from(s: &'s str) -> NfcStr<'s> { NfcStr{ s } }
 }
 
-impl<'s> AsStr for &'s str {
-    fn as_str<'t>(&'t self) -> &'t str { self }
+impl<'s> fmt::Display for NfcStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", composed(self.s).collect::<String>())
+    }
 }
 
-impl<'s> AsStr for CaseStr<'s> {
-    fn as_str<'t>(&'t self) -> &'t str { self.s }
+key_str!(NfcStr, composed);
+
+#[test]
+fn test_nfc_str() {
+    assert_eq!(NfcStr::from("caf\u{e9}"), NfcStr::from("cafe\u{301}"));
+    assert_eq!(hash_of(&NfcStr::from("caf\u{e9}")), hash_of(&NfcStr::from("cafe\u{301}")));
+    assert_ne!(NfcStr::from("caf\u{e9}"), NfcStr::from("cafe"));
+    assert_ne!(NfcStr::from("Caf\u{e9}"), NfcStr::from("caf\u{e9}"));
+    assert_ne!(NfcStr::from("\u{fb01}"), NfcStr::from("fi"));
+    // Combining marks are put in canonical order.
+    assert_eq!(NfcStr::from("q\u{307}\u{323}"), NfcStr::from("q\u{323}\u{307}"));
+    assert_eq!(NfcStr::from("cafe\u{301}").to_string(), "caf\u{e9}");
+}
+
+/// A string compared after compatibility composition (NFKC), so that, for
+/// example, ligatures and full-width letters are the same as the letters
+/// they stand for.
+#[derive(Debug,Clone,Copy)]
+pub struct NfkcStr<'s>{ s: &'s str }
+
+impl<'s> NfkcStr<'s> {
+    pub fn from(s: &'s str) -> NfkcStr<'s> { NfkcStr{ s } }
+}
+
+impl<'s> fmt::Display for NfkcStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", compatible(self.s).collect::<String>())
+    }
+}
+
+key_str!(NfkcStr, compatible);
+
+#[test]
+fn test_nfkc_str() {
+    assert_eq!(NfkcStr::from("\u{fb01}le"), NfkcStr::from("file"));
+    assert_eq!(NfkcStr::from("\u{ff21}\u{ff22}"), NfkcStr::from("AB"));
+    assert_eq!(NfkcStr::from("\u{2460}"), NfkcStr::from("1"));
+    assert_eq!(NfkcStr::from("caf\u{e9}"), NfkcStr::from("cafe\u{301}"));
+    assert_eq!(hash_of(&NfkcStr::from("\u{fb01}")), hash_of(&NfkcStr::from("fi")));
+    assert_ne!(NfkcStr::from("File"), NfkcStr::from("file"));
+}
+
+/// A string compared without regard to accents: combining marks are
+/// dropped after canonical decomposition. Case still matters.
+#[derive(Debug,Clone,Copy)]
+pub struct UnaccentedStr<'s>{ s: &'s str }
+
+impl<'s> UnaccentedStr<'s> {
+    pub fn from(s: &'s str) -> UnaccentedStr<'s> { UnaccentedStr{ s } }
+}
+
+impl<'s> fmt::Display for UnaccentedStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", unaccented(self.s).collect::<String>())
+    }
+}
+
+key_str!(UnaccentedStr, unaccented);
+
+#[test]
+fn test_unaccented_str() {
+    assert_eq!(UnaccentedStr::from("na\u{ef}ve"), UnaccentedStr::from("naive"));
+    assert_eq!(UnaccentedStr::from("Z\u{fc}rich"), UnaccentedStr::from("Zu\u{308}rich"));
+    assert_eq!(UnaccentedStr::from("\u{c5}ngstr\u{f6}m"), UnaccentedStr::from("Angstrom"));
+    assert_eq!(hash_of(&UnaccentedStr::from("r\u{e9}sum\u{e9}")), hash_of(&UnaccentedStr::from("resume")));
+    assert_ne!(UnaccentedStr::from("Resume"), UnaccentedStr::from("resume"));
+    assert!(UnaccentedStr::from("\u{e9}a") < UnaccentedStr::from("eb"));
+    assert_eq!(UnaccentedStr::from("na\u{ef}ve").to_string(), "naive");
 }
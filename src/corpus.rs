@@ -0,0 +1,401 @@
+use std;
+use std::fs;
+use std::hash::Hash;
+use std::io::{BufRead,BufReader};
+use std::path::{Path,PathBuf};
+
+use encoding::{Encoding,ErrorPolicy};
+use error::{Error,Result};
+use parallel;
+use text_source;
+use tokenizer::{RunTokenizer,Tokenizer};
+use word_sequence::{Word,WordSequence};
+
+/// A named, decoded text.
+#[derive(Debug)]
+pub struct Document {
+    pub name: String,
+    pub text: String,
+}
+
+/// A collection of documents, to be tokenized together.
+#[derive(Debug,Default)]
+pub struct Corpus {
+    pub documents: Vec<Document>,
+}
+
+impl Corpus {
+    pub fn new() -> Corpus { Corpus { documents: Vec::new() } }
+
+    pub fn add_document(&mut self, name: &str, text: String) {
+        self.documents.push(Document { name: name.to_string(), text });
+    }
+
+    /// Read and decode a file (or `-`, standard input) into the corpus.
+    pub fn add_file(&mut self, filename: &str) -> Result<()> {
+        self.add_file_as(filename, None, ErrorPolicy::Strict)
+    }
+
+    /// Read a file into the corpus, decoding it from an encoding if one is
+    /// given, as for `TextSource::decode_as`.
+    pub fn add_file_as(&mut self, filename: &str, encoding: Option<Encoding>, policy: ErrorPolicy) -> Result<()> {
+        let source = text_source::open(filename)?;
+        let text = source.decode_as(encoding, policy)?.text.into_owned();
+        self.add_document(filename, text);
+        Ok(())
+    }
+
+    /// A corpus of files and directories; directories contribute every file
+    /// beneath them, in sorted order.
+    pub fn from_paths<S: AsRef<str>>(paths: &[S]) -> Result<Corpus> {
+        Corpus::from_paths_as(paths, None, ErrorPolicy::Strict)
+    }
+
+    /// A corpus of files and directories, decoded as by `add_file_as`.
+    pub fn from_paths_as<S: AsRef<str>>(paths: &[S], encoding: Option<Encoding>, policy: ErrorPolicy) -> Result<Corpus> {
+        let mut corpus = Corpus::new();
+        for path in paths {
+            let path = path.as_ref();
+            if path != "-" && Path::new(path).is_dir() {
+                for file in walk(Path::new(path))? {
+                    corpus.add_file_as(&file.to_string_lossy(), encoding, policy)?;
+                }
+            } else {
+                corpus.add_file_as(path, encoding, policy)?;
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// A corpus of every file beneath a directory.
+    pub fn from_directory(dir: &str) -> Result<Corpus> {
+        Corpus::from_paths(&[dir])
+    }
+
+    /// A corpus of the files matching a glob pattern. `?` matches any one
+    /// character and `*` any run of characters within a path component;
+    /// `**` matches any number of directories. As with an empty directory,
+    /// a pattern matching nothing, even beneath a directory that does not
+    /// exist, gives an empty corpus.
+    pub fn from_glob(pattern: &str) -> Result<Corpus> {
+        Corpus::from_glob_as(pattern, None, ErrorPolicy::Strict)
+    }
+
+    /// A corpus of the files matching a glob pattern, decoded as by
+    /// `add_file_as`.
+    pub fn from_glob_as(pattern: &str, encoding: Option<Encoding>, policy: ErrorPolicy) -> Result<Corpus> {
+        let base = glob_base(pattern);
+        let root = if base.is_empty() { Path::new(".") } else { Path::new(base) };
+        let mut corpus = Corpus::new();
+        if !root.is_dir() {
+            return Ok(corpus);
+        }
+        let relative = |path: &Path| {
+            let name = path.to_string_lossy().into_owned();
+            if base.is_empty() { name.trim_start_matches("./").to_string() } else { name }
+        };
+        // Without `**`, a directory can hold matches only if its path
+        // matches as many of the pattern's components.
+        let components: Vec<&str> = pattern.split('/').collect();
+        let descend = |dir: &Path| {
+            if pattern.contains("**") {
+                return true;
+            }
+            let name: Vec<char> = relative(dir).chars().collect();
+            let depth = name.iter().filter(|&&c| c == '/').count() + 1;
+            depth < components.len() && glob_match(&components[..depth].join("/").chars().collect::<Vec<_>>(), &name)
+        };
+        let pattern: Vec<char> = pattern.chars().collect();
+        for file in walk_where(root, descend)? {
+            let name = relative(&file);
+            let chars: Vec<char> = name.chars().collect();
+            if glob_match(&pattern, &chars) {
+                corpus.add_file_as(&name, encoding, policy)?;
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// A corpus of the files listed in a manifest, one per line. Blank lines
+    /// and lines starting with `#` are ignored; relative paths are relative
+    /// to the manifest's directory.
+    pub fn from_manifest(manifest: &str) -> Result<Corpus> {
+        Corpus::from_manifest_as(manifest, None, ErrorPolicy::Strict)
+    }
+
+    /// A corpus of the files listed in a manifest, decoded as by
+    /// `add_file_as`.
+    pub fn from_manifest_as(manifest: &str, encoding: Option<Encoding>, policy: ErrorPolicy) -> Result<Corpus> {
+        let file = fs::File::open(manifest).map_err(|e| Error::Open(manifest.to_string(), e))?;
+        let dir = Path::new(manifest).parent().unwrap_or_else(|| Path::new(""));
+        let mut paths = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(Error::Read)?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            paths.push(dir.join(line).to_string_lossy().into_owned());
+        }
+        Corpus::from_paths_as(&paths, encoding, policy)
+    }
+
+    pub fn len(&self) -> usize { self.documents.len() }
+    pub fn is_empty(&self) -> bool { self.documents.is_empty() }
+
+    /// Tokenize every document into a single WordSequence with a shared
+    /// vocabulary.
+    pub fn tokenize<'c,T,F,P>(&'c self, to_t: F, is_word: P) -> CorpusSequence<T>
+        where T: Hash + Eq + Clone,
+              F: Fn(&'c str) -> T,
+              P: Fn(&'c str) -> bool {
+        self.tokenize_with(&RunTokenizer, to_t, is_word)
+    }
+
+    /// Like `tokenize`, using a given tokenizer.
+    pub fn tokenize_with<'c,T,K,F,P>(&'c self, tokenizer: &K, to_t: F, is_word: P) -> CorpusSequence<T>
+        where T: Hash + Eq + Clone,
+              K: Tokenizer + ?Sized,
+              F: Fn(&'c str) -> T,
+              P: Fn(&'c str) -> bool {
+        let mut sequence = WordSequence::empty();
+        let mut offsets = vec!(0);
+        for document in &self.documents {
+            sequence.append_with(tokenizer, &document.text, &to_t, &is_word);
+            offsets.push(sequence.words.len());
+        }
+        CorpusSequence { sequence, offsets }
+    }
+
+    /// Like `tokenize_with`, spreading the work over several threads. The
+    /// result is the same, provided the tokenizer's tokens never contain
+    /// whitespace.
+    pub fn tokenize_parallel<'c,T,K,F,P>(&'c self, tokenizer: &K, to_t: F, is_word: P, threads: usize) -> CorpusSequence<T>
+        where T: Hash + Eq + Clone + Send,
+              K: Tokenizer + Sync + ?Sized,
+              F: Fn(&'c str) -> T + Sync,
+              P: Fn(&'c str) -> bool + Sync {
+        // Split each document into pieces of about the same size.
+        let total: usize = self.documents.iter().map(|d| d.text.len()).sum();
+        let size = std::cmp::max(total.div_ceil(std::cmp::max(threads, 1)), 1);
+        let mut pieces = Vec::new();
+        for (doc,document) in self.documents.iter().enumerate() {
+            let points = parallel::split_points(&document.text, document.text.len().div_ceil(size));
+            pieces.extend(points.windows(2).map(|p| (doc, p[0], p[1])));
+        }
+        let sequences: Vec<WordSequence<T>> = parallel::map_ranges(pieces.len(), threads, |r| {
+            r.map(|i| {
+                let (doc, start, end) = pieces[i];
+                WordSequence::with_tokenizer(tokenizer, &self.documents[doc].text[start..end], &to_t, &is_word)
+            }).collect::<Vec<_>>()
+        }).into_iter().flatten().collect();
+        let mut sequence = WordSequence::empty();
+        let mut offsets = vec!(0);
+        let mut next = 0;
+        for doc in 0..self.len() {
+            sequence.texts.push(sequence.words.len());
+            while next < pieces.len() && pieces[next].0 == doc {
+                sequence.append_sequence(&sequences[next], pieces[next].1);
+                next += 1;
+            }
+            offsets.push(sequence.words.len());
+        }
+        CorpusSequence { sequence, offsets }
+    }
+}
+
+// ----------------------------------------
+
+/// The tokens of a corpus, as one WordSequence plus the boundaries of each
+/// document within it.
+#[derive(Debug)]
+pub struct CorpusSequence<T: Hash + Eq + Clone> {
+    pub sequence: WordSequence<T>,
+    /// The index of each document's first token; the last entry is the total
+    /// number of tokens.
+    pub offsets: Vec<usize>,
+}
+
+impl<T: Hash + Eq + Clone> CorpusSequence<T> {
+    /// The number of documents.
+    pub fn len(&self) -> usize { self.offsets.len() - 1 }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The concatenated tokens of every document.
+    pub fn words(&self) -> &[Word] { &self.sequence.words }
+
+    /// The tokens of one document.
+    pub fn document(&self, doc: usize) -> &[Word] {
+        &self.sequence.words[self.offsets[doc]..self.offsets[doc+1]]
+    }
+
+    /// The tokens of each document, in order.
+    pub fn documents(&self) -> impl Iterator<Item=&[Word]> {
+        (0..self.len()).map(move |doc| self.document(doc))
+    }
+
+    /// The document containing the token at a position in `words()`.
+    pub fn document_of(&self, idx: usize) -> Option<usize> {
+        if idx >= self.sequence.words.len() { return None; }
+        match self.offsets.binary_search(&idx) {
+            Ok(mut doc) => {
+                // Skip past any empty documents starting at the same place.
+                while self.offsets[doc+1] == idx { doc += 1; }
+                Some(doc)
+            }
+            Err(doc) => Some(doc - 1),
+        }
+    }
+}
+
+#[test]
+fn test_tokenize_parallel() {
+    let mut corpus = Corpus::new();
+    corpus.add_document("one", "a b a c d e f g h".to_string());
+    corpus.add_document("empty", String::new());
+    corpus.add_document("two", "b c i j".to_string());
+    let expected = corpus.tokenize(|s| s, |_| true);
+    for threads in 1..5 {
+        let cs = corpus.tokenize_parallel(&RunTokenizer, |s| s, |_| true, threads);
+        assert_eq!(cs.words(), expected.words());
+        assert_eq!(cs.sequence.spans, expected.sequence.spans);
+        assert_eq!(cs.sequence.texts, expected.sequence.texts);
+        assert_eq!(cs.offsets, expected.offsets);
+    }
+}
+
+#[test]
+fn test_tokenize() {
+    let mut corpus = Corpus::new();
+    corpus.add_document("one", "a b a".to_string());
+    corpus.add_document("empty", String::new());
+    corpus.add_document("two", "b c".to_string());
+    let cs = corpus.tokenize(|s| s, |_| true);
+    assert_eq!(cs.len(), 3);
+    assert_eq!(cs.words(), &[0,1,0,1,2]);
+    assert_eq!(cs.document(0), &[0,1,0]);
+    assert_eq!(cs.document(1), &[] as &[Word]);
+    assert_eq!(cs.document(2), &[1,2]);
+    assert_eq!(cs.documents().count(), 3);
+    assert_eq!(cs.document_of(2), Some(0));
+    assert_eq!(cs.document_of(3), Some(2));
+    assert_eq!(cs.document_of(5), None);
+}
+
+// ----------------------------------------
+
+// Every file beneath a directory, sorted.
+fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    walk_where(dir, |_| true)
+}
+
+// Every file beneath a directory, sorted, looking only in the
+// subdirectories for which `descend` is true.
+fn walk_where<D: Fn(&Path) -> bool>(dir: &Path, descend: D) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec!(dir.to_path_buf());
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| Error::Open(dir.to_string_lossy().into_owned(), e))?;
+        for entry in entries {
+            let path = entry.map_err(Error::Read)?.path();
+            if !path.is_dir() {
+                files.push(path);
+            } else if descend(&path) {
+                pending.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// The leading directories of a glob pattern that contain no wildcards.
+fn glob_base(pattern: &str) -> &str {
+    let wild = pattern.find(['*', '?']).unwrap_or(pattern.len());
+    match pattern[..wild].rfind('/') {
+        Some(0) => "/",
+        Some(slash) => &pattern[..slash],
+        None => "",
+    }
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    let mut memo = vec!(None; (pattern.len() + 1) * (name.len() + 1));
+    glob_match_at(pattern, name, 0, 0, &mut memo)
+}
+
+// Whether the pattern from `p` matches the name from `n`. Each pair of
+// positions is tried once, so that runs of wildcards cannot make the
+// search exponential.
+fn glob_match_at(pattern: &[char], name: &[char], p: usize, n: usize, memo: &mut [Option<bool>]) -> bool {
+    let key = p * (name.len() + 1) + n;
+    if let Some(matched) = memo[key] {
+        return matched;
+    }
+    let matched = match pattern.get(p) {
+        None => n == name.len(),
+        Some(&'*') if pattern.get(p+1) == Some(&'*') => {
+            let rest = if pattern.get(p+2) == Some(&'/') { p + 3 } else { p + 2 };
+            (n..name.len()+1).any(|i| (i == n || name[i-1] == '/') && glob_match_at(pattern, name, rest, i, memo))
+                || glob_match_at(pattern, name, p + 2, n, memo)
+        }
+        Some(&'*') => {
+            let run = name[n..].iter().take_while(|&&c| c != '/').count();
+            (n..n+run+1).any(|i| glob_match_at(pattern, name, p + 1, i, memo))
+        }
+        Some(&'?') => {
+            n < name.len() && name[n] != '/' && glob_match_at(pattern, name, p + 1, n + 1, memo)
+        }
+        Some(&c) => {
+            n < name.len() && name[n] == c && glob_match_at(pattern, name, p + 1, n + 1, memo)
+        }
+    };
+    memo[key] = Some(matched);
+    matched
+}
+
+#[test]
+fn test_glob_match() {
+    fn m(p: &str, n: &str) -> bool {
+        glob_match(&p.chars().collect::<Vec<_>>(), &n.chars().collect::<Vec<_>>())
+    }
+    assert!(m("data/*.txt", "data/Tom_Sawyer.txt"));
+    assert!(!m("data/*.txt", "data/sub/Tom_Sawyer.txt"));
+    assert!(m("data/**/*.txt", "data/Tom_Sawyer.txt"));
+    assert!(m("data/**/*.txt", "data/sub/deeper/a.txt"));
+    assert!(m("data/training-?.txt", "data/training-1.txt"));
+    assert!(!m("data/training-?.txt", "data/training-12.txt"));
+    assert!(m("**/a/**/b/**/c", "x/a/y/b/z/c"));
+    let deep = "a/".repeat(40) + "b";
+    assert!(!m(&"**/a/".repeat(20), &deep));
+    assert!(!m(&"*a".repeat(20), &("a".repeat(60) + "b")));
+    assert_eq!(glob_base("data/*.txt"), "data");
+    assert_eq!(glob_base("*.txt"), "");
+}
+
+#[test]
+fn test_from_glob() {
+    let corpus = unwrap!( Corpus::from_glob("data/training-*.txt").ok() );
+    assert_eq!(corpus.len(), 1);
+    assert_eq!(corpus.documents[0].name, "data/training-1.txt");
+    assert!(corpus.documents[0].text.starts_with("\r\n\"TOM!+\""));
+    let corpus = unwrap!( Corpus::from_glob("data/*.nothing").ok() );
+    assert!(corpus.is_empty());
+    let corpus = unwrap!( Corpus::from_glob("no/such/dir/*.txt").ok() );
+    assert!(corpus.is_empty());
+    let corpus = unwrap!( Corpus::from_glob("*/training-?.txt").ok() );
+    assert_eq!(corpus.len(), 1);
+    assert_eq!(corpus.documents[0].name, "data/training-1.txt");
+    let corpus = unwrap!( Corpus::from_glob_as("data/training-*.txt", Some(Encoding::Latin1), ErrorPolicy::Strict).ok() );
+    assert!(corpus.documents[0].text.starts_with("\u{ef}\u{bb}\u{bf}\r\n"));
+}
+
+#[test]
+fn test_walk_where() {
+    let all = unwrap!( walk(Path::new("src")).ok() );
+    assert!(all.contains(&PathBuf::from("src/bin/kwic.rs")));
+    let top = unwrap!( walk_where(Path::new("src"), |_| false).ok() );
+    assert!(top.contains(&PathBuf::from("src/corpus.rs")));
+    assert!(!top.contains(&PathBuf::from("src/bin/kwic.rs")));
+    let corpus = unwrap!( Corpus::from_glob("src/*/kwic.rs").ok() );
+    assert_eq!(corpus.len(), 1);
+}
@@ -0,0 +1,304 @@
+use std;
+use std::borrow::Cow;
+use std::fmt;
+
+use error::{Error,Result};
+
+/// Character encodings understood by the decoder.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    /// The encoding with a name, ignoring case: its name as displayed, or
+    /// one of `utf8`, `utf16le`, `utf16be`, `latin1` and `cp1252`.
+    pub fn named(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8"          => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le"    => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be"    => Some(Encoding::Utf16Be),
+            "iso-8859-1" | "latin1"   => Some(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Encoding::Utf8        => "UTF-8",
+            Encoding::Utf16Le     => "UTF-16LE",
+            Encoding::Utf16Be     => "UTF-16BE",
+            Encoding::Latin1      => "ISO-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What to do with byte sequences that are invalid in the input encoding.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum ErrorPolicy {
+    /// Fail with `Error::InvalidEncoding`.
+    Strict,
+    /// Replace each invalid sequence with U+FFFD.
+    Replace,
+}
+
+/// Decoded text, along with the encoding it was decoded from. Any byte
+/// order mark has been removed.
+#[derive(Debug)]
+pub struct Decoded<'a> {
+    pub text: Cow<'a,str>,
+    pub encoding: Encoding,
+    pub bom: bool,
+}
+
+#[test]
+fn test_named() {
+    for &encoding in &[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be, Encoding::Latin1, Encoding::Windows1252] {
+        assert_eq!(Encoding::named(&encoding.to_string()), Some(encoding));
+    }
+    assert_eq!(Encoding::named("Latin1"), Some(Encoding::Latin1));
+    assert_eq!(Encoding::named("ebcdic"), None);
+}
+
+// ----------------------------------------
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+
+fn bom_of(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.starts_with(UTF8_BOM) { Some(Encoding::Utf8) }
+    else if bytes.starts_with(UTF16LE_BOM) { Some(Encoding::Utf16Le) }
+    else if bytes.starts_with(UTF16BE_BOM) { Some(Encoding::Utf16Be) }
+    else { None }
+}
+
+fn bom_len(encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::Utf8 => UTF8_BOM.len(),
+        Encoding::Utf16Le | Encoding::Utf16Be => UTF16LE_BOM.len(),
+        _ => 0,
+    }
+}
+
+/// Guess the encoding of a block of bytes. A byte order mark is trusted;
+/// otherwise text with many NUL bytes in alternating positions is UTF-16,
+/// and text that is valid UTF-8, or nearly so, is UTF-8: its few invalid
+/// bytes are left to `decode`. Anything else is an 8-bit encoding, as for
+/// `detect_8bit`.
+pub fn detect(bytes: &[u8]) -> Encoding {
+    if let Some(encoding) = bom_of(bytes) {
+        return encoding;
+    }
+    if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+        let even = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        let half = bytes.len() / 2;
+        if odd > half / 2 && even == 0 { return Encoding::Utf16Le; }
+        if even > half / 2 && odd == 0 { return Encoding::Utf16Be; }
+    }
+    if is_mostly_utf8(bytes) {
+        return Encoding::Utf8;
+    }
+    detect_8bit(bytes)
+}
+
+/// Guess the 8-bit encoding of a block of bytes: Windows-1252 if it uses
+/// the C1 range (where 1252 puts its punctuation), else Latin-1.
+pub fn detect_8bit(bytes: &[u8]) -> Encoding {
+    if bytes.iter().any(|&b| (0x80..0xa0).contains(&b)) {
+        Encoding::Windows1252
+    } else {
+        Encoding::Latin1
+    }
+}
+
+// The number of valid multibyte UTF-8 sequences needed for each invalid
+// one for text to be taken as UTF-8. Text in an 8-bit encoding seldom holds
+// a valid sequence at all.
+const UTF8_MAJORITY: usize = 10;
+
+// Whether a block of bytes is valid UTF-8, or has at least UTF8_MAJORITY
+// valid multibyte sequences for each invalid one.
+fn is_mostly_utf8(mut bytes: &[u8]) -> bool {
+    let (mut valid, mut invalid) = (0, 0);
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => {
+                valid += s.chars().filter(|c| !c.is_ascii()).count();
+                break;
+            }
+            Err(e) => {
+                let (good, rest) = bytes.split_at(e.valid_up_to());
+                valid += unwrap!( std::str::from_utf8(good).ok() ).chars().filter(|c| !c.is_ascii()).count();
+                invalid += 1;
+                // An incomplete sequence at the end has no error length.
+                bytes = &rest[e.error_len().unwrap_or(rest.len())..];
+            }
+        }
+    }
+    invalid == 0 || valid >= UTF8_MAJORITY * invalid
+}
+
+#[test]
+fn test_detect() {
+    assert_eq!(detect(b"\xef\xbb\xbfabc"), Encoding::Utf8);
+    assert_eq!(detect(b"\xff\xfea\x00"), Encoding::Utf16Le);
+    assert_eq!(detect(b"\xfe\xff\x00a"), Encoding::Utf16Be);
+    assert_eq!(detect("caf\u{e9}".as_bytes()), Encoding::Utf8);
+    assert_eq!(detect(b"a\x00b\x00c\x00"), Encoding::Utf16Le);
+    assert_eq!(detect(b"\x00a\x00b\x00c"), Encoding::Utf16Be);
+    assert_eq!(detect(b"caf\xe9"), Encoding::Latin1);
+    assert_eq!(detect(b"\x93quoted\x94"), Encoding::Windows1252);
+    // A stray byte in UTF-8 text.
+    let mut bytes = "caf\u{e9} na\u{ef}ve r\u{e9}sum\u{e9} ".repeat(3).into_bytes();
+    bytes.push(0xe9);
+    assert_eq!(detect(&bytes), Encoding::Utf8);
+    let mut bytes = "caf\u{e9} ".to_string().into_bytes();
+    bytes.extend(b"\x93quoted\x94");
+    assert_eq!(detect(&bytes), Encoding::Windows1252);
+}
+
+// ----------------------------------------
+
+/// Detect the encoding of a block of bytes and decode it. Text taken as
+/// UTF-8 despite a few invalid bytes has them replaced under
+/// `ErrorPolicy::Replace`, but under `ErrorPolicy::Strict` is decoded in
+/// an 8-bit encoding instead, as every byte is valid in one.
+pub fn decode(bytes: &[u8], policy: ErrorPolicy) -> Result<Decoded<'_>> {
+    let encoding = detect(bytes);
+    match decode_as(bytes, encoding, policy) {
+        Err(Error::InvalidUtf8(_)) if encoding == Encoding::Utf8 => decode_as(bytes, detect_8bit(bytes), policy),
+        decoded => decoded,
+    }
+}
+
+/// Decode a block of bytes in a given encoding, removing a byte order mark
+/// for that encoding if one is present.
+pub fn decode_as(bytes: &[u8], encoding: Encoding, policy: ErrorPolicy) -> Result<Decoded<'_>> {
+    let bom = bom_of(bytes) == Some(encoding);
+    let body = if bom { &bytes[bom_len(encoding)..] } else { bytes };
+    let text = match encoding {
+        Encoding::Utf8        => decode_utf8(body, policy)?,
+        Encoding::Utf16Le     => Cow::Owned(decode_utf16(body, u16::from_le_bytes, encoding, policy)?),
+        Encoding::Utf16Be     => Cow::Owned(decode_utf16(body, u16::from_be_bytes, encoding, policy)?),
+        Encoding::Latin1      => Cow::Owned(body.iter().map(|&b| b as char).collect()),
+        Encoding::Windows1252 => Cow::Owned(body.iter().map(|&b| windows_1252(b)).collect()),
+    };
+    Ok( Decoded { text, encoding, bom } )
+}
+
+fn decode_utf8(bytes: &[u8], policy: ErrorPolicy) -> Result<Cow<'_,str>> {
+    match policy {
+        ErrorPolicy::Strict  => Ok( Cow::Borrowed(std::str::from_utf8(bytes)?) ),
+        ErrorPolicy::Replace => Ok( String::from_utf8_lossy(bytes) ),
+    }
+}
+
+fn decode_utf16<F>(bytes: &[u8], to_u16: F, encoding: Encoding, policy: ErrorPolicy) -> Result<String>
+    where F: Fn([u8;2]) -> u16 {
+    let units = bytes.chunks(2)
+        .filter(|c| c.len() == 2)
+        .map(|c| to_u16([c[0], c[1]]));
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut offset = 0;
+    for res in std::char::decode_utf16(units) {
+        match (res, policy) {
+            (Ok(ch), _) => {
+                text.push(ch);
+                offset += 2 * ch.len_utf16();
+            }
+            (Err(_), ErrorPolicy::Replace) => {
+                text.push(std::char::REPLACEMENT_CHARACTER);
+                offset += 2;
+            }
+            (Err(_), ErrorPolicy::Strict) => {
+                return Err(Error::InvalidEncoding(encoding, offset));
+            }
+        }
+    }
+    if !bytes.len().is_multiple_of(2) {
+        match policy {
+            ErrorPolicy::Replace => text.push(std::char::REPLACEMENT_CHARACTER),
+            ErrorPolicy::Strict  => return Err(Error::InvalidEncoding(encoding, bytes.len() - 1)),
+        }
+    }
+    Ok(text)
+}
+
+// Windows-1252 differs from Latin-1 only in 0x80-0x9f. The five bytes it
+// leaves undefined are mapped to the C1 controls, as Latin-1 does.
+fn windows_1252(b: u8) -> char {
+    const HIGH: [char; 32] = [
+        '\u{20ac}', '\u{81}',   '\u{201a}', '\u{192}',  '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+        '\u{2c6}',  '\u{2030}', '\u{160}',  '\u{2039}', '\u{152}',  '\u{8d}',   '\u{17d}',  '\u{8f}',
+        '\u{90}',   '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+        '\u{2dc}',  '\u{2122}', '\u{161}',  '\u{203a}', '\u{153}',  '\u{9d}',   '\u{17e}',  '\u{178}',
+    ];
+    if (0x80..0xa0).contains(&b) { HIGH[(b - 0x80) as usize] } else { b as char }
+}
+
+#[test]
+fn test_decode_utf8_bom() {
+    let d = unwrap!( decode(b"\xef\xbb\xbf\"TOM!", ErrorPolicy::Strict).ok() );
+    assert_eq!(d.encoding, Encoding::Utf8);
+    assert!(d.bom);
+    assert_eq!(d.text, "\"TOM!");
+}
+
+#[test]
+fn test_decode_utf16() {
+    let d = unwrap!( decode(b"\xff\xfeT\x00o\x00m\x00", ErrorPolicy::Strict).ok() );
+    assert_eq!((d.encoding, d.text.as_ref()), (Encoding::Utf16Le, "Tom"));
+    let d = unwrap!( decode_as(b"\x00T\x00o\x00m", Encoding::Utf16Be, ErrorPolicy::Strict).ok() );
+    assert_eq!(d.text, "Tom");
+    // An unpaired surrogate.
+    let bad = b"a\x00\x00\xd8b\x00";
+    match decode_as(bad, Encoding::Utf16Le, ErrorPolicy::Strict) {
+        Err(Error::InvalidEncoding(Encoding::Utf16Le, 2)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+    let d = unwrap!( decode_as(bad, Encoding::Utf16Le, ErrorPolicy::Replace).ok() );
+    assert_eq!(d.text, "a\u{fffd}b");
+}
+
+#[test]
+fn test_decode_8bit() {
+    let d = unwrap!( decode(b"na\xefve caf\xe9", ErrorPolicy::Strict).ok() );
+    assert_eq!((d.encoding, d.text.as_ref()), (Encoding::Latin1, "na\u{ef}ve caf\u{e9}"));
+    let d = unwrap!( decode(b"\x93Hi\x94 \x97 \x80", ErrorPolicy::Strict).ok() );
+    assert_eq!((d.encoding, d.text.as_ref()), (Encoding::Windows1252, "\u{201c}Hi\u{201d} \u{2014} \u{20ac}"));
+}
+
+#[test]
+fn test_decode_stray_byte() {
+    let mut bytes = "caf\u{e9} ".repeat(10).into_bytes();
+    bytes.push(0xe9);
+    let d = unwrap!( decode(&bytes, ErrorPolicy::Replace).ok() );
+    assert_eq!(d.encoding, Encoding::Utf8);
+    assert!(d.text.ends_with("caf\u{e9} \u{fffd}"));
+    let d = unwrap!( decode(&bytes, ErrorPolicy::Strict).ok() );
+    assert_eq!(d.encoding, Encoding::Latin1);
+    assert!(d.text.ends_with("caf\u{c3}\u{a9} \u{e9}"));
+}
+
+#[test]
+fn test_decode_utf8_lossy() {
+    let mut bytes = b"ab?c".to_vec();
+    bytes[2] = 0xff;
+    match decode_as(&bytes, Encoding::Utf8, ErrorPolicy::Strict) {
+        Err(Error::InvalidUtf8(2)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+    let d = unwrap!( decode_as(&bytes, Encoding::Utf8, ErrorPolicy::Replace).ok() );
+    assert_eq!(d.text, "ab\u{fffd}c");
+}
@@ -0,0 +1,95 @@
+use std;
+use std::fmt;
+use std::io;
+
+use encoding::Encoding;
+
+/// Failures reported by the library.
+#[derive(Debug)]
+pub enum Error {
+    /// The named file could not be opened.
+    Open(String, io::Error),
+    /// The size of an open file could not be determined.
+    Stat(io::Error),
+    /// An open file could not be mapped into memory.
+    Mmap(io::Error),
+    /// An input stream could not be read.
+    Read(io::Error),
+    /// An output stream could not be written.
+    Write(io::Error),
+    /// The input was not valid UTF-8; the value is the byte offset of the
+    /// first invalid sequence.
+    InvalidUtf8(usize),
+    /// The input was not valid in the given encoding; the value is the byte
+    /// offset of the first invalid sequence.
+    InvalidEncoding(Encoding, usize),
+    /// The input contained nothing to work with.
+    EmptyInput,
+    /// A regular expression could not be compiled.
+    InvalidPattern(String),
+    /// A program was given an option it cannot use, such as an unknown
+    /// stemmer.
+    InvalidOption(String),
+    /// Training data did not contain what the trainer needs.
+    MalformedTraining(String),
+    /// A word list, such as a table of irregular forms, could not be read.
+    MalformedLexicon(String),
+    /// A file of standoff annotations could not be read.
+    MalformedAnnotations(String),
+    /// A binary file, such as a tokenized corpus, was damaged or written by
+    /// an unknown version.
+    InvalidFormat(String),
+}
+
+pub type Result<T> = std::result::Result<T,Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Open(ref filename, ref e) => write!(f, "cannot open {}: {}", filename, e),
+            Error::Stat(ref e)               => write!(f, "cannot stat file: {}", e),
+            Error::Mmap(ref e)               => write!(f, "cannot mmap file: {}", e),
+            Error::Read(ref e)               => write!(f, "cannot read input: {}", e),
+            Error::Write(ref e)              => write!(f, "cannot write output: {}", e),
+            Error::InvalidUtf8(offset)       => write!(f, "invalid UTF-8 at byte {}", offset),
+            Error::InvalidEncoding(enc, off) => write!(f, "invalid {} at byte {}", enc, off),
+            Error::EmptyInput                => write!(f, "empty input"),
+            Error::InvalidPattern(ref m)     => write!(f, "invalid pattern: {}", m),
+            Error::InvalidOption(ref m)      => write!(f, "invalid option: {}", m),
+            Error::MalformedTraining(ref m)  => write!(f, "malformed training data: {}", m),
+            Error::MalformedLexicon(ref m)   => write!(f, "malformed word list: {}", m),
+            Error::MalformedAnnotations(ref m) => write!(f, "malformed annotations: {}", m),
+            Error::InvalidFormat(ref m)      => write!(f, "invalid data file: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Open(_, ref e) | Error::Stat(ref e) | Error::Mmap(ref e)
+                | Error::Read(ref e) | Error::Write(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Error { Error::InvalidUtf8(e.valid_up_to()) }
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(Error::InvalidUtf8(12).to_string(), "invalid UTF-8 at byte 12");
+    assert_eq!(Error::EmptyInput.to_string(), "empty input");
+}
+
+#[test]
+fn test_from_utf8_error() {
+    let mut bytes = b"ab?c".to_vec();
+    bytes[2] = 0xff;
+    match std::str::from_utf8(&bytes).map_err(Error::from) {
+        Err(Error::InvalidUtf8(2)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
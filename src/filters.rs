@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use encoding::ErrorPolicy;
+use error::{Error,Result};
+use text_source;
+use word_sequence::CharClass;
+
+// Predicates on words, for the `is_word` argument of WordSequence and
+// CorpusSequence constructors. Each is an ordinary `Fn(&str) -> bool`, so
+// closures mix freely with them; `and`, `or` and `not` combine any of them,
+// and `parse` builds one from a description given at run time.
+
+/// The stopword list used by `Stopwords::english`.
+pub const ENGLISH_STOPWORDS: &str = include_str!("../data/english-stopwords.txt");
+
+/// A predicate chosen at run time.
+pub type BoxedFilter<'a> = Box<dyn Fn(&str) -> bool + Send + Sync + 'a>;
+
+/// Whether a word contains any alphabetic characters.
+pub fn any_alphabetic(s: &str) -> bool { s.chars().any(char::is_alphabetic) }
+
+/// Whether a word is made only of alphabetic characters.
+pub fn is_alphabetic(s: &str) -> bool { !s.is_empty() && s.chars().all(char::is_alphabetic) }
+
+/// Whether a word is a number: digits, possibly with commas or periods
+/// between them, as in 1,000 and 3.14.
+pub fn is_numeric(s: &str) -> bool {
+    s.chars().next().is_some_and(char::is_numeric)
+        && s.chars().next_back().is_some_and(char::is_numeric)
+        && s.chars().all(|c| c.is_numeric() || c == ',' || c == '.')
+}
+
+/// Whether a word is made only of punctuation and symbols.
+pub fn is_punctuation(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| CharClass::classify(c) == CharClass::Other)
+}
+
+/// A predicate for words of at least `n` characters.
+pub fn min_length(n: usize) -> impl Fn(&str) -> bool + Copy {
+    move |s| s.chars().count() >= n
+}
+
+/// A predicate for words satisfying both of two others.
+pub fn and<P,Q>(p: P, q: Q) -> impl Fn(&str) -> bool
+    where P: Fn(&str) -> bool,
+          Q: Fn(&str) -> bool {
+    move |s| p(s) && q(s)
+}
+
+/// A predicate for words satisfying either of two others.
+pub fn or<P,Q>(p: P, q: Q) -> impl Fn(&str) -> bool
+    where P: Fn(&str) -> bool,
+          Q: Fn(&str) -> bool {
+    move |s| p(s) || q(s)
+}
+
+/// A predicate for words not satisfying another.
+pub fn not<P>(p: P) -> impl Fn(&str) -> bool
+    where P: Fn(&str) -> bool {
+    move |s| !p(s)
+}
+
+#[test]
+fn test_predicates() {
+    assert!(any_alphabetic("x2") && !any_alphabetic("42"));
+    assert!(is_alphabetic("Café") && !is_alphabetic("x2") && !is_alphabetic(""));
+    assert!(is_numeric("1,000") && is_numeric("3.14") && is_numeric("7"));
+    assert!(!is_numeric("3.") && !is_numeric("x2") && !is_numeric(""));
+    assert!(is_punctuation("--") && is_punctuation("$") && !is_punctuation("a.") && !is_punctuation(""));
+    assert!(min_length(3)("née") && !min_length(3)("no"));
+    let p = and(is_alphabetic, not(min_length(4)));
+    assert!(p("cat") && !p("cats") && !p("c4t"));
+    let p = or(is_numeric, is_punctuation);
+    assert!(p("42") && p("!") && !p("a"));
+}
+
+// ----------------------------------------
+
+/// A list of words to leave out, such as function words, compared without
+/// regard to case.
+#[derive(Clone,Debug,Default)]
+pub struct Stopwords {
+    words: HashSet<String>,
+}
+
+impl Stopwords {
+    pub fn new() -> Stopwords { Stopwords::default() }
+
+    /// The built-in list of English function words.
+    pub fn english() -> Stopwords { Stopwords::parse(ENGLISH_STOPWORDS) }
+
+    /// The words in a file, as read by `parse`, decoded from whatever
+    /// encoding it appears to be in.
+    pub fn from_file(filename: &str) -> Result<Stopwords> {
+        let source = text_source::open(filename)?;
+        Ok( Stopwords::parse(&source.decode(ErrorPolicy::Strict)?.text) )
+    }
+
+    /// The words in a list, separated by whitespace. Lines starting with #
+    /// are ignored.
+    pub fn parse(list: &str) -> Stopwords {
+        let mut stopwords = Stopwords::new();
+        for line in list.lines().filter(|line| !line.trim_start().starts_with('#')) {
+            for word in line.split_whitespace() {
+                stopwords.insert(word);
+            }
+        }
+        stopwords
+    }
+
+    pub fn insert(&mut self, word: &str) { self.words.insert(word.to_lowercase()); }
+
+    pub fn contains(&self, word: &str) -> bool { self.words.contains(&word.to_lowercase()) }
+
+    pub fn len(&self) -> usize { self.words.len() }
+    pub fn is_empty(&self) -> bool { self.words.is_empty() }
+
+    /// A predicate for words not in the list.
+    pub fn filter(&self) -> impl Fn(&str) -> bool + Copy + Send + Sync + '_ {
+        move |s| !self.contains(s)
+    }
+}
+
+#[test]
+fn test_stopwords() {
+    let english = Stopwords::english();
+    assert!(english.contains("the") && english.contains("Of") && !english.contains("Tom"));
+    let stopwords = Stopwords::parse("# none of these\nthe a\n  an\n");
+    assert_eq!(stopwords.len(), 3);
+    let p = and(is_alphabetic, stopwords.filter());
+    assert!(p("cat") && !p("The"));
+}
+
+#[test]
+fn test_stopwords_from_file() {
+    let file = std::env::temp_dir().join("nl_foundations-stopwords.txt");
+    for bytes in &[&b"\xef\xbb\xbfthe\nd\xc3\xa9j\xc3\xa0\n"[..], &b"the\nd\xe9j\xe0\n"[..]] {
+        unwrap!( std::fs::write(&file, bytes).ok() );
+        let stopwords = unwrap!( Stopwords::from_file(&file.to_string_lossy()).ok() );
+        assert!(stopwords.contains("the") && stopwords.contains("d\u{e9}j\u{e0}"));
+    }
+    let _ = std::fs::remove_file(&file);
+}
+
+// ----------------------------------------
+
+/// Build a predicate from a description: filters separated by commas, all
+/// of which must hold, where each filter is one or more alternatives
+/// separated by |, each a name optionally preceded by ! to negate it. The
+/// names are `any`, `alphabetic`, `any-alphabetic`, `numeric`,
+/// `punctuation`, `stopword` (a word in `stopwords`) and `min-length=N`.
+/// For example, `alphabetic|numeric,!stopword,min-length=3`.
+pub fn parse<'a>(description: &str, stopwords: &'a Stopwords) -> Result<BoxedFilter<'a>> {
+    let mut filter: BoxedFilter<'a> = Box::new(|_| true);
+    for conjunct in description.split(',') {
+        let mut alternatives: Option<BoxedFilter<'a>> = None;
+        for alternative in conjunct.split('|') {
+            let p = named(alternative.trim(), stopwords)?;
+            alternatives = Some(match alternatives {
+                Some(q) => Box::new(or(q, p)),
+                None => p,
+            });
+        }
+        filter = Box::new(and(filter, unwrap!( alternatives )));
+    }
+    Ok(filter)
+}
+
+// The predicate for one name in a description, possibly negated.
+fn named<'a>(name: &str, stopwords: &'a Stopwords) -> Result<BoxedFilter<'a>> {
+    if let Some(name) = name.strip_prefix('!') {
+        return Ok(Box::new(not(named(name.trim_start(), stopwords)?)));
+    }
+    if let Some(n) = name.strip_prefix("min-length=") {
+        return match n.parse::<usize>() {
+            Ok(n) => Ok(Box::new(min_length(n))),
+            Err(_) => Err(Error::InvalidPattern(format!("bad length in filter {}", name))),
+        };
+    }
+    Ok(match name {
+        "any" => Box::new(|_| true),
+        "alphabetic" => Box::new(is_alphabetic),
+        "any-alphabetic" => Box::new(any_alphabetic),
+        "numeric" => Box::new(is_numeric),
+        "punctuation" => Box::new(is_punctuation),
+        "stopword" => Box::new(move |s| stopwords.contains(s)),
+        _ => return Err(Error::InvalidPattern(format!("unknown filter {:?}", name))),
+    })
+}
+
+#[test]
+fn test_parse() {
+    let stopwords = Stopwords::english();
+    let p = unwrap!( parse("alphabetic|numeric, !stopword,min-length=2", &stopwords).ok() );
+    assert!(p("cat") && p("42") && !p("the") && !p("x") && !p("--"));
+    let p = unwrap!( parse("!alphabetic", &stopwords).ok() );
+    assert!(p("42") && !p("cat"));
+    assert!(parse("alphabetic,", &stopwords).is_err());
+    assert!(parse("min-length=two", &stopwords).is_err());
+    let unknown = parse("vowels", &stopwords);
+    match unknown {
+        Err(Error::InvalidPattern(ref m)) if m.contains("vowels") => { }
+        _ => panic!("unknown filter accepted"),
+    }
+}
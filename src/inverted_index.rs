@@ -0,0 +1,335 @@
+use std::borrow::Cow;
+use std::cmp::{max,min};
+use std::io::Write;
+
+use binary::{aligned,checksum,invalid,put,u64_at,usizes,Aligned};
+use error::{Error,Result};
+use text_source::{self,TextSource};
+use vocabulary::UNKNOWN;
+use word_sequence::Word;
+
+// An inverted index on disk, in the form in which it is used, so that a
+// saved index can be mapped and queried at once. Every number is a
+// little-endian u64.
+//
+//   magic, version (u32), flags (u32)
+//   number of types, tokens, documents and postings
+//   checksum of the words indexed
+//   starts    [types + 1]  index of each type's first posting
+//   postings  [postings]   positions of each type, in order
+//   offsets   [docs + 1]   index of each document's first token
+
+/// The first bytes of an index file.
+pub const MAGIC: &[u8] = b"NLFIDX\0\0";
+/// The version of the format written by `write`.
+pub const VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 56;
+
+/// The positions at which each word occurs in a sequence of documents, for
+/// looking up words, phrases and words near each other without scanning
+/// the sequence. Tokens with the unknown id are not indexed.
+pub struct InvertedIndex {
+    source: Box<dyn TextSource>,
+    types: usize,
+    tokens: usize,
+    docs: usize,
+    postings: usize,
+}
+
+impl InvertedIndex {
+    /// Index a sequence of words, divided into documents by `offsets` (the
+    /// index of each document's first token, then the number of tokens, as
+    /// in a CorpusSequence).
+    pub fn new(words: &[Word], offsets: &[usize]) -> InvertedIndex {
+        assert!(offsets.first() == Some(&0) && offsets.last() == Some(&words.len()),
+                "offsets do not match the words");
+        let types = words.iter().filter(|&&w| w != UNKNOWN).max().map_or(0, |&w| w + 1);
+        let mut starts = vec!(0; types + 1);
+        for &w in words.iter().filter(|&&w| w != UNKNOWN) {
+            starts[w + 1] += 1;
+        }
+        for w in 0..types {
+            starts[w + 1] += starts[w];
+        }
+        let mut postings = vec!(0; starts[types]);
+        let mut next = starts.clone();
+        for (i,&w) in words.iter().enumerate().filter(|&(_,&w)| w != UNKNOWN) {
+            postings[next[w]] = i;
+            next[w] += 1;
+        }
+        let header = [
+            u64_at(MAGIC, 0), VERSION as u64,
+            types as u64, words.len() as u64, (offsets.len() - 1) as u64, postings.len() as u64,
+            checksum(words),
+        ];
+        let buf: Vec<u64> = header.iter().cloned()
+            .chain(starts.iter().chain(&postings).chain(offsets).map(|&n| n as u64))
+            .collect();
+        let len = 8 * buf.len();
+        InvertedIndex {
+            source: Box::new(Aligned { buf, len }),
+            types,
+            tokens: words.len(),
+            docs: offsets.len() - 1,
+            postings: postings.len(),
+        }
+    }
+
+    /// Open an index file, mapping it into memory if possible.
+    pub fn open(filename: &str) -> Result<InvertedIndex> {
+        InvertedIndex::from_source(text_source::open(filename)?)
+    }
+
+    /// Use the contents of a source as an index, after checking it is
+    /// consistent.
+    pub fn from_source(source: Box<dyn TextSource>) -> Result<InvertedIndex> {
+        let source = aligned(source)?;
+        let (types, tokens, docs, postings) = {
+            let bytes = source.get_slice();
+            if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+                return invalid("bad header");
+            }
+            let version = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+            if version != VERSION {
+                return Err(Error::InvalidFormat(format!("unsupported version {}", version)));
+            }
+            let field = |i: usize| u64_at(bytes, 16 + 8 * i);
+            if (0..4).any(|i| field(i) > bytes.len() as u64) {
+                return invalid("bad header");
+            }
+            let (types, tokens, docs, postings) = (field(0) as usize, field(1) as usize, field(2) as usize, field(3) as usize);
+            if HEADER_LEN + 8 * (types + 1 + postings + docs + 1) != bytes.len() {
+                return invalid("truncated file");
+            }
+            (types, tokens, docs, postings)
+        };
+        let index = InvertedIndex { source, types, tokens, docs, postings };
+        index.validate()?;
+        Ok(index)
+    }
+
+    // Check that postings are grouped in order and name tokens, and that the
+    // documents divide the tokens.
+    fn validate(&self) -> Result<()> {
+        let starts = self.starts();
+        if starts[0] != 0 || starts[self.types] != self.postings {
+            return invalid("bad posting offsets");
+        }
+        for w in 0..self.types {
+            if starts[w] > starts[w+1] {
+                return invalid("bad posting offsets");
+            }
+            let postings = self.postings(w);
+            if postings.windows(2).any(|p| p[0] >= p[1]) || postings.last().is_some_and(|&p| p >= self.tokens) {
+                return invalid("bad posting");
+            }
+        }
+        let offsets = self.offsets();
+        if offsets[0] != 0 || offsets[self.docs] != self.tokens || offsets.windows(2).any(|o| o[0] > o[1]) {
+            return invalid("bad document offsets");
+        }
+        Ok(())
+    }
+
+    /// Write the index, in the form read by `open`.
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        put(out, self.source.get_slice())?;
+        out.flush().map_err(Error::Write)
+    }
+
+    fn starts(&self) -> &[usize] { usizes(self.source.get_slice(), HEADER_LEN, self.types + 1) }
+
+    /// The number of documents.
+    pub fn len(&self) -> usize { self.docs }
+    pub fn is_empty(&self) -> bool { self.docs == 0 }
+
+    /// The number of tokens indexed, including any unknown ones.
+    pub fn tokens(&self) -> usize { self.tokens }
+
+    /// Whether the index was built from a sequence of words and documents.
+    pub fn matches(&self, words: &[Word], offsets: &[usize]) -> bool {
+        self.offsets() == offsets && u64_at(self.source.get_slice(), 48) == checksum(words)
+    }
+
+    /// The index of each document's first token; the last entry is the
+    /// total number of tokens.
+    pub fn offsets(&self) -> &[usize] {
+        usizes(self.source.get_slice(), HEADER_LEN + 8 * (self.types + 1 + self.postings), self.docs + 1)
+    }
+
+    /// The positions at which a word occurs, in order.
+    pub fn postings(&self, w: Word) -> &[usize] {
+        if w >= self.types { return &[]; }
+        let starts = self.starts();
+        let all = usizes(self.source.get_slice(), HEADER_LEN + 8 * (self.types + 1), self.postings);
+        &all[starts[w]..starts[w+1]]
+    }
+
+    /// The number of times a word occurs.
+    pub fn count(&self, w: Word) -> usize { self.postings(w).len() }
+
+    /// The document holding a token.
+    pub fn document_of(&self, position: usize) -> usize {
+        self.offsets().partition_point(|&o| o <= position) - 1
+    }
+
+    /// The positions at which a word occurs in a document.
+    pub fn document_postings(&self, w: Word, doc: usize) -> &[usize] {
+        let (start, end) = (self.offsets()[doc], self.offsets()[doc+1]);
+        let postings = self.postings(w);
+        let lower = postings.partition_point(|&p| p < start);
+        let upper = postings.partition_point(|&p| p < end);
+        &postings[lower..upper]
+    }
+
+    /// The documents in which a word occurs, in order.
+    pub fn documents(&self, w: Word) -> Vec<usize> {
+        let mut docs: Vec<usize> = Vec::new();
+        for &p in self.postings(w) {
+            if docs.last().is_none_or(|&d| p >= self.offsets()[d+1]) {
+                docs.push(self.document_of(p));
+            }
+        }
+        docs
+    }
+
+    /// The documents in which every one of some words occurs.
+    pub fn all(&self, words: &[Word]) -> Vec<usize> {
+        let mut words = words.to_vec();
+        words.sort_by_key(|&w| self.count(w));
+        match words.split_first() {
+            Some((&rarest, rest)) => {
+                let mut docs = self.documents(rarest);
+                docs.retain(|&d| rest.iter().all(|&w| !self.document_postings(w, d).is_empty()));
+                docs
+            }
+            None => vec!(),
+        }
+    }
+
+    /// The positions at which any of some words occurs, in order.
+    pub fn any_postings(&self, words: &[Word]) -> Cow<'_,[usize]> {
+        if let [w] = *words {
+            return Cow::Borrowed(self.postings(w));
+        }
+        let mut positions: Vec<usize> = words.iter().flat_map(|&w| self.postings(w).iter().cloned()).collect();
+        positions.sort_unstable();
+        positions.dedup();
+        Cow::Owned(positions)
+    }
+
+    /// The positions at which a phrase starts, in order. Phrases do not
+    /// cross document boundaries.
+    pub fn phrase(&self, words: &[Word]) -> Vec<usize> {
+        let alternatives: Vec<Vec<Word>> = words.iter().map(|&w| vec!(w)).collect();
+        self.phrase_any(&alternatives)
+    }
+
+    /// The positions at which a phrase starts, in order, where each word of
+    /// the phrase may be any of its alternatives, as the forms of a word
+    /// that differ only in case.
+    pub fn phrase_any(&self, alternatives: &[Vec<Word>]) -> Vec<usize> {
+        let postings: Vec<Cow<[usize]>> = alternatives.iter().map(|a| self.any_postings(a)).collect();
+        // Candidates come from the rarest word of the phrase.
+        let rarest = match (0..postings.len()).min_by_key(|&i| postings[i].len()) {
+            Some(rarest) => rarest,
+            None => return vec!(),
+        };
+        postings[rarest].iter()
+            .filter(|&&p| p >= rarest)
+            .map(|&p| p - rarest)
+            .filter(|&start| {
+                let end = start + postings.len();
+                end <= self.offsets()[self.document_of(start) + 1]
+                    && postings.iter().enumerate().all(|(i,ps)| ps.binary_search(&(start + i)).is_ok())
+            })
+            .collect()
+    }
+
+    /// Pairs of positions at which one word occurs within `distance` words
+    /// of another, before or after it, in the same document; in order. If
+    /// the words are the same, each pair comes once, earlier position first.
+    pub fn near(&self, first: Word, second: Word, distance: usize) -> Vec<(usize,usize)> {
+        self.near_any(&[first], &[second], distance)
+    }
+
+    /// Pairs of positions as for `near`, where each word may be any of its
+    /// alternatives. The same alternatives for both give each pair once.
+    pub fn near_any(&self, first: &[Word], second: &[Word], distance: usize) -> Vec<(usize,usize)> {
+        let seconds = self.any_postings(second);
+        let mut pairs = Vec::new();
+        for &p in self.any_postings(first).iter() {
+            let doc = self.document_of(p);
+            let start = max(p.saturating_sub(distance), self.offsets()[doc]);
+            let end = min(p + distance + 1, self.offsets()[doc+1]);
+            let lower = seconds.partition_point(|&q| q < start);
+            let upper = seconds.partition_point(|&q| q < end);
+            pairs.extend(seconds[lower..upper].iter().filter(|&&q| if first == second { q > p } else { q != p }).map(|&q| (p, q)));
+        }
+        pairs
+    }
+}
+
+#[test]
+fn test_inverted_index() {
+    // a b a c | b a b | c
+    let words = [0, 1, 0, 2, 1, 0, 1, 2];
+    let index = InvertedIndex::new(&words, &[0, 4, 7, 8]);
+    assert_eq!(index.len(), 3);
+    assert_eq!(index.postings(0), &[0, 2, 5]);
+    assert_eq!(index.postings(3), &[] as &[usize]);
+    assert_eq!(index.document_of(4), 1);
+    assert_eq!(index.document_postings(1, 1), &[4, 6]);
+    assert_eq!(index.documents(2), vec!(0, 2));
+    assert_eq!(index.all(&[0, 1]), vec!(0, 1));
+    assert_eq!(index.all(&[0, 2]), vec!(0));
+    assert_eq!(index.phrase(&[0, 1]), vec!(0, 5));
+    assert_eq!(index.phrase(&[1, 0]), vec!(1, 4));
+    // c b crosses from the first document into the second.
+    assert_eq!(index.phrase(&[2, 1]), vec!());
+    assert_eq!(index.near(2, 1, 2), vec!((3, 1)));
+    assert_eq!(index.near(0, 1, 1), vec!((0, 1), (2, 1), (5, 4), (5, 6)));
+    assert_eq!(index.near(0, 0, 2), vec!((0, 2)));
+    assert_eq!(index.near(1, 1, 9), vec!((4, 6)));
+    // a|c b, as for the forms of a word.
+    assert_eq!(&index.any_postings(&[0, 2])[..], &[0, 2, 3, 5, 7]);
+    assert_eq!(index.phrase_any(&[vec!(0, 2), vec!(1)]), vec!(0, 5));
+    assert_eq!(index.phrase_any(&[vec!(1), vec!(0, 2)]), vec!(1, 4));
+    assert_eq!(index.phrase_any(&[vec!(0, 2), vec!()]), vec!());
+    assert_eq!(index.near_any(&[0, 2], &[0, 2], 1), vec!((2, 3)));
+}
+
+#[test]
+fn test_against_suffix_array() {
+    use suffix_array::SuffixArray;
+    use word_sequence::WordSequence;
+    let corpus = unwrap!( ::corpus::Corpus::from_paths(&["data/training-1.txt"]).ok() );
+    let ws = WordSequence::new(&corpus.documents[0].text, |s| s, |_| true);
+    let index = InvertedIndex::new(&ws.words, &[0, ws.words.len()]);
+    let sa = SuffixArray::new(&ws.words);
+    for ngram in ws.words.windows(3).step_by(89) {
+        assert_eq!(index.phrase(ngram), sa.positions(ngram));
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let words = [3, 1, 4, 1, 5, UNKNOWN, 2, 6, 5, 3, 5];
+    let index = InvertedIndex::new(&words, &[0, 5, 11]);
+    let mut bytes = Vec::new();
+    unwrap!( index.write(&mut bytes).ok() );
+    let loaded = unwrap!( InvertedIndex::from_source(Box::new(bytes.clone())).ok() );
+    assert_eq!(loaded.offsets(), &[0, 5, 11]);
+    assert_eq!(loaded.tokens(), 11);
+    assert!(loaded.matches(&words, &[0, 5, 11]));
+    assert!(!loaded.matches(&words, &[0, 6, 11]));
+    let mut other = words;
+    other.swap(0, 1);
+    assert!(!loaded.matches(&other, &[0, 5, 11]));
+    for w in 0..8 {
+        assert_eq!(loaded.postings(w), index.postings(w));
+    }
+    bytes[HEADER_LEN] = 1;
+    assert!(InvertedIndex::from_source(Box::new(bytes)).is_err());
+}
@@ -1,15 +1,41 @@
 extern crate libc;
 extern crate getopts;
+extern crate regex;
+extern crate unicode_segmentation;
+extern crate unicode_normalization;
+extern crate caseless;
 
 #[macro_use] mod macros;
+mod binary;
 
+pub mod error;
+pub mod encoding;
 pub mod mmap;
+pub mod text_source;
 pub mod word_sequence;
+pub mod vocabulary;
+pub mod tokenizer;
+pub mod ptb;
+pub mod corpus;
+pub mod tokenized;
+pub mod stream;
+pub mod parallel;
 pub mod case_string;
+pub mod stemmer;
+pub mod morphology;
+pub mod filters;
+pub mod preprocess;
+pub mod structure;
+pub mod annotation;
 pub mod sample;
+pub mod suffix_array;
+pub mod inverted_index;
+pub mod options;
 
 pub mod  bayesian_classification;
 
+pub use error::{Error,Result};
+
 #[test]
 fn it_works() {
 }
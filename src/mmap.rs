@@ -2,6 +2,8 @@ use std;
 
 use libc;
 
+use error::{Error,Result};
+
 // =====================================
 
 // A file descriptor, open for reading.
@@ -17,16 +19,17 @@ impl Drop for FileDescriptor {
 }
 
 impl FileDescriptor {
-    unsafe fn open(filename: &str) -> Result<FileDescriptor,String> {
+    unsafe fn open(filename: &str) -> Result<FileDescriptor> {
         if let Ok(file) = std::ffi::CString::new(filename) {
             let fd = libc::open(file.as_ptr(), libc::O_RDONLY, 0);
             if fd >= 0 {
                 Ok( FileDescriptor(fd) )
             } else {
-                Err( format!("failure in open({}): {}", filename, std::io::Error::last_os_error()) )
+                Err( Error::Open(filename.to_string(), std::io::Error::last_os_error()) )
             }
         } else {
-            Err( format!("cannot convert filename to CString: {}", filename) )
+            let e = std::io::Error::new(std::io::ErrorKind::InvalidInput, "file name contains a NUL byte");
+            Err( Error::Open(filename.to_string(), e) )
         }
     }
 }
@@ -36,12 +39,13 @@ fn test_open_success() {
     unsafe {
         match FileDescriptor::open("Cargo.toml") {
             Ok(_) => { },
-            Err(e) => { panic!(e); }
+            Err(e) => { panic!("{}", e); }
         }
     }
 }
 
 #[test]
+#[allow(clippy::single_match)]
 fn test_open_failure() {
     unsafe {
         match FileDescriptor::open("nonexistent") {
@@ -54,11 +58,11 @@ fn test_open_failure() {
 // -------------------------------------
 
 impl FileDescriptor {
-    unsafe fn get_size(&self) -> Result<libc::size_t,String> {
+    unsafe fn get_size(&self) -> Result<libc::size_t> {
         let FileDescriptor(fd) = *self;
         let mut stat: libc::stat = std::mem::zeroed();
         if libc::fstat(fd, &mut stat) < 0 {
-            Err( format!("failure in fstat(): {}", std::io::Error::last_os_error()) )
+            Err( Error::Stat(std::io::Error::last_os_error()) )
         } else {
             Ok( stat.st_size as libc::size_t )
         }
@@ -74,9 +78,9 @@ fn test_get_size() {
         if let Ok(m) = std::fs::metadata("Cargo.toml") {
             let res = FileDescriptor::open("Cargo.toml")
                 .and_then(|fd| { fd.get_size() })
-                .and_then(|sz| { if sz == m.size() as usize { Ok(true) } else { Err(format!("{} != 149", sz)) }});
-            if res.is_err() {
-                panic!(res.unwrap_err());
+                .map(|sz| { assert_eq!(sz, m.size() as usize); });
+            if let Err(e) = res {
+                panic!("{}", e);
             }
         } else {
             panic!("cannot get metadata for Cargo.toml");
@@ -106,7 +110,9 @@ impl Drop for MappedRegion {
 
 impl MappedRegion {
 
-    pub fn mmap(filename: &str) -> Result<MappedRegion,String> {
+    /// Map the named file into memory, read-only. An empty file cannot be
+    /// mapped and is reported as `Error::EmptyInput`.
+    pub fn mmap(filename: &str) -> Result<MappedRegion> {
         unsafe {
             match FileDescriptor::open(filename) {
                 Ok(fd) => map(fd),
@@ -117,28 +123,30 @@ impl MappedRegion {
 
     pub fn get_slice(&self) -> &[u8] {
         unsafe {
-            std::slice::from_raw_parts(self.ptr, self.sz as usize)
+            std::slice::from_raw_parts(self.ptr, self.sz)
         }
     }
 
-    pub fn get_str<'s>(&'s self) -> Result<&'s str,String> {
-        std::str::from_utf8(self.get_slice()).map_err(|e| { format!("{}", e) })
+    /// The contents of the region, if they are valid UTF-8.
+    pub fn get_str(&self) -> Result<&str> {
+        std::str::from_utf8(self.get_slice()).map_err(Error::from)
     }
 }
 
 // -------------------------------------
 
-unsafe fn map(fd: FileDescriptor) -> Result<MappedRegion,String> {
+unsafe fn map(fd: FileDescriptor) -> Result<MappedRegion> {
     match fd.get_size() {
+        Ok(0) => { Err(Error::EmptyInput) }
         Ok(sz) => {
-            let address = libc::mmap(0 as *mut libc::c_void, sz, libc::PROT_READ, libc::MAP_PRIVATE, *fd.get_fd(), 0);
-            if address < 0 as *mut libc::c_void {
-                Err( format!("failure in mmap(): {}", std::io::Error::last_os_error()) )
+            let address = libc::mmap(std::ptr::null_mut(), sz, libc::PROT_READ, libc::MAP_PRIVATE, *fd.get_fd(), 0);
+            if address == libc::MAP_FAILED {
+                Err( Error::Mmap(std::io::Error::last_os_error()) )
             } else {
                 Ok( MappedRegion {
                     _fd: fd,
                     ptr: address as *mut u8,
-                    sz: sz,
+                    sz,
                 })
             }
         }
@@ -155,14 +163,23 @@ fn test_mmap() {
                 assert_eq!(mr.get_slice().len(), m.size() as usize);
                 match mr.get_str() {
                     Ok(s) => {
-                        assert_eq!( unwrap!( s.lines().nth(0) ), "[package]");
+                        assert_eq!( unwrap!( s.lines().next() ), "[package]");
                     }
-                    Err(e) => { panic!(e); }
+                    Err(e) => { panic!("{}", e); }
                 }
             }
-            Err(e) => { panic!(e); }
+            Err(e) => { panic!("{}", e); }
         }
     } else {
         panic!("cannot get metadata for Cargo.toml");
     }
 }
+
+#[test]
+fn test_mmap_nonexistent() {
+    match MappedRegion::mmap("nonexistent") {
+        Err(Error::Open(_, _)) => { }
+        Err(e) => { panic!("unexpected error: {}", e); }
+        Ok(_) => { panic!("mmap of nonexistent file succeeded"); }
+    }
+}
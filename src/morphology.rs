@@ -0,0 +1,429 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+
+use case_string::AsStr;
+use encoding::ErrorPolicy;
+use error::{Error,Result};
+use text_source;
+
+// The analyzer looks a word up in a table of irregular forms, and failing
+// that takes off the English inflectional endings: -s and -es, -ed, -ing,
+// and -er and -est, undoing the spelling changes they bring (cried, stopped,
+// hoped). Without a dictionary of lemmas the rules can only guess, so every
+// guess is offered as a candidate, most likely first; the table is where
+// their mistakes are corrected.
+
+/// The table of irregular English forms used by `Analyzer::english`.
+pub const ENGLISH_IRREGULAR: &str = include_str!("../data/english-irregular.txt");
+
+/// An inflection of a word.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum Feature {
+    /// A plural noun: spectacles.
+    Plural,
+    /// A verb in the third person singular present: looks.
+    ThirdSingular,
+    /// A verb in the past tense: looked, went.
+    Past,
+    /// A past participle: looked, gone.
+    PastParticiple,
+    /// A present participle: looking.
+    PresentParticiple,
+    /// A comparative adjective: taller.
+    Comparative,
+    /// A superlative adjective: tallest.
+    Superlative,
+}
+
+impl Feature {
+    /// The feature with a name as displayed: PL, 3SG, PAST, PASTPART,
+    /// PRESPART, COMP or SUP.
+    pub fn named(name: &str) -> Option<Feature> {
+        match name {
+            "PL" => Some(Feature::Plural),
+            "3SG" => Some(Feature::ThirdSingular),
+            "PAST" => Some(Feature::Past),
+            "PASTPART" => Some(Feature::PastParticiple),
+            "PRESPART" => Some(Feature::PresentParticiple),
+            "COMP" => Some(Feature::Comparative),
+            "SUP" => Some(Feature::Superlative),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Plural => "PL",
+            Feature::ThirdSingular => "3SG",
+            Feature::Past => "PAST",
+            Feature::PastParticiple => "PASTPART",
+            Feature::PresentParticiple => "PRESPART",
+            Feature::Comparative => "COMP",
+            Feature::Superlative => "SUP",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One reading of a word: its lemma and the inflection, if any, that makes
+/// the word from it. Displayed as `look+PAST`.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct Analysis {
+    pub lemma: String,
+    pub feature: Option<Feature>,
+}
+
+impl Analysis {
+    pub fn new(lemma: &str, feature: Option<Feature>) -> Analysis {
+        Analysis { lemma: lemma.to_string(), feature }
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.feature {
+            Some(feature) => write!(f, "{}+{}", self.lemma, feature),
+            None => write!(f, "{}", self.lemma),
+        }
+    }
+}
+
+/// A rule-based analyzer of English inflection, with a table of irregular
+/// forms. Words are lower-cased, and so are lemmas.
+#[derive(Clone,Debug,Default)]
+pub struct Analyzer {
+    irregular: HashMap<String,Vec<Analysis>>,
+}
+
+impl Analyzer {
+    /// An analyzer with no irregular forms.
+    pub fn new() -> Analyzer { Analyzer::default() }
+
+    /// An analyzer with the built-in table of irregular English forms.
+    pub fn english() -> Analyzer {
+        unwrap!( Analyzer::parse(ENGLISH_IRREGULAR).ok() )
+    }
+
+    /// An analyzer with the irregular forms in a file, decoded from
+    /// whatever encoding it appears to be in.
+    pub fn from_file(filename: &str) -> Result<Analyzer> {
+        let source = text_source::open(filename)?;
+        Analyzer::parse(&source.decode(ErrorPolicy::Strict)?.text)
+    }
+
+    /// An analyzer with the irregular forms in a table: one form per line,
+    /// followed by its lemma and its features by name, all separated by
+    /// whitespace. A form with no features is a base form, left alone by
+    /// the suffix rules. Blank lines and lines starting with # are ignored.
+    pub fn parse(table: &str) -> Result<Analyzer> {
+        let mut analyzer = Analyzer::new();
+        for (n,line) in table.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let form = match fields.next() {
+                Some(form) if !form.starts_with('#') => form,
+                _ => continue,
+            };
+            let lemma = match fields.next() {
+                Some(lemma) => lemma,
+                None => return Err(Error::MalformedLexicon(format!("line {}: no lemma for {}", n + 1, form))),
+            };
+            let features: Vec<&str> = fields.collect();
+            if features.is_empty() {
+                analyzer.add(form, lemma, None);
+            }
+            for name in features {
+                match Feature::named(name) {
+                    Some(feature) => analyzer.add(form, lemma, Some(feature)),
+                    None => return Err(Error::MalformedLexicon(format!("line {}: unknown feature {}", n + 1, name))),
+                }
+            }
+        }
+        Ok(analyzer)
+    }
+
+    /// Add an irregular form, which will be analyzed only by the table.
+    pub fn add(&mut self, form: &str, lemma: &str, feature: Option<Feature>) {
+        let analysis = Analysis::new(&lemma.to_lowercase(), feature);
+        let analyses = self.irregular.entry(form.to_lowercase()).or_default();
+        if !analyses.contains(&analysis) {
+            analyses.push(analysis);
+        }
+    }
+
+    /// The candidate analyses of a word, most likely first. There is always
+    /// at least one; a word the rules do not apply to is its own lemma.
+    pub fn analyze(&self, word: &str) -> Vec<Analysis> {
+        let word = word.to_lowercase();
+        if let Some(analyses) = self.irregular.get(&word) {
+            return analyses.clone();
+        }
+        let mut analyses = Vec::new();
+        if word.bytes().all(|b| b.is_ascii_lowercase()) {
+            inflections(&word, &mut analyses);
+        }
+        let base = Analysis::new(&word, None);
+        if word.ends_with("er") || word.ends_with("est") {
+            // Far more words end in -er and -est than are comparatives.
+            analyses.insert(0, base);
+        } else {
+            analyses.push(base);
+        }
+        analyses
+    }
+
+    /// The most likely lemma of a word.
+    pub fn lemma(&self, word: &str) -> String {
+        let mut analyses = self.analyze(word);
+        analyses.swap_remove(0).lemma
+    }
+
+    /// A function making LemmaStrs with this analyzer, for use as the `to_t`
+    /// of a WordSequence.
+    pub fn key(&self) -> impl for<'s> Fn(&'s str) -> LemmaStr<'s> + Copy + Send + Sync + '_ {
+        move |s| LemmaStr::new(s, self)
+    }
+}
+
+// The analyses of a lower-case ASCII word by the suffix rules.
+fn inflections(word: &str, analyses: &mut Vec<Analysis>) {
+    let mut add = |lemma: String, features: &[Feature]| {
+        for &feature in features {
+            analyses.push(Analysis { lemma: lemma.clone(), feature: Some(feature) });
+        }
+    };
+    if let Some(lemma) = s_lemma(word) {
+        add(lemma, &[Feature::Plural, Feature::ThirdSingular]);
+    } else if let Some(stem) = word.strip_suffix("ed") {
+        if let Some(lemma) = ed_lemma(word, stem) {
+            add(lemma, &[Feature::Past, Feature::PastParticiple]);
+        }
+    } else if let Some(stem) = word.strip_suffix("ing") {
+        if has_vowel(stem) {
+            add(restore(stem), &[Feature::PresentParticiple]);
+        }
+    } else if let Some(stem) = word.strip_suffix("er") {
+        if let Some(lemma) = comparative_lemma(stem) {
+            add(lemma, &[Feature::Comparative]);
+        }
+    } else if let Some(stem) = word.strip_suffix("est") {
+        if let Some(lemma) = comparative_lemma(stem) {
+            add(lemma, &[Feature::Superlative]);
+        }
+    }
+}
+
+// The lemma of a plural or third person singular ending in -s. Words in
+// -as are mostly plurals (ideas), and those that are not (bias, atlas) are
+// listed as base forms.
+fn s_lemma(word: &str) -> Option<String> {
+    let n = word.len();
+    if n < 4 || !word.ends_with('s') || ["ss", "us", "is"].iter().any(|s| word.ends_with(s)) {
+        return None;
+    }
+    if word.ends_with("ies") {
+        // ties, but flies.
+        return Some(if n == 4 { format!("{}ie", &word[..1]) } else { format!("{}y", &word[..n-3]) });
+    }
+    if ["sses", "shes", "ches", "xes", "zzes"].iter().any(|s| word.ends_with(s)) {
+        return Some(word[..n-2].to_string());
+    }
+    Some(word[..n-1].to_string())
+}
+
+// The lemma of a past tense or participle ending in -ed.
+fn ed_lemma(word: &str, stem: &str) -> Option<String> {
+    // Words in -eed (need, speed) are mostly not past tenses.
+    if !has_vowel(stem) || word.ends_with("eed") {
+        return None;
+    }
+    if let Some(stem) = word.strip_suffix("ied") {
+        // tied, but cried.
+        return Some(if stem.len() == 1 { format!("{}ie", stem) } else { format!("{}y", stem) });
+    }
+    Some(restore(stem))
+}
+
+// The lemma of a comparative or superlative without its ending.
+fn comparative_lemma(stem: &str) -> Option<String> {
+    if !has_vowel(stem) || stem.len() < 2 {
+        return None;
+    }
+    Some(match stem.strip_suffix('i') {
+        Some(stem) => format!("{}y", stem),
+        None => restore(stem),
+    })
+}
+
+// Undo the spelling changes made to a stem by a suffix starting with a
+// vowel: undouble its final consonant (stopp, but add) or restore a silent
+// e (hop, lov, troubl).
+fn restore(stem: &str) -> String {
+    let b = stem.as_bytes();
+    let n = b.len();
+    if n >= 3 && b[n-1] == b[n-2] && !is_vowel(b, n-1) && !b"lsz".contains(&b[n-1]) && !(n == 3 && is_vowel(b, 0)) {
+        return stem[..n-1].to_string();
+    }
+    if needs_e(b) { format!("{}e", stem) } else { stem.to_string() }
+}
+
+// Whether a stem lost a silent e to its suffix.
+fn needs_e(b: &[u8]) -> bool {
+    let n = b.len();
+    let last = b[n-1];
+    if b"cuv".contains(&last) || (last == b's' && n >= 2 && b[n-2] != b's') {
+        return true;
+    }
+    if n >= 3 {
+        let pair = &b[n-2..];
+        // related, troubled, realized, judged, merged.
+        if (pair == b"at" && !is_vowel(b, n-3))
+            || (last == b'l' && !is_vowel(b, n-2) && !b"lrw".contains(&b[n-2]))
+            || pair == b"iz" || pair == b"yz" || pair == b"dg" || pair == b"rg" {
+            return true;
+        }
+    }
+    short(b)
+}
+
+// Whether a stem is a single short syllable: a non-vowel, a vowel and a
+// non-vowel other than w, x or y, as in hop and smil, or a vowel and a
+// non-vowel, as in us.
+fn short(b: &[u8]) -> bool {
+    let n = b.len();
+    let cvc = n >= 3 && !is_vowel(b, n-3) && is_vowel(b, n-2) && !is_vowel(b, n-1) && !b"wxy".contains(&b[n-1]);
+    let vc = n == 2 && is_vowel(b, 0) && !is_vowel(b, 1);
+    vc || (cvc && (0..n-3).all(|i| !is_vowel(b, i)))
+}
+
+// Whether the ith letter is a vowel; y is one after a consonant.
+fn is_vowel(b: &[u8], i: usize) -> bool {
+    match b[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => true,
+        b'y' => i > 0 && !is_vowel(b, i - 1),
+        _ => false,
+    }
+}
+
+fn has_vowel(stem: &str) -> bool {
+    let b = stem.as_bytes();
+    (0..b.len()).any(|i| is_vowel(b, i))
+}
+
+#[test]
+fn test_analyze() {
+    let analyzer = Analyzer::english();
+    let first = |word: &str| analyzer.analyze(word)[0].to_string();
+    assert_eq!(first("looked"), "look+PAST");
+    assert_eq!(first("spectacles"), "spectacle+PL");
+    assert_eq!(first("went"), "go+PAST");
+    assert_eq!(analyzer.analyze("looked"), vec!(
+        Analysis::new("look", Some(Feature::Past)),
+        Analysis::new("look", Some(Feature::PastParticiple)),
+        Analysis::new("looked", None)));
+    assert_eq!(analyzer.analyze("Left").len(), 2);
+    assert_eq!(first("taller"), "taller");
+    assert_eq!(analyzer.analyze("taller")[1].to_string(), "tall+COMP");
+    assert_eq!(analyzer.analyze("happiest")[1].to_string(), "happy+SUP");
+    assert_eq!(first("Tom"), "tom");
+    assert_eq!(first("naïve"), "naïve");
+    for &(word, lemma) in &[
+        ("flies", "fly"), ("ties", "tie"), ("boxes", "box"), ("horses", "horse"), ("glass", "glass"),
+        ("cried", "cry"), ("tied", "tie"), ("stopped", "stop"), ("added", "add"), ("called", "call"),
+        ("hoped", "hope"), ("opened", "open"), ("loved", "love"), ("related", "relate"), ("heated", "heat"),
+        ("troubled", "trouble"), ("curled", "curl"), ("realized", "realize"), ("used", "use"), ("fixed", "fix"),
+        ("played", "play"), ("noticed", "notice"), ("judged", "judge"), ("need", "need"), ("bed", "bed"),
+        ("running", "run"), ("making", "make"), ("writing", "write"), ("looking", "look"), ("sing", "sing"),
+        ("children", "child"), ("was", "be"), ("nothing", "nothing"), ("better", "good"),
+        ("bias", "bias"), ("alias", "alias"), ("atlas", "atlas"), ("canvas", "canvas"), ("ideas", "idea"),
+        ("biases", "bias"), ("atlases", "atlas"), ("famous", "famous"), ("nervous", "nervous"),
+    ] {
+        assert_eq!(analyzer.lemma(word), lemma, "{}", word);
+    }
+}
+
+#[test]
+fn test_parse() {
+    let analyzer = unwrap!( Analyzer::parse("# test\n\nkine cow PL\nsaw see PAST\nsaw saw\n").ok() );
+    assert_eq!(analyzer.lemma("kine"), "cow");
+    assert_eq!(analyzer.analyze("saw"), vec!(Analysis::new("see", Some(Feature::Past)), Analysis::new("saw", None)));
+    assert_eq!(analyzer.lemma("went"), "went");
+    match Analyzer::parse("went go PAST\nkine cow PLURAL\n") {
+        Err(Error::MalformedLexicon(ref m)) if m.starts_with("line 2") => { }
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+    assert!(Analyzer::parse("went\n").is_err());
+}
+
+#[test]
+fn test_from_file() {
+    let file = std::env::temp_dir().join("nl_foundations-lexicon.txt");
+    for bytes in &[&b"\xef\xbb\xbfkine cow PL\nk\xc3\xbche kuh PL\n"[..], &b"kine cow PL\nk\xfche kuh PL\n"[..]] {
+        unwrap!( std::fs::write(&file, bytes).ok() );
+        let analyzer = unwrap!( Analyzer::from_file(&file.to_string_lossy()).ok() );
+        assert_eq!(analyzer.lemma("kine"), "cow");
+        assert_eq!(analyzer.lemma("k\u{fc}he"), "kuh");
+    }
+    let _ = std::fs::remove_file(&file);
+}
+
+// ----------------------------------------
+
+/// A word compared by its lemma, keeping the original text. As the T of a
+/// WordSequence, it groups the inflections of a word, regular or not.
+#[derive(Debug,Clone)]
+pub struct LemmaStr<'s>{ s: &'s str, lemma: String }
+
+impl<'s> LemmaStr<'s> {
+    pub fn new(s: &'s str, analyzer: &Analyzer) -> LemmaStr<'s> {
+        LemmaStr{ s, lemma: analyzer.lemma(s) }
+    }
+
+    pub fn lemma(&self) -> &str { &self.lemma }
+}
+
+impl<'s> fmt::Display for LemmaStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.lemma)
+    }
+}
+
+impl<'s> PartialEq for LemmaStr<'s> {
+    fn eq(&self, other: &Self) -> bool { self.lemma == other.lemma }
+}
+
+impl<'s> Eq for LemmaStr<'s> { }
+
+impl<'s> PartialOrd for LemmaStr<'s> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<'s> Ord for LemmaStr<'s> {
+    fn cmp(&self, other: &Self) -> Ordering { self.lemma.cmp(&other.lemma) }
+}
+
+impl<'s> Hash for LemmaStr<'s> {
+    fn hash<H:Hasher>(&self, state: &mut H) { self.lemma.hash(state); }
+}
+
+impl<'s> AsStr for LemmaStr<'s> {
+    fn as_str(&self) -> &str { self.s }
+}
+
+#[test]
+fn test_lemma_str() {
+    use word_sequence::WordSequence;
+    let analyzer = Analyzer::english();
+    let text = "He goes; she went. They looked and look.";
+    let ws = WordSequence::new(text, analyzer.key(), |s| s.chars().all(char::is_alphabetic));
+    assert_eq!(ws.words, vec!(0, 1, 2, 1, 3, 4, 5, 4));
+    assert_eq!(ws[1].lemma(), "go");
+    assert_eq!(ws[4].as_str(), "looked");
+    assert_eq!(ws.surface_forms(|_| text).forms(1).len(), 2);
+}
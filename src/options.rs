@@ -0,0 +1,128 @@
+use std;
+
+use getopts::{Matches,Options};
+
+use encoding::{Encoding,ErrorPolicy};
+use error::{Error,Result};
+use filters::{self,BoxedFilter,Stopwords};
+use stemmer::Stemmer;
+
+// Options shared by the programs in src/bin, choosing how they read text,
+// with `--encoding NAME` and `--lossy`, and the words they work with, with
+// `--stem` and `--stemmer NAME`, `--stopwords FILE`, and an option
+// describing a filter. Each program registers the options with its own
+// help text, and parses them here.
+
+/// How to decode text files: from the encoding given by `--encoding`, or
+/// else the one they appear to be in, and with `--lossy`, replacing invalid
+/// bytes rather than failing.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Decoding {
+    pub encoding: Option<Encoding>,
+    pub policy: ErrorPolicy,
+}
+
+/// The decoding chosen by `--encoding` and `--lossy`.
+pub fn decoding(matches: &Matches) -> Result<Decoding> {
+    let encoding = match matches.opt_str("encoding") {
+        Some(name) => match Encoding::named(&name) {
+            Some(encoding) => Some(encoding),
+            None => return Err(Error::InvalidOption(format!("unknown encoding {}", name))),
+        },
+        None => None,
+    };
+    let policy = if matches.opt_present("lossy") { ErrorPolicy::Replace } else { ErrorPolicy::Strict };
+    Ok(Decoding { encoding, policy })
+}
+
+/// The stemmer named by `--stemmer`, or porter2 with `--stem`, or none.
+pub fn stemmer(matches: &Matches) -> Result<Option<Stemmer>> {
+    match matches.opt_str("stemmer").or_else(|| matches.opt_present("stem").then(|| "porter2".to_string())) {
+        Some(name) => match Stemmer::named(&name) {
+            Some(stemmer) => Ok(Some(stemmer)),
+            None => Err(Error::InvalidOption(format!("unknown stemmer {}", name))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The stopwords listed in the file given by `--stopwords`, if any.
+pub fn stopwords(matches: &Matches) -> Result<Option<Stopwords>> {
+    match matches.opt_str("stopwords") {
+        Some(file) => Stopwords::from_file(&file).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The filter described by the option `name`, if given, as for
+/// `filters::parse`.
+pub fn filter<'a>(matches: &Matches, name: &str, stopwords: &'a Stopwords) -> Result<Option<BoxedFilter<'a>>> {
+    match matches.opt_str(name) {
+        Some(description) => filters::parse(&description, stopwords).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// A filter also leaving out the `--stopwords`, if any were given.
+pub fn and_stopwords<'a>(filter: BoxedFilter<'a>, stopwords: Option<&'a Stopwords>) -> BoxedFilter<'a> {
+    match stopwords {
+        Some(stopwords) => Box::new(filters::and(filter, stopwords.filter())),
+        None => filter,
+    }
+}
+
+/// Report an error from the options and exit: for a bad option, with the
+/// usage and a status of 2, or for a file that could not be read, with a
+/// status of 1.
+pub fn exit(program: &str, opts: &Options, e: Error) -> ! {
+    match e {
+        Error::InvalidOption(_) | Error::InvalidPattern(_) => {
+            println!("{}", e);
+            println!("{}", opts.short_usage(program));
+            std::process::exit(2);
+        }
+        e => {
+            eprintln!("{}: {}", program, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn test_options() {
+    let mut opts = Options::new();
+    opts.optflag("s", "stem", "");
+    opts.optopt("", "stemmer", "", "NAME");
+    opts.optopt("", "stopwords", "", "FILE");
+    opts.optopt("f", "filter", "", "FILTER");
+    opts.optopt("e", "encoding", "", "NAME");
+    opts.optflag("", "lossy", "");
+    let parse = |args: &[&str]| unwrap!( opts.parse(args).ok() );
+    let english = Stopwords::english();
+
+    assert_eq!(unwrap!( decoding(&parse(&[])).ok() ), Decoding { encoding: None, policy: ErrorPolicy::Strict });
+    assert_eq!(unwrap!( decoding(&parse(&["-e", "latin1", "--lossy"])).ok() ),
+               Decoding { encoding: Some(Encoding::Latin1), policy: ErrorPolicy::Replace });
+    assert!(decoding(&parse(&["--encoding", "ebcdic"])).is_err());
+
+    assert_eq!(unwrap!( stemmer(&parse(&[])).ok() ), None);
+    assert_eq!(unwrap!( stemmer(&parse(&["-s"])).ok() ), Some(Stemmer::Porter2));
+    assert_eq!(unwrap!( stemmer(&parse(&["--stemmer", "porter"])).ok() ), Some(Stemmer::Porter));
+    match stemmer(&parse(&["--stemmer", "lancaster"])) {
+        Err(Error::InvalidOption(ref m)) if m.contains("lancaster") => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+
+    assert!(unwrap!( stopwords(&parse(&[])).ok() ).is_none());
+    match stopwords(&parse(&["--stopwords", "no/such/file"])) {
+        Err(Error::Open(..)) => { }
+        r => panic!("unexpected result: {:?}", r.map(|s| s.map(|s| s.len()))),
+    }
+
+    assert!(unwrap!( filter(&parse(&[]), "f", &english).ok() ).is_none());
+    assert!(filter(&parse(&["-f", "vowels"]), "f", &english).is_err());
+    let p = unwrap!( unwrap!( filter(&parse(&["-f", "alphabetic"]), "f", &english).ok() ) );
+    let mine = Stopwords::parse("cat\n");
+    let p = and_stopwords(p, Some(&mine));
+    assert!(p("the") && p("dog") && !p("cat") && !p("42"));
+}
@@ -0,0 +1,132 @@
+use std;
+use std::hash::Hash;
+use std::ops::Range;
+use std::thread;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use tokenizer::Tokenizer;
+use word_sequence::{CharClass,WordSequence};
+
+// Work is split into contiguous pieces, each piece is processed on its own
+// thread, and the results are merged in order. Merging in order is what
+// makes the results match a sequential run: vocabularies are merged in id
+// order, so each word keeps the id of its first occurrence in the whole
+// text, while counts are sums and do not depend on order at all.
+
+/// The number of threads to use by default: one per available core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Split `0..len` into at most `threads` contiguous ranges, apply `f` to
+/// each on its own thread, and return the results in order.
+pub fn map_ranges<R,F>(len: usize, threads: usize, f: F) -> Vec<R>
+    where R: Send,
+          F: Fn(Range<usize>) -> R + Sync {
+    let threads = threads.clamp(1, std::cmp::max(len, 1));
+    let size = len.div_ceil(threads);
+    let ranges: Vec<Range<usize>> = (0..threads)
+        .map(|i| std::cmp::min(i * size, len)..std::cmp::min((i + 1) * size, len))
+        .collect();
+    if threads == 1 {
+        return ranges.into_iter().map(&f).collect();
+    }
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = ranges.into_iter()
+            .map(|range| scope.spawn(move || f(range)))
+            .collect();
+        handles.into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+#[test]
+fn test_map_ranges() {
+    let sums = map_ranges(10, 3, |r| r.sum::<usize>());
+    assert_eq!(sums, vec!(6, 22, 17));
+    assert_eq!(map_ranges(2, 8, |r| r.len()), vec!(1,1));
+    assert_eq!(map_ranges(0, 4, |r| r.len()), vec!(0));
+}
+
+// ----------------------------------------
+
+// The first position at or after `pos` just after a whitespace grapheme,
+// where a text can be split without splitting a token.
+fn boundary_after(text: &str, pos: usize) -> usize {
+    if pos >= text.len() { return text.len(); }
+    let mut start = pos.saturating_sub(1);
+    while !text.is_char_boundary(start) { start -= 1; }
+    text[start..].grapheme_indices(true)
+        .find(|&(_,g)| CharClass::classify_grapheme(g) == CharClass::Whitespace)
+        .map(|(i,g)| start + i + g.len())
+        .unwrap_or(text.len())
+}
+
+/// Positions dividing a text into at most `n` pieces of roughly equal size,
+/// each ending just after whitespace so that no token is split. The first
+/// position is 0 and the last is the length of the text.
+pub fn split_points(text: &str, n: usize) -> Vec<usize> {
+    let size = text.len().div_ceil(std::cmp::max(n, 1));
+    let mut points = vec!(0);
+    while points[points.len()-1] < text.len() {
+        let last = points[points.len()-1];
+        points.push(boundary_after(text, last + size));
+    }
+    points
+}
+
+#[test]
+fn test_split_points() {
+    let text = "one two three four five";
+    let points = split_points(text, 3);
+    assert_eq!(points, vec!(0, 8, 19, 23));
+    assert_eq!(split_points("", 4), vec!(0));
+    assert_eq!(split_points("nospaces", 4), vec!(0, 8));
+}
+
+/// Tokenize a text on several threads. The result is the same as that of
+/// `WordSequence::with_tokenizer`, provided the tokenizer's tokens never
+/// contain whitespace.
+pub fn tokenize<'a,T,K,F,P>(tokenizer: &K, text: &'a str, to_t: F, is_word: P, threads: usize) -> WordSequence<T>
+    where T: Hash + Eq + Clone + Send,
+          K: Tokenizer + Sync + ?Sized,
+          F: Fn(&'a str) -> T + Sync,
+          P: Fn(&'a str) -> bool + Sync {
+    let points = split_points(text, threads);
+    let pieces = map_ranges(points.len() - 1, threads, |r| {
+        r.map(|i| WordSequence::with_tokenizer(tokenizer, &text[points[i]..points[i+1]], &to_t, &is_word))
+            .collect::<Vec<_>>()
+    });
+    let mut ws = WordSequence::empty();
+    ws.texts.push(0);
+    for (i,piece) in pieces.iter().flat_map(|p| p.iter()).enumerate() {
+        ws.append_sequence(piece, points[i]);
+    }
+    ws
+}
+
+#[test]
+fn test_tokenize() {
+    use tokenizer::{EnglishTokenizer,RunTokenizer};
+    let corpus = unwrap!( ::corpus::Corpus::from_paths(&["data/training-1.txt"]).ok() );
+    let text = &corpus.documents[0].text;
+    let expected = WordSequence::with_tokenizer(&EnglishTokenizer, text, |s| s, |_| true);
+    for threads in 1..6 {
+        let ws = tokenize(&EnglishTokenizer, text, |s| s, |_| true, threads);
+        assert_eq!(ws.words, expected.words);
+        assert_eq!(ws.spans, expected.spans);
+        assert_eq!(ws.texts, expected.texts);
+        assert_eq!(ws.vocabulary.counts(), expected.vocabulary.counts());
+    }
+    let ws = tokenize(&RunTokenizer, text, |s| s, |s| s.len() > 2, 4);
+    assert_eq!(ws.words, WordSequence::new(text, |s| s, |s| s.len() > 2).words);
+    let expected = WordSequence::new(text, ::case_string::CaseStr::from, |_| true);
+    let ws = tokenize(&RunTokenizer, text, ::case_string::CaseStr::from, |_| true, 4);
+    let (forms, expected_forms) = (ws.surface_forms(|_| text), expected.surface_forms(|_| text));
+    for w in 0..ws.vocabulary.len() {
+        assert_eq!(forms.forms(w), expected_forms.forms(w));
+    }
+}
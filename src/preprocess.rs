@@ -0,0 +1,323 @@
+use corpus::Corpus;
+use word_sequence::Span;
+
+// Cleaning only ever deletes from a text, apart from a space put in place
+// of a tag between two words, so the cleaned text is a series of pieces of
+// the original. The offset map records where each piece came from.
+
+/// What to remove from texts before tokenizing them; by default,
+/// everything.
+#[derive(Clone,Copy,Debug)]
+pub struct Preprocessor {
+    /// Remove a Project Gutenberg header and footer.
+    pub gutenberg: bool,
+    /// Remove SGML, HTML and XML tags, recording where they were.
+    pub tags: bool,
+    /// Remove the underscores marking _emphasis_, recording what they
+    /// marked.
+    pub emphasis: bool,
+    /// Rejoin words hyphenated across line breaks.
+    pub hyphens: bool,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Preprocessor {
+        Preprocessor { gutenberg: true, tags: true, emphasis: true, hyphens: true }
+    }
+}
+
+/// A cleaned text. Spans are of the original text unless said otherwise.
+#[derive(Clone,Debug)]
+pub struct Cleaned {
+    pub text: String,
+    /// The offsets in the original of the cleaned text.
+    pub map: OffsetMap,
+    /// The part of the original left after removing any Gutenberg header
+    /// and footer.
+    pub body: Span,
+    /// The tags removed.
+    pub tags: Vec<Span>,
+    /// The emphasized passages, without their underscores.
+    pub emphasis: Vec<Span>,
+}
+
+/// A map from offsets in a cleaned text to offsets in its original.
+#[derive(Clone,Debug)]
+pub struct OffsetMap {
+    // The start of each piece in the cleaned text and in the original.
+    pieces: Vec<(usize,usize)>,
+}
+
+impl OffsetMap {
+    /// The offset in the original of an offset in the cleaned text.
+    pub fn original(&self, offset: usize) -> usize {
+        let (clean, original) = self.pieces[self.pieces.partition_point(|&(c,_)| c <= offset) - 1];
+        original + offset - clean
+    }
+
+    /// The span of the original from which a span of the cleaned text
+    /// came, including anything removed from its middle.
+    pub fn original_span(&self, span: Span) -> Span {
+        if span.is_empty() {
+            let start = self.original(span.start);
+            return Span::new(start, start);
+        }
+        Span::new(self.original(span.start), self.original(span.end - 1) + 1)
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Preprocessor { Preprocessor::default() }
+
+    /// A preprocessor that removes nothing.
+    pub fn none() -> Preprocessor {
+        Preprocessor { gutenberg: false, tags: false, emphasis: false, hyphens: false }
+    }
+
+    /// Clean a text.
+    pub fn clean(&self, text: &str) -> Cleaned {
+        let body = if self.gutenberg { gutenberg_body(text) } else { Span::new(0, text.len()) };
+        let b = text.as_bytes();
+        let mut builder = Builder { source: text, text: String::new(), pieces: vec!((0, body.start)), from: body.start };
+        let mut tags = Vec::new();
+        let mut emphasis = Vec::new();
+        let mut closing: Option<usize> = None;
+        let mut i = body.start;
+        while i < body.end {
+            if closing.is_some_and(|end| end < i) {
+                // The closing underscore was inside a tag.
+                closing = None;
+            }
+            match b[i] {
+                b'<' if self.tags => if let Some(end) = tag_end(b, i, body.end) {
+                    tags.push(Span::new(i, end));
+                    builder.skip(i, end);
+                    let before = builder.text.chars().next_back().is_some_and(|c| !c.is_whitespace());
+                    if before && end < body.end && !is_space(b[end]) && is_block(&text[i..end]) {
+                        builder.insert(" ", i);
+                    }
+                    i = end;
+                    continue;
+                },
+                b'_' if closing == Some(i) => {
+                    builder.skip(i, i + 1);
+                    closing = None;
+                }
+                b'_' if self.emphasis && closing.is_none() && opens(b, i, body.start) => if let Some(end) = closes(b, i, body.end) {
+                    emphasis.push(Span::new(i + 1, end));
+                    builder.skip(i, i + 1);
+                    closing = Some(end);
+                },
+                b'-' if self.hyphens && i > body.start && b[i-1].is_ascii_alphabetic() => if let Some(next) = line_break(b, i + 1, body.end) {
+                    if b[next].is_ascii_lowercase() {
+                        builder.skip(i, next);
+                        i = next;
+                        continue;
+                    }
+                },
+                _ => { }
+            }
+            i += 1;
+        }
+        builder.skip(body.end, body.end);
+        Cleaned { text: builder.text, map: OffsetMap { pieces: builder.pieces }, body, tags, emphasis }
+    }
+
+    /// Clean every document of a corpus, returning the cleaned corpus and
+    /// the offset map of each document.
+    pub fn clean_corpus(&self, corpus: &Corpus) -> (Corpus,Vec<OffsetMap>) {
+        let mut cleaned_corpus = Corpus::new();
+        let mut maps = Vec::with_capacity(corpus.len());
+        for document in &corpus.documents {
+            let cleaned = self.clean(&document.text);
+            cleaned_corpus.add_document(&document.name, cleaned.text);
+            maps.push(cleaned.map);
+        }
+        (cleaned_corpus, maps)
+    }
+}
+
+// The cleaned text as it is built, and the end of the last piece of the
+// original copied into it.
+struct Builder<'a> {
+    source: &'a str,
+    text: String,
+    pieces: Vec<(usize,usize)>,
+    from: usize,
+}
+
+impl<'a> Builder<'a> {
+    // Copy the original up to `start`, and leave out the rest up to `end`.
+    fn skip(&mut self, start: usize, end: usize) {
+        if self.from < start {
+            let piece = (self.text.len(), self.from);
+            match self.pieces.last_mut() {
+                Some(last) if last.0 == piece.0 => *last = piece,
+                _ => self.pieces.push(piece),
+            }
+            self.text.push_str(&self.source[self.from..start]);
+        }
+        self.from = end;
+    }
+
+    // Put in text that is not in the original, mapped to `at`.
+    fn insert(&mut self, s: &str, at: usize) {
+        self.pieces.push((self.text.len(), at));
+        self.text.push_str(s);
+    }
+}
+
+fn is_space(b: u8) -> bool { b == b' ' || b == b'\t' || b == b'\r' || b == b'\n' }
+
+// Letters, digits and underscores, and any non-ASCII byte.
+fn is_word_byte(b: u8) -> bool { b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80 }
+
+// The end of a tag starting at `i`: a < followed by a letter, /, ! or ?,
+// and then anything but another < up to a >.
+fn tag_end(b: &[u8], i: usize, end: usize) -> Option<usize> {
+    const MAX_TAG: usize = 1024;
+    if i + 1 >= end || !(b[i+1].is_ascii_alphabetic() || b"/!?".contains(&b[i+1])) {
+        return None;
+    }
+    (i + 2..end.min(i + MAX_TAG))
+        .find(|&j| b[j] == b'<' || b[j] == b'>')
+        .filter(|&j| b[j] == b'>')
+        .map(|j| j + 1)
+}
+
+// Whether a tag separates words, as <br> and <p> do and <i> does not.
+fn is_block(tag: &str) -> bool {
+    const BLOCKS: &[&str] = &[
+        "address", "blockquote", "br", "dd", "div", "dl", "dt", "h1", "h2", "h3", "h4", "h5", "h6",
+        "hr", "li", "ol", "p", "pre", "table", "td", "th", "tr", "ul",
+    ];
+    let name: String = tag[1..].trim_start_matches('/').chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect();
+    BLOCKS.contains(&&*name.to_lowercase())
+}
+
+// Whether an underscore can open emphasis: it starts a word.
+fn opens(b: &[u8], i: usize, start: usize) -> bool {
+    (i == start || !is_word_byte(b[i-1])) && i + 1 < b.len() && !is_space(b[i+1]) && b[i+1] != b'_'
+}
+
+// The underscore closing emphasis opened at `i`, which ends a word in the
+// same paragraph.
+fn closes(b: &[u8], i: usize, end: usize) -> Option<usize> {
+    let mut j = i + 2;
+    while j < end {
+        match b[j] {
+            b'\n' if line_break(b, j, end).is_none_or(|k| b[k] == b'\n') => return None,
+            b'_' if !is_space(b[j-1]) && (j + 1 == end || !is_word_byte(b[j+1])) => return Some(j),
+            _ => { }
+        }
+        j += 1;
+    }
+    None
+}
+
+// The start of the text following a line break at `i`, after any
+// indentation; a blank line is text starting with its line break.
+fn line_break(b: &[u8], i: usize, end: usize) -> Option<usize> {
+    let mut j = i;
+    if j < end && b[j] == b'\r' { j += 1; }
+    if j >= end || b[j] != b'\n' { return None; }
+    j += 1;
+    while j < end && (b[j] == b' ' || b[j] == b'\t' || b[j] == b'\r') { j += 1; }
+    if j < end { Some(j) } else { None }
+}
+
+// ----------------------------------------
+
+// The part of a text between a Project Gutenberg header and footer, if it
+// has them. The header ends with a line like `*** START OF THIS PROJECT
+// GUTENBERG EBOOK ... ***`, perhaps followed by a paragraph of credits; the
+// footer starts with `End of the Project Gutenberg EBook of ...` or `***
+// END OF THIS PROJECT GUTENBERG EBOOK ... ***`.
+fn gutenberg_body(text: &str) -> Span {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        lines.push((offset, line));
+        offset += line.len();
+    }
+    let header = lines.iter().position(|&(_,line)| is_start(line));
+    let first = header.map_or(0, |h| h + 1);
+    let footer = lines[first..].iter().position(|&(_,line)| is_end(line)).map(|f| first + f);
+    let mut start = header.map_or(0, |h| lines[h].0 + lines[h].1.len());
+    if header.is_some() {
+        // Skip a paragraph of credits.
+        let mut rest = lines[first..footer.unwrap_or(lines.len())].iter().skip_while(|&&(_,line)| line.trim().is_empty());
+        if let Some(&(offset, line)) = rest.next() {
+            if line.starts_with("Produced by") {
+                start = rest.find(|&&(_,line)| line.trim().is_empty()).map_or(offset, |&(offset,_)| offset);
+            }
+        }
+    }
+    let end = footer.map_or(text.len(), |f| lines[f].0);
+    Span::new(start, end.max(start))
+}
+
+fn is_start(line: &str) -> bool {
+    let line = line.trim().to_uppercase();
+    (line.starts_with("***") && line.contains("START OF") && line.contains("GUTENBERG"))
+        || line.starts_with("*END*THE SMALL PRINT")
+}
+
+fn is_end(line: &str) -> bool {
+    let line = line.trim().to_uppercase();
+    (line.starts_with("***") && line.contains("END OF") && line.contains("GUTENBERG"))
+        || line.starts_with("END OF THE PROJECT GUTENBERG")
+        || line.starts_with("END OF PROJECT GUTENBERG")
+}
+
+#[test]
+fn test_gutenberg() {
+    let text = "The Project Gutenberg EBook of X\r\n\r\n*** START OF THIS PROJECT GUTENBERG EBOOK X ***\r\n\r\nProduced by Someone\r\nand Others\r\n\r\nCHAPTER I\r\n\r\nThe end.\r\n\r\nEnd of the Project Gutenberg EBook of X\r\n\r\n*** END OF THIS PROJECT GUTENBERG EBOOK X ***\r\nLicense.\r\n";
+    let cleaned = Preprocessor::new().clean(text);
+    assert_eq!(cleaned.text.trim(), "CHAPTER I\r\n\r\nThe end.");
+    assert_eq!(&text[cleaned.body.start..cleaned.body.end], cleaned.text);
+    assert_eq!(Preprocessor::new().clean("No header.").text, "No header.");
+    assert_eq!(Preprocessor::none().clean(text).text, text);
+}
+
+#[test]
+fn test_markup() {
+    let text = "<p>\n_\"Et ignotas,\n18._\n</p> a<br>b <i>it</i>'s snake_case and _ alone_\nrejoin an exam-\n   ple, but not well-\nKnown, _one\n\nparagraph_";
+    let cleaned = Preprocessor::new().clean(text);
+    assert_eq!(cleaned.text, "\n\"Et ignotas,\n18.\n a b it's snake_case and _ alone_\nrejoin an example, but not well-\nKnown, _one\n\nparagraph_");
+    assert_eq!(cleaned.tags.len(), 5);
+    assert_eq!(&text[cleaned.tags[2].start..cleaned.tags[2].end], "<br>");
+    assert_eq!(cleaned.emphasis.len(), 1);
+    assert_eq!(&text[cleaned.emphasis[0].start..cleaned.emphasis[0].end], "\"Et ignotas,\n18.");
+    // Spans in the cleaned text map back to where they came from.
+    let map = &cleaned.map;
+    let find = |s: &str| { let i = unwrap!( cleaned.text.find(s) ); map.original_span(Span::new(i, i + s.len())) };
+    let example = find("example");
+    assert_eq!(&text[example.start..example.end], "exam-\n   ple");
+    let span = find("it's");
+    assert_eq!(&text[span.start..span.end], "it</i>'s");
+    let span = find("a b");
+    assert_eq!(&text[span.start..span.end], "a<br>b");
+    assert_eq!(map.original(0), 3);
+    assert_eq!(map.original(cleaned.text.len()), text.len());
+}
+
+#[test]
+fn test_tom_sawyer() {
+    use word_sequence::WordSequence;
+    let corpus = unwrap!( Corpus::from_paths(&["data/Tom_Sawyer.txt"]).ok() );
+    let text = &corpus.documents[0].text;
+    let cleaned = Preprocessor::new().clean(text);
+    assert!(!cleaned.text.contains("Gutenberg") && !cleaned.text.contains("Produced by"));
+    assert!(cleaned.text.trim_start().starts_with("THE ADVENTURES OF TOM SAWYER"));
+    assert!(cleaned.text.trim_end().ends_with("part of their lives at present."));
+    assert!(!cleaned.emphasis.is_empty());
+    let ws = WordSequence::new(&cleaned.text, |s| s, |_| true);
+    for (i,&span) in ws.spans.iter().enumerate() {
+        let original = cleaned.map.original_span(span);
+        let source = text[original.start..original.end].replace('_', "");
+        assert_eq!(source, ws[ws.words[i]].replace('_', ""), "token {}", i);
+    }
+}
@@ -0,0 +1,209 @@
+use tokenizer::{EnglishTokenizer,Token,Tokenizer};
+use word_sequence::CharClass;
+
+/// A tokenizer following Penn Treebank conventions: clitics (`n't`, `'s`,
+/// `'ll`...) are separate tokens, double quotes become ``` `` ``` and `''`,
+/// and brackets are escaped as `-LRB-`, `-RRB-` and so on. Replaced tokens
+/// carry their PTB form; their spans still point at the original characters.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct PtbTokenizer;
+
+impl Tokenizer for PtbTokenizer {
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> {
+        Box::new(ptb_tokens(text).into_iter())
+    }
+}
+
+// Abbreviations that keep their period.
+const ABBREVIATIONS: &[&str] = &[
+    "Mr", "Mrs", "Ms", "Dr", "St", "Jr", "Sr", "Prof", "Rev", "Hon", "Gen", "Col",
+    "Capt", "Lt", "Sgt", "Mt", "Co", "Corp", "Inc", "Ltd", "vs", "etc",
+    "Jan", "Feb", "Mar", "Apr", "Jun", "Jul", "Aug", "Sep", "Sept", "Oct", "Nov", "Dec",
+];
+
+/// Whether a word is an abbreviation that keeps its period, such as Mr or
+/// an initial.
+pub fn is_abbreviation(word: &str) -> bool {
+    ABBREVIATIONS.contains(&word)
+        || (word.chars().count() == 1 && word.chars().all(|ch| ch.is_uppercase()))
+}
+
+fn bracket_form(ch: char) -> Option<&'static str> {
+    match ch {
+        '(' => Some("-LRB-"),
+        ')' => Some("-RRB-"),
+        '[' => Some("-LSB-"),
+        ']' => Some("-RSB-"),
+        '{' => Some("-LCB-"),
+        '}' => Some("-RCB-"),
+        _ => None,
+    }
+}
+
+// A quote opens if it starts the text or follows whitespace or an opening
+// bracket or quote.
+fn is_opening(text: &str, pos: usize) -> bool {
+    match text[..pos].chars().next_back() {
+        None => true,
+        Some(ch) => ch.is_whitespace() || "([{<`\u{201c}\u{2018}".contains(ch),
+    }
+}
+
+fn quote_form(text: &str, pos: usize, ch: char) -> Option<&'static str> {
+    match ch {
+        '"' => Some(if is_opening(text, pos) { "``" } else { "''" }),
+        '\'' => Some(if is_opening(text, pos) { "`" } else { "'" }),
+        '\u{201c}' => Some("``"),
+        '\u{201d}' => Some("''"),
+        '\u{2018}' => Some("`"),
+        '\u{2019}' => Some("'"),
+        _ => None,
+    }
+}
+
+// The position at which a clitic starts within a word, if it has one.
+fn clitic_start(word: &str) -> Option<usize> {
+    let (apos, ch) = word.char_indices().rev().find(|&(_,ch)| ch == '\'' || ch == '\u{2019}')?;
+    let suffix = word[apos + ch.len_utf8()..].to_lowercase();
+    let stem = &word[..apos];
+    if suffix == "t" && stem.len() > 1 && stem.ends_with(['n', 'N']) {
+        Some(apos - 1)
+    } else if ["s", "m", "d", "ll", "re", "ve"].contains(&suffix.as_str()) && !stem.is_empty() {
+        Some(apos)
+    } else {
+        None
+    }
+}
+
+fn ptb_tokens(text: &str) -> Vec<Token> {
+    let base: Vec<Token> = EnglishTokenizer.tokens(text).collect();
+    let mut tokens = Vec::with_capacity(base.len());
+    let mut i = 0;
+    while i < base.len() {
+        let tok = base[i];
+        let s = tok.text(text);
+        let (start, end) = (tok.span.start, tok.span.end);
+        let first = s.chars().next().unwrap_or(' ');
+        if tok.class == CharClass::Other
+            && (bracket_form(first).is_some() || quote_form(text, start, first).is_some()) {
+            // A run of brackets or quotes: one token per character.
+            for (j,ch) in s.char_indices() {
+                let form = bracket_form(ch).or_else(|| quote_form(text, start + j, ch));
+                tokens.push(Token::with_form(tok.class, start + j, start + j + ch.len_utf8(), unwrap!( form )));
+            }
+        } else if tok.class == CharClass::Alphabetic && i + 1 < base.len()
+            && base[i+1].span.start == end && base[i+1].text(text) == "." && is_abbreviation(s) {
+            tokens.push(Token::new(tok.class, start, base[i+1].span.end));
+            i += 1;
+        } else if let Some(c) = clitic_start(s).filter(|_| tok.class == CharClass::Alphabetic) {
+            tokens.push(Token::new(tok.class, start, start + c));
+            tokens.push(Token::new(CharClass::Other, start + c, end));
+        } else {
+            tokens.push(tok);
+        }
+        i += 1;
+    }
+    tokens
+}
+
+// ----------------------------------------
+
+fn unescape(token: &str) -> &str {
+    match token {
+        "``" | "''" => "\"",
+        "`" => "'",
+        "-LRB-" => "(",
+        "-RRB-" => ")",
+        "-LSB-" => "[",
+        "-RSB-" => "]",
+        "-LCB-" => "{",
+        "-RCB-" => "}",
+        _ => token,
+    }
+}
+
+fn attaches_left(token: &str) -> bool {
+    match token {
+        "''" | "'" | "-RRB-" | "-RSB-" | "-RCB-" | "," | "." | ";" | ":" | "!" | "?" | "%" | "..." => true,
+        _ => is_clitic(token),
+    }
+}
+
+fn is_clitic(token: &str) -> bool {
+    let token = token.replace('\u{2019}', "'").to_lowercase();
+    ["n't", "'s", "'m", "'d", "'ll", "'re", "'ve"].contains(&token.as_str())
+}
+
+fn attaches_right(token: &str) -> bool {
+    matches!(token, "``" | "`" | "-LRB-" | "-LSB-" | "-LCB-" | "$" | "#")
+}
+
+/// Reverse PTB tokenization: rejoin clitics and punctuation and restore
+/// quotes and brackets.
+pub fn detokenize<S: AsRef<str>>(tokens: &[S]) -> String {
+    let mut text = String::new();
+    let mut prev: Option<&str> = None;
+    for token in tokens {
+        let token = token.as_ref();
+        if let Some(p) = prev {
+            if !attaches_right(p) && !attaches_left(token) {
+                text.push(' ');
+            }
+        }
+        text.push_str(unescape(token));
+        prev = Some(token);
+    }
+    text
+}
+
+// ----------------------------------------
+
+#[cfg(test)]
+const REFERENCE: &[(&str, &str)] = &[
+    ("\"Don't worry,\" he said.",
+     "`` Do n't worry , '' he said ."),
+    ("They can't (and won't) pay $5.50 for it.",
+     "They ca n't -LRB- and wo n't -RRB- pay $ 5.50 for it ."),
+    ("Mr. Smith's dog isn't here; it's at St. Petersburg.",
+     "Mr. Smith 's dog is n't here ; it 's at St. Petersburg ."),
+    ("She said 'no' to me, and I'd agree [mostly].",
+     "She said ` no ' to me , and I 'd agree -LSB- mostly -RSB- ."),
+    ("\"What's gone with that boy, I wonder?\"",
+     "`` What 's gone with that boy , I wonder ? ''"),
+];
+
+#[test]
+fn test_ptb_reference() {
+    use tokenizer::token_strs;
+    for &(raw, ptb) in REFERENCE {
+        assert_eq!(token_strs(&PtbTokenizer, raw).join(" "), ptb);
+    }
+}
+
+#[test]
+fn test_ptb_spans() {
+    let text = "(can't)";
+    let tokens: Vec<Token> = PtbTokenizer.tokens(text).collect();
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(tokens[0].form, Some("-LRB-"));
+    assert_eq!((tokens[0].span.start, tokens[0].span.end), (0,1));
+    assert_eq!(&text[tokens[1].span.start..tokens[1].span.end], "ca");
+    assert_eq!(&text[tokens[2].span.start..tokens[2].span.end], "n't");
+}
+
+#[test]
+fn test_detokenize() {
+    for &(raw, ptb) in REFERENCE {
+        let tokens: Vec<&str> = ptb.split(' ').collect();
+        assert_eq!(detokenize(&tokens), raw);
+    }
+}
+
+#[test]
+fn test_word_sequence() {
+    use word_sequence::WordSequence;
+    let ws = WordSequence::with_tokenizer(&PtbTokenizer, "\"I can't,\" she said. \"Can't?\"", |s| s, |_| true);
+    assert_eq!(ws[ws.words[0]], "``");
+    assert_eq!(ws[ws.words[3]], "n't");
+    assert_eq!(ws.words[3], ws.words[11]);
+}
@@ -9,6 +9,10 @@ pub struct Sample<T> {
     pub total: usize,
 }
 
+impl<T: Eq + Hash> Default for Sample<T> {
+    fn default() -> Sample<T> { Sample::new() }
+}
+
 impl<T: Eq + Hash> Sample<T> {
     /// Creates a new Sample.
     pub fn new() -> Sample<T> {
@@ -25,6 +29,14 @@ impl<T: Eq + Hash> Sample<T> {
         self.total += 1;
     }
 
+    /// Add the events of another sample to this one.
+    pub fn merge(&mut self, other: Sample<T>) {
+        for (event, n) in other.counts {
+            *self.counts.entry(event).or_insert(0) += n;
+        }
+        self.total += other.total;
+    }
+
     /// The probability of an event in a sample.
     pub fn p(&self, event: &T) -> f64 {
         let c = *self.counts.get(event).unwrap_or(&0);
@@ -43,7 +55,7 @@ impl<T: Eq + Hash> Extend<T> for Sample<T> {
 impl<T: Eq + Hash> FromIterator<T> for Sample<T> {
     fn from_iter<I: IntoIterator<Item=T>>(iterable: I) -> Sample<T> {
         let mut sample = Sample::new();
-        sample.extend( iterable.into_iter() );
+        sample.extend( iterable );
         sample
     }
 }
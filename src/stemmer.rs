@@ -0,0 +1,577 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+
+use case_string::AsStr;
+
+// Both stemmers work on lower-case ASCII words; anything else is only
+// lower-cased. They follow the published descriptions: Martin Porter's
+// reference C implementation of the 1980 algorithm, and the English
+// ("Porter2") stemmer of the Snowball project.
+
+/// A stemming algorithm for English.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Stemmer {
+    /// The original Porter stemmer.
+    Porter,
+    /// Porter's revised English stemmer, from Snowball.
+    Porter2,
+}
+
+impl Stemmer {
+    /// The stemmer with a name: "porter" or "porter2".
+    pub fn named(name: &str) -> Option<Stemmer> {
+        match name {
+            "porter"  => Some(Stemmer::Porter),
+            "porter2" => Some(Stemmer::Porter2),
+            _         => None,
+        }
+    }
+
+    /// A function making StemStrs with this stemmer, for use as the `to_t`
+    /// of a WordSequence.
+    pub fn key(self) -> impl for<'s> Fn(&'s str) -> StemStr<'s> + Copy + Send + Sync {
+        move |s| StemStr::new(s, self)
+    }
+
+    /// The stem of a word, in lower case.
+    pub fn stem(self, word: &str) -> String {
+        match self {
+            Stemmer::Porter  => porter(word),
+            Stemmer::Porter2 => porter2(word),
+        }
+    }
+}
+
+// ----------------------------------------
+
+/// The stem of an English word by the original Porter algorithm.
+pub fn porter(word: &str) -> String {
+    let lower = word.to_lowercase();
+    if lower.len() <= 2 || !lower.bytes().all(|b| b.is_ascii_lowercase()) {
+        return lower;
+    }
+    let mut p = Porter { b: lower.into_bytes(), j: 0 };
+    p.step1ab();
+    if p.b.len() > 1 {
+        p.step1c();
+        p.step2();
+        p.step3();
+        p.step4();
+        p.step5();
+    }
+    unwrap!( String::from_utf8(p.b).ok() )
+}
+
+// The word being stemmed and, after a successful `ends`, the length of the
+// part before the suffix.
+struct Porter {
+    b: Vec<u8>,
+    j: usize,
+}
+
+impl Porter {
+    fn cons(&self, i: usize) -> bool {
+        match self.b[i] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !self.cons(i - 1),
+            _ => true,
+        }
+    }
+
+    // The number of vowel-consonant sequences in the stem, b[..j].
+    fn m(&self) -> usize {
+        let mut n = 0;
+        let mut i = 0;
+        while i < self.j && self.cons(i) { i += 1; }
+        loop {
+            while i < self.j && !self.cons(i) { i += 1; }
+            if i >= self.j { return n; }
+            while i < self.j && self.cons(i) { i += 1; }
+            n += 1;
+        }
+    }
+
+    fn vowel_in_stem(&self) -> bool { (0..self.j).any(|i| !self.cons(i)) }
+
+    // Whether b[..=i] ends in a double consonant.
+    fn double_c(&self, i: usize) -> bool {
+        i >= 1 && self.b[i] == self.b[i-1] && self.cons(i)
+    }
+
+    // Whether b[..=i] ends consonant-vowel-consonant, the last not w, x or y.
+    fn cvc(&self, i: usize) -> bool {
+        i >= 2 && self.cons(i) && !self.cons(i-1) && self.cons(i-2)
+            && !matches!(self.b[i], b'w' | b'x' | b'y')
+    }
+
+    fn ends(&mut self, s: &str) -> bool {
+        if self.b.ends_with(s.as_bytes()) {
+            self.j = self.b.len() - s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn set_to(&mut self, s: &str) {
+        self.b.truncate(self.j);
+        self.b.extend_from_slice(s.as_bytes());
+    }
+
+    fn r(&mut self, s: &str) {
+        if self.m() > 0 { self.set_to(s); }
+    }
+
+    fn last(&self) -> u8 { self.b[self.b.len() - 1] }
+
+    // Plurals and -ed or -ing.
+    fn step1ab(&mut self) {
+        if self.last() == b's' {
+            if self.ends("sses") {
+                self.b.truncate(self.b.len() - 2);
+            } else if self.ends("ies") {
+                self.set_to("i");
+            } else if self.b[self.b.len() - 2] != b's' {
+                self.b.pop();
+            }
+        }
+        if self.ends("eed") {
+            if self.m() > 0 { self.b.pop(); }
+        } else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+            self.b.truncate(self.j);
+            let k = self.b.len() - 1;
+            if self.ends("at") {
+                self.set_to("ate");
+            } else if self.ends("bl") {
+                self.set_to("ble");
+            } else if self.ends("iz") {
+                self.set_to("ize");
+            } else if self.double_c(k) {
+                if !matches!(self.b[k], b'l' | b's' | b'z') { self.b.pop(); }
+            } else {
+                self.j = self.b.len();
+                if self.m() == 1 && self.cvc(k) { self.b.push(b'e'); }
+            }
+        }
+    }
+
+    // Terminal y to i when there is another vowel in the stem.
+    fn step1c(&mut self) {
+        if self.ends("y") && self.vowel_in_stem() {
+            let k = self.b.len() - 1;
+            self.b[k] = b'i';
+        }
+    }
+
+    // Double suffixes to single ones.
+    fn step2(&mut self) {
+        const RULES: &[(&str,&str)] = &[
+            ("ational", "ate"), ("tional", "tion"), ("enci", "ence"), ("anci", "ance"),
+            ("izer", "ize"), ("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"),
+            ("ousli", "ous"), ("ization", "ize"), ("ation", "ate"), ("ator", "ate"),
+            ("alism", "al"), ("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous"),
+            ("aliti", "al"), ("iviti", "ive"), ("biliti", "ble"), ("logi", "log"),
+        ];
+        self.replace_first(RULES);
+    }
+
+    // -ic-, -full, -ness etc.
+    fn step3(&mut self) {
+        const RULES: &[(&str,&str)] = &[
+            ("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"),
+            ("ical", "ic"), ("ful", ""), ("ness", ""),
+        ];
+        self.replace_first(RULES);
+    }
+
+    // Apply the first rule whose suffix the word ends with, if m() > 0. No
+    // two suffixes in a step end the same way unless the longer comes first.
+    fn replace_first(&mut self, rules: &[(&str,&str)]) {
+        if self.b.len() < 2 { return; }
+        if let Some(&(_, to)) = rules.iter().find(|&&(from,_)| self.ends(from)) {
+            self.r(to);
+        }
+    }
+
+    // -ant, -ence etc., in context <c>vcvc<v>.
+    fn step4(&mut self) {
+        const SUFFIXES: &[&str] = &[
+            "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent",
+            "ion", "ou", "ism", "ate", "iti", "ous", "ive", "ize",
+        ];
+        let found = SUFFIXES.iter().find(|&&s| {
+            self.ends(s) && (s != "ion" || (self.j > 0 && matches!(self.b[self.j - 1], b's' | b't')))
+        });
+        if found.is_some() && self.m() > 1 {
+            self.b.truncate(self.j);
+        }
+    }
+
+    // A final -e, and -ll to -l.
+    fn step5(&mut self) {
+        self.j = self.b.len();
+        if self.last() == b'e' {
+            self.j -= 1;
+            let a = self.m();
+            if a > 1 || (a == 1 && !self.cvc(self.j - 1)) {
+                self.b.pop();
+            }
+            self.j = self.b.len();
+        }
+        let k = self.b.len() - 1;
+        if self.last() == b'l' && self.double_c(k) && { self.j = k; self.m() > 1 } {
+            self.b.pop();
+        }
+    }
+}
+
+#[test]
+fn test_porter() {
+    let pairs = [
+        ("caresses", "caress"), ("ponies", "poni"), ("ties", "ti"), ("caress", "caress"),
+        ("cats", "cat"), ("feed", "feed"), ("agreed", "agre"), ("plastered", "plaster"),
+        ("bled", "bled"), ("motoring", "motor"), ("sing", "sing"), ("conflated", "conflat"),
+        ("troubled", "troubl"), ("sized", "size"), ("hopping", "hop"), ("tanned", "tan"),
+        ("falling", "fall"), ("hissing", "hiss"), ("fizzed", "fizz"), ("failing", "fail"),
+        ("filing", "file"), ("happy", "happi"), ("sky", "sky"), ("relational", "relat"),
+        ("conditional", "condit"), ("rational", "ration"), ("valenci", "valenc"),
+        ("digitizer", "digit"), ("conformabli", "conform"), ("radicalli", "radic"),
+        ("differentli", "differ"), ("vileli", "vile"), ("analogousli", "analog"),
+        ("vietnamization", "vietnam"), ("predication", "predic"), ("operator", "oper"),
+        ("feudalism", "feudal"), ("decisiveness", "decis"), ("hopefulness", "hope"),
+        ("callousness", "callous"), ("formaliti", "formal"), ("sensitiviti", "sensit"),
+        ("sensibiliti", "sensibl"), ("triplicate", "triplic"), ("formative", "form"),
+        ("formalize", "formal"), ("electriciti", "electr"), ("electrical", "electr"),
+        ("hopeful", "hope"), ("goodness", "good"), ("revival", "reviv"),
+        ("allowance", "allow"), ("inference", "infer"), ("airliner", "airlin"),
+        ("gyroscopic", "gyroscop"), ("adjustable", "adjust"), ("defensible", "defens"),
+        ("irritant", "irrit"), ("replacement", "replac"), ("adjustment", "adjust"),
+        ("dependent", "depend"), ("adoption", "adopt"), ("homologou", "homolog"),
+        ("communism", "commun"), ("activate", "activ"), ("angulariti", "angular"),
+        ("homologous", "homolog"), ("effective", "effect"), ("bowdlerize", "bowdler"),
+        ("probate", "probat"), ("rate", "rate"), ("cease", "ceas"), ("controll", "control"),
+        ("roll", "roll"), ("generalizations", "gener"), ("oscillators", "oscil"),
+        ("Running", "run"), ("is", "is"), ("na\u{ef}ve", "na\u{ef}ve"),
+    ];
+    for &(word, stem) in pairs.iter() {
+        assert_eq!(porter(word), stem, "{}", word);
+    }
+}
+
+// ----------------------------------------
+
+/// The stem of an English word by the Porter2 (Snowball English) algorithm.
+pub fn porter2(word: &str) -> String {
+    let lower = word.to_lowercase().replace('\u{2019}', "'");
+    if lower.len() <= 2 || !lower.bytes().all(|b| b.is_ascii_lowercase() || b == b'\'') {
+        return lower;
+    }
+    if let Some(stem) = exception1(&lower) {
+        return stem.to_string();
+    }
+    let mut w = lower.into_bytes();
+    if w[0] == b'\'' { w.remove(0); }
+    for i in 0..w.len() {
+        if w[i] == b'y' && (i == 0 || is_vowel(w[i-1])) {
+            w[i] = b'Y';
+        }
+    }
+    let r1 = [&b"gener"[..], b"commun", b"arsen"].iter()
+        .find(|p| w.starts_with(p))
+        .map(|p| p.len())
+        .unwrap_or_else(|| region_after(&w, 0));
+    let r2 = region_after(&w, r1);
+
+    // Step 0: apostrophes.
+    if let Some(s) = longest(&w, &["'s'", "'s", "'"]) {
+        w.truncate(w.len() - s.len());
+    }
+    // What is left may be too short for the suffixes below.
+    if w.len() <= 2 {
+        return finish(w);
+    }
+
+    // Step 1a: plurals.
+    match longest(&w, &["sses", "ied", "ies", "us", "ss", "s"]) {
+        Some("sses") => { w.truncate(w.len() - 2); }
+        Some("ied") | Some("ies") => {
+            let to: &[u8] = if w.len() > 4 { b"i" } else { b"ie" };
+            replace(&mut w, 3, to);
+        }
+        Some("s") if w[..w.len()-2].iter().any(|&c| is_vowel(c)) => { w.pop(); }
+        _ => { }
+    }
+    if ["inning", "outing", "canning", "herring", "earring", "proceed", "exceed", "succeed"]
+        .iter().any(|&e| e.as_bytes() == &w[..]) {
+        return finish(w);
+    }
+
+    // Step 1b: -ed and -ing.
+    match longest(&w, &["eedly", "ingly", "edly", "eed", "ing", "ed"]) {
+        Some(s @ "eed") | Some(s @ "eedly") if w.len() - s.len() >= r1 => {
+            replace(&mut w, s.len(), b"ee");
+        }
+        Some("eed") | Some("eedly") => { }
+        Some(s) => {
+            let stem = w.len() - s.len();
+            if w[..stem].iter().any(|&c| is_vowel(c)) {
+                w.truncate(stem);
+                if w.ends_with(b"at") || w.ends_with(b"bl") || w.ends_with(b"iz") {
+                    w.push(b'e');
+                } else if ["bb", "dd", "ff", "gg", "mm", "nn", "pp", "rr", "tt"].iter().any(|d| w.ends_with(d.as_bytes())) {
+                    w.pop();
+                } else if r1 >= w.len() && ends_short_syllable(&w) {
+                    w.push(b'e');
+                }
+            }
+        }
+        None => { }
+    }
+
+    // Step 1c: a final y after a consonant.
+    let n = w.len();
+    if n > 2 && (w[n-1] == b'y' || w[n-1] == b'Y') && !is_vowel(w[n-2]) {
+        w[n-1] = b'i';
+    }
+
+    // Step 2: double suffixes, in R1.
+    const STEP2: &[(&str,&str)] = &[
+        ("tional", "tion"), ("enci", "ence"), ("anci", "ance"), ("abli", "able"),
+        ("entli", "ent"), ("izer", "ize"), ("ization", "ize"), ("ational", "ate"),
+        ("ation", "ate"), ("ator", "ate"), ("alism", "al"), ("aliti", "al"), ("alli", "al"),
+        ("fulness", "ful"), ("ousli", "ous"), ("ousness", "ous"), ("iveness", "ive"),
+        ("iviti", "ive"), ("biliti", "ble"), ("bli", "ble"), ("ogi", "og"), ("fulli", "ful"),
+        ("lessli", "less"), ("li", ""),
+    ];
+    if let Some((from, to)) = longest_rule(&w, STEP2) {
+        let stem = w.len() - from.len();
+        let ok = stem >= r1 && match from {
+            "ogi" => stem > 0 && w[stem-1] == b'l',
+            "li"  => stem > 0 && b"cdeghkmnrt".contains(&w[stem-1]),
+            _     => true,
+        };
+        if ok { replace(&mut w, from.len(), to.as_bytes()); }
+    }
+
+    // Step 3: more suffixes, in R1.
+    const STEP3: &[(&str,&str)] = &[
+        ("tional", "tion"), ("ational", "ate"), ("alize", "al"), ("icate", "ic"),
+        ("iciti", "ic"), ("ical", "ic"), ("ful", ""), ("ness", ""), ("ative", ""),
+    ];
+    if let Some((from, to)) = longest_rule(&w, STEP3) {
+        let stem = w.len() - from.len();
+        if stem >= r1 && (from != "ative" || stem >= r2) {
+            replace(&mut w, from.len(), to.as_bytes());
+        }
+    }
+
+    // Step 4: suffixes removed in R2.
+    const STEP4: &[&str] = &[
+        "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent",
+        "ism", "ate", "iti", "ous", "ive", "ize", "ion",
+    ];
+    if let Some(s) = longest(&w, STEP4) {
+        let stem = w.len() - s.len();
+        if stem >= r2 && (s != "ion" || (stem > 0 && matches!(w[stem-1], b's' | b't'))) {
+            w.truncate(stem);
+        }
+    }
+
+    // Step 5: a final e or l.
+    let n = w.len();
+    if w[n-1] == b'e' {
+        if n > r2 || (n > r1 && !ends_short_syllable(&w[..n-1])) {
+            w.pop();
+        }
+    } else if w[n-1] == b'l' && n > r2 && n >= 2 && w[n-2] == b'l' {
+        w.pop();
+    }
+    finish(w)
+}
+
+fn exception1(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "skis" => "ski",
+        "skies" => "sky",
+        "dying" => "die",
+        "lying" => "lie",
+        "tying" => "tie",
+        "idly" => "idl",
+        "gently" => "gentl",
+        "ugly" => "ugli",
+        "early" => "earli",
+        "only" => "onli",
+        "singly" => "singl",
+        "sky" => "sky",
+        "news" => "news",
+        "howe" => "howe",
+        "atlas" => "atlas",
+        "cosmos" => "cosmos",
+        "bias" => "bias",
+        "andes" => "andes",
+        _ => return None,
+    })
+}
+
+fn is_vowel(c: u8) -> bool { matches!(c, b'a' | b'e' | b'i' | b'o' | b'u' | b'y') }
+
+// The start of the region after the first non-vowel following a vowel, at
+// or after `start`.
+fn region_after(w: &[u8], start: usize) -> usize {
+    (start + 1..w.len())
+        .find(|&i| !is_vowel(w[i]) && is_vowel(w[i-1]))
+        .map(|i| i + 1)
+        .unwrap_or(w.len())
+}
+
+// Whether a word ends in a short syllable: a vowel followed by a non-vowel
+// other than w, x or Y and preceded by a non-vowel, or a vowel followed by
+// a non-vowel at the start of the word.
+fn ends_short_syllable(w: &[u8]) -> bool {
+    let n = w.len();
+    (n >= 3 && !is_vowel(w[n-3]) && is_vowel(w[n-2]) && !is_vowel(w[n-1])
+        && !matches!(w[n-1], b'w' | b'x' | b'Y'))
+        || (n == 2 && is_vowel(w[0]) && !is_vowel(w[1]))
+}
+
+// The longest of some suffixes that a word ends with.
+fn longest<'a>(w: &[u8], suffixes: &[&'a str]) -> Option<&'a str> {
+    suffixes.iter().cloned()
+        .filter(|s| w.ends_with(s.as_bytes()))
+        .max_by_key(|s| s.len())
+}
+
+fn longest_rule<'a>(w: &[u8], rules: &[(&'a str,&'a str)]) -> Option<(&'a str,&'a str)> {
+    rules.iter().cloned()
+        .filter(|r| w.ends_with(r.0.as_bytes()))
+        .max_by_key(|r| r.0.len())
+}
+
+fn replace(w: &mut Vec<u8>, suffix_len: usize, to: &[u8]) {
+    let n = w.len();
+    w.truncate(n - suffix_len);
+    w.extend_from_slice(to);
+}
+
+fn finish(mut w: Vec<u8>) -> String {
+    for c in w.iter_mut().filter(|c| **c == b'Y') {
+        *c = b'y';
+    }
+    unwrap!( String::from_utf8(w).ok() )
+}
+
+#[test]
+fn test_porter2() {
+    let pairs = [
+        ("consign", "consign"), ("consigned", "consign"), ("consigning", "consign"),
+        ("consignment", "consign"), ("consist", "consist"), ("consisted", "consist"),
+        ("consistency", "consist"), ("consistent", "consist"), ("consistently", "consist"),
+        ("consisting", "consist"), ("consists", "consist"), ("consolation", "consol"),
+        ("consolations", "consol"), ("consolatory", "consolatori"), ("console", "consol"),
+        ("consoled", "consol"), ("consoles", "consol"), ("consolidate", "consolid"),
+        ("consolidated", "consolid"), ("consolidating", "consolid"), ("consoling", "consol"),
+        ("consolingly", "consol"), ("consols", "consol"), ("consonant", "conson"),
+        ("consort", "consort"), ("consorted", "consort"), ("consorting", "consort"),
+        ("conspicuous", "conspicu"), ("conspicuously", "conspicu"),
+        ("conspiracy", "conspiraci"), ("conspirator", "conspir"), ("conspirators", "conspir"),
+        ("conspire", "conspir"), ("conspired", "conspir"), ("conspiring", "conspir"),
+        ("constable", "constabl"), ("constables", "constabl"), ("constance", "constanc"),
+        ("constancy", "constanc"), ("constant", "constant"),
+        ("knack", "knack"), ("knackeries", "knackeri"), ("knaves", "knave"),
+        ("knavish", "knavish"), ("kneaded", "knead"), ("kneel", "kneel"),
+        ("kneeling", "kneel"), ("knees", "knee"), ("knell", "knell"), ("knelt", "knelt"),
+        ("knew", "knew"), ("knife", "knife"), ("knight", "knight"), ("knightly", "knight"),
+        ("knights", "knight"), ("knit", "knit"), ("knits", "knit"), ("knitted", "knit"),
+        ("knitting", "knit"), ("knives", "knive"), ("knocker", "knocker"),
+        ("caresses", "caress"), ("ponies", "poni"), ("ties", "tie"), ("cries", "cri"),
+        ("gaps", "gap"), ("gas", "gas"), ("kiwis", "kiwi"), ("dying", "die"),
+        ("skies", "sky"), ("news", "news"), ("generously", "generous"), ("cry", "cri"),
+        ("by", "by"), ("say", "say"), ("succeeded", "succeed"), ("Tom's", "tom"),
+        ("Tom\u{2019}s", "tom"), ("youth", "youth"), ("na\u{ef}ve", "na\u{ef}ve"),
+    ];
+    for &(word, stem) in pairs.iter() {
+        assert_eq!(porter2(word), stem, "{}", word);
+    }
+}
+
+// ----------------------------------------
+
+/// A word compared by its stem, keeping the original text. As the T of a
+/// WordSequence, it groups inflected forms under one type.
+#[derive(Debug,Clone)]
+pub struct StemStr<'s>{ s: &'s str, stem: String }
+
+impl<'s> StemStr<'s> {
+    pub fn new(s: &'s str, stemmer: Stemmer) -> StemStr<'s> {
+        StemStr{ s, stem: stemmer.stem(s) }
+    }
+
+    /// A word stemmed by the Porter algorithm.
+    pub fn porter(s: &'s str) -> StemStr<'s> { StemStr::new(s, Stemmer::Porter) }
+
+    /// A word stemmed by the Porter2 algorithm.
+    pub fn porter2(s: &'s str) -> StemStr<'s> { StemStr::new(s, Stemmer::Porter2) }
+
+    pub fn stem(&self) -> &str { &self.stem }
+}
+
+impl<'s> fmt::Display for StemStr<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stem)
+    }
+}
+
+impl<'s> PartialEq for StemStr<'s> {
+    fn eq(&self, other: &Self) -> bool { self.stem == other.stem }
+}
+
+impl<'s> Eq for StemStr<'s> { }
+
+impl<'s> PartialOrd for StemStr<'s> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<'s> Ord for StemStr<'s> {
+    fn cmp(&self, other: &Self) -> Ordering { self.stem.cmp(&other.stem) }
+}
+
+impl<'s> Hash for StemStr<'s> {
+    fn hash<H:Hasher>(&self, state: &mut H) { self.stem.hash(state); }
+}
+
+impl<'s> AsStr for StemStr<'s> {
+    fn as_str(&self) -> &str { self.s }
+}
+
+#[test]
+fn test_short_words() {
+    // Every word of up to five letters from a small alphabet, including
+    // those that step 0 cuts down to a letter or nothing.
+    let letters = ['a', 'e', 's', 'y', 'l', 'd', '\''];
+    let mut words = vec!(String::new());
+    for _ in 0..5 {
+        words = words.iter().flat_map(|w| letters.iter().map(move |&c| format!("{}{}", w, c))).collect();
+        for word in &words {
+            porter(word);
+            porter2(word);
+        }
+    }
+    assert_eq!(porter2("s's"), "s");
+    assert_eq!(porter2("''s"), "");
+}
+
+#[test]
+fn test_stem_str() {
+    use word_sequence::WordSequence;
+    let text = "Knitting knits; she knitted.";
+    let ws = WordSequence::new(text, StemStr::porter2, |s| s.chars().all(char::is_alphabetic));
+    assert_eq!(ws.words, vec!(0, 0, 1, 0));
+    assert_eq!(ws[0].stem(), "knit");
+    assert_eq!(ws[0].as_str(), "Knitting");
+    assert_eq!(ws.surface_forms(|_| text).forms(0).len(), 3);
+    assert_eq!(StemStr::porter("connections"), StemStr::porter("connected"));
+    assert_eq!(Stemmer::named("porter2"), Some(Stemmer::Porter2));
+    let ws = WordSequence::new("relational relation", Stemmer::Porter.key(), |s| s.len() > 1);
+    assert_eq!(ws.words, vec!(0, 0));
+}
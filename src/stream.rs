@@ -0,0 +1,372 @@
+use std;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::io::Read;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use error::{Error,Result};
+use tokenizer::Tokenizer;
+use word_sequence::{CharClass,Span,WordSequence};
+
+// Streams are read and tokenized in pieces. A piece of text can be
+// tokenized on its own up to its last whitespace: tokens never contain
+// whitespace, so no token there can continue into the next piece. The rest
+// is held back until more text arrives, up to MAX_PENDING bytes; a longer
+// run without whitespace is tokenized anyway, splitting the token that
+// spans the cut. (A tokenizer whose tokens may contain whitespace, such as
+// some RegexTokenizers, will see its tokens split at chunk boundaries.)
+
+/// The tokens of a string, with their classes and spans, found lazily.
+pub fn tokens<'a,K>(tokenizer: &'a K, text: &'a str) -> impl Iterator<Item=(&'a str,CharClass,Span)> + 'a
+    where K: Tokenizer + ?Sized {
+    tokenizer.tokens(text).map(move |token| (token.text(text), token.class, token.span))
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAX_PENDING: usize = 16 * CHUNK_SIZE;
+
+// The end of the part of a text that can be tokenized now: just after the
+// last whitespace grapheme that is followed by more text. The search starts
+// at `from`, a grapheme boundary with no such whitespace before it.
+fn complete_len(text: &str, from: usize) -> usize {
+    text[from..].grapheme_indices(true).rev()
+        .skip(1)
+        .find(|&(_,g)| CharClass::classify_grapheme(g) == CharClass::Whitespace)
+        .map(|(i,g)| from + i + g.len())
+        .unwrap_or(0)
+}
+
+// Text received but not yet tokenized.
+#[derive(Debug,Default)]
+struct Pending {
+    text: String,
+    // The position of the text in the stream.
+    offset: usize,
+    // How far the text has been searched for whitespace: the start of its
+    // last grapheme, which more text may extend.
+    searched: usize,
+}
+
+impl Pending {
+    // The end of the part of the text that can be tokenized now, searching
+    // only what arrived since the last call.
+    fn complete_len(&mut self) -> usize {
+        let end = complete_len(&self.text, self.searched);
+        if end > 0 {
+            return end;
+        }
+        let last = self.text[self.searched..].grapheme_indices(true).next_back();
+        self.searched += last.map_or(0, |(i,_)| i);
+        if self.text.len() >= MAX_PENDING { self.searched } else { 0 }
+    }
+
+    // Tokenize what can be tokenized (or, at the end of the stream,
+    // everything), giving each token's text, class and span in the stream.
+    fn drain<K,G>(&mut self, tokenizer: &K, end_of_stream: bool, mut emit: G)
+        where K: Tokenizer + ?Sized,
+              G: FnMut(&str, CharClass, Span) {
+        let end = if end_of_stream { self.text.len() } else { self.complete_len() };
+        if end == 0 { return; }
+        let part = &self.text[..end];
+        for token in tokenizer.tokens(part) {
+            let span = Span::new(self.offset + token.span.start, self.offset + token.span.end);
+            emit(token.text(part), token.class, span);
+        }
+        self.text.drain(..end);
+        self.offset += end;
+        self.searched = 0;
+    }
+}
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+// Decodes UTF-8 arriving in arbitrary pieces, holding back a character
+// split between pieces. A leading byte order mark is dropped.
+#[derive(Debug,Default)]
+struct Utf8Decoder {
+    partial: Vec<u8>,
+    // The number of bytes decoded, including any byte order mark.
+    consumed: usize,
+    started: bool,
+}
+
+impl Utf8Decoder {
+    fn decode(&mut self, bytes: &[u8], out: &mut String) -> Result<()> {
+        self.partial.extend_from_slice(bytes);
+        if !self.started {
+            // Wait until it is clear whether there is a byte order mark.
+            if self.partial.len() < BOM.len() && BOM.starts_with(&self.partial) {
+                return Ok(());
+            }
+            self.started = true;
+            if self.partial.starts_with(BOM) {
+                self.partial.drain(..BOM.len());
+                self.consumed = BOM.len();
+            }
+        }
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(s) => s.len(),
+            Err(e) => match e.error_len() {
+                None => e.valid_up_to(),
+                Some(_) => return Err(Error::InvalidUtf8(self.consumed + e.valid_up_to())),
+            },
+        };
+        out.push_str(unsafe { std::str::from_utf8_unchecked(&self.partial[..valid]) });
+        self.consumed += valid;
+        self.partial.drain(..valid);
+        Ok(())
+    }
+
+    // Check that the stream did not end partway through a character.
+    fn finish(&self) -> Result<()> {
+        if self.partial.is_empty() { Ok(()) } else { Err(Error::InvalidUtf8(self.consumed)) }
+    }
+}
+
+// ----------------------------------------
+
+/// The tokens of a UTF-8 stream, read a chunk at a time so that only the
+/// tokens of one chunk are held in memory. Spans are byte offsets in the
+/// decoded text, not counting any byte order mark.
+pub struct TokenReader<R: Read,K: Tokenizer> {
+    reader: R,
+    tokenizer: K,
+    decoder: Utf8Decoder,
+    pending: Pending,
+    ready: VecDeque<(String,CharClass,Span)>,
+    done: bool,
+}
+
+impl<R: Read,K: Tokenizer> TokenReader<R,K> {
+    pub fn new(reader: R, tokenizer: K) -> TokenReader<R,K> {
+        TokenReader {
+            reader,
+            tokenizer,
+            decoder: Utf8Decoder::default(),
+            pending: Pending::default(),
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    // Read and tokenize another chunk.
+    fn fill(&mut self) -> Result<()> {
+        let mut buf = vec!(0; CHUNK_SIZE);
+        let n = loop {
+            match self.reader.read(&mut buf) {
+                Ok(n) => break n,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Read(e)),
+            }
+        };
+        if n == 0 {
+            self.done = true;
+            self.decoder.finish()?;
+        } else {
+            self.decoder.decode(&buf[..n], &mut self.pending.text)?;
+        }
+        let ready = &mut self.ready;
+        self.pending.drain(&self.tokenizer, self.done, |s, class, span| ready.push_back((s.to_string(), class, span)));
+        Ok(())
+    }
+}
+
+impl<R: Read,K: Tokenizer> Iterator for TokenReader<R,K> {
+    type Item = Result<(String,CharClass,Span)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.ready.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.fill() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+// ----------------------------------------
+
+/// Builds a WordSequence from text supplied a piece at a time. Pieces may
+/// split tokens, or characters when read from a stream of bytes; spans are
+/// offsets in the text as a whole.
+pub struct WordSequenceBuilder<T: Hash + Eq + Clone,K,F,P> {
+    sequence: WordSequence<T>,
+    tokenizer: K,
+    to_t: F,
+    is_word: P,
+    decoder: Utf8Decoder,
+    pending: Pending,
+}
+
+impl<T,K,F,P> WordSequenceBuilder<T,K,F,P>
+    where T: Hash + Eq + Clone,
+          K: Tokenizer,
+          F: Fn(&str) -> T,
+          P: Fn(&str) -> bool {
+    pub fn new(tokenizer: K, to_t: F, is_word: P) -> WordSequenceBuilder<T,K,F,P> {
+        WordSequenceBuilder::with_sequence(WordSequence::empty(), tokenizer, to_t, is_word)
+    }
+
+    /// A builder that adds a text to an existing sequence, and its
+    /// vocabulary.
+    pub fn with_sequence(mut sequence: WordSequence<T>, tokenizer: K, to_t: F, is_word: P) -> WordSequenceBuilder<T,K,F,P> {
+        sequence.texts.push(sequence.words.len());
+        WordSequenceBuilder {
+            sequence,
+            tokenizer,
+            to_t,
+            is_word,
+            decoder: Utf8Decoder::default(),
+            pending: Pending::default(),
+        }
+    }
+
+    /// Add the next piece of text.
+    pub fn feed(&mut self, text: &str) {
+        self.pending.text.push_str(text);
+        self.tokenize(false);
+    }
+
+    /// Add the next piece of a UTF-8 byte stream.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.decoder.decode(bytes, &mut self.pending.text)?;
+        self.tokenize(false);
+        Ok(())
+    }
+
+    /// Add everything from a reader, a chunk at a time.
+    pub fn read<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        let mut buf = vec!(0; CHUNK_SIZE);
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.feed_bytes(&buf[..n])?,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => { }
+                Err(e) => return Err(Error::Read(e)),
+            }
+        }
+    }
+
+    /// The sequence so far. Tokens at the end of the text may still be
+    /// held back, waiting to see whether they continue.
+    pub fn sequence(&self) -> &WordSequence<T> { &self.sequence }
+
+    /// Tokenize any text held back and return the finished sequence.
+    pub fn finish(mut self) -> Result<WordSequence<T>> {
+        self.decoder.finish()?;
+        self.tokenize(true);
+        Ok(self.sequence)
+    }
+
+    fn tokenize(&mut self, end_of_stream: bool) {
+        let (ws, to_t, is_word) = (&mut self.sequence, &self.to_t, &self.is_word);
+        self.pending.drain(&self.tokenizer, end_of_stream, |s, class, span| {
+            if is_word(s) {
+                let w = ws.vocabulary.add(to_t(s), class);
+                ws.words.push(w);
+                ws.spans.push(span);
+            }
+        });
+    }
+}
+
+// ----------------------------------------
+
+#[cfg(test)]
+const SAMPLE: &str = "\"Tom!\" No answer.\r\n\"What's gone with that boy,  I wonder? You TOM!\" na\u{308}ive \u{4e2d}\u{6587} 3.14";
+
+// A reader that returns a few bytes at a time.
+#[cfg(test)]
+struct Trickle<'a>(&'a [u8]);
+
+#[cfg(test)]
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.0.len().min(buf.len()).min(3);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_complete_len() {
+    assert_eq!(complete_len("abc def", 0), 4);
+    assert_eq!(complete_len("abc ", 0), 0);
+    assert_eq!(complete_len("abc \r\n", 0), 4);
+    assert_eq!(complete_len("abc", 0), 0);
+    assert_eq!(complete_len("abc def ghi", 5), 8);
+    let mut pending = Pending::default();
+    for piece in &["abc", "de", "f\r", "\n", "g"] {
+        pending.text.push_str(piece);
+        let end = pending.complete_len();
+        assert_eq!(end, if *piece == "g" { 8 } else { 0 });
+    }
+    assert_eq!(pending.searched, 6);
+}
+
+#[test]
+fn test_token_reader() {
+    use tokenizer::EnglishTokenizer;
+    let expected: Vec<(String,CharClass,Span)> = tokens(&EnglishTokenizer, SAMPLE)
+        .map(|(s,c,span)| (s.to_string(), c, span))
+        .collect();
+    let streamed: Vec<(String,CharClass,Span)> = TokenReader::new(Trickle(SAMPLE.as_bytes()), EnglishTokenizer)
+        .map(|t| unwrap!( t.ok() ))
+        .collect();
+    assert_eq!(streamed, expected);
+    let mut bytes = b"ab ?c".to_vec();
+    bytes[3] = 0xff;
+    let results: Vec<_> = TokenReader::new(Trickle(&bytes), EnglishTokenizer).collect();
+    match results.last() {
+        Some(&Err(Error::InvalidUtf8(3))) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn test_builder() {
+    use tokenizer::{EnglishTokenizer,RunTokenizer};
+    let expected = WordSequence::new(SAMPLE, |s| s.to_string(), |_| true);
+    for split in (0..SAMPLE.len()).filter(|&i| SAMPLE.is_char_boundary(i)) {
+        let mut builder = WordSequenceBuilder::new(RunTokenizer, |s: &str| s.to_string(), |_: &str| true);
+        builder.feed(&SAMPLE[..split]);
+        builder.feed(&SAMPLE[split..]);
+        let ws = unwrap!( builder.finish().ok() );
+        assert_eq!(ws.words, expected.words);
+        assert_eq!(ws.spans, expected.spans);
+    }
+    let expected = WordSequence::with_tokenizer(&EnglishTokenizer, SAMPLE, |s| s.to_string(), |_| true);
+    let mut builder = WordSequenceBuilder::new(EnglishTokenizer, |s: &str| s.to_string(), |_: &str| true);
+    let mut bytes = b"\xef\xbb\xbf".to_vec();
+    bytes.extend_from_slice(SAMPLE.as_bytes());
+    unwrap!( builder.read(Trickle(&bytes)).ok() );
+    let ws = unwrap!( builder.finish().ok() );
+    assert_eq!(ws.words, expected.words);
+    assert_eq!(ws.spans, expected.spans);
+    assert_eq!(ws.texts, vec!(0));
+}
+
+#[test]
+fn test_long_run() {
+    use tokenizer::RunTokenizer;
+    // Text without whitespace is searched once, and held back only so far.
+    let mut builder = WordSequenceBuilder::new(RunTokenizer, |s: &str| s.to_string(), |_: &str| true);
+    for _ in 0..MAX_PENDING / 8 {
+        builder.feed("abcdefg");
+        builder.feed("h");
+    }
+    assert_eq!(builder.sequence().len(), 1);
+    assert_eq!(builder.sequence().spans[0], Span::new(0, MAX_PENDING - 1));
+    builder.feed("ij k");
+    let ws = unwrap!( builder.finish().ok() );
+    assert_eq!(ws.spans, vec!(Span::new(0, MAX_PENDING - 1), Span::new(MAX_PENDING - 1, MAX_PENDING + 2),
+                              Span::new(MAX_PENDING + 3, MAX_PENDING + 4)));
+}
@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use ptb::is_abbreviation;
+use word_sequence::Span;
+
+/// A division of a sequence of tokens into consecutive units, such as
+/// documents or sentences, by the index of each unit's first token.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Segmentation {
+    // The start of each unit, then the number of tokens.
+    offsets: Vec<usize>,
+}
+
+impl Segmentation {
+    /// A segmentation given the index of each unit's first token followed
+    /// by the number of tokens, as in a CorpusSequence.
+    pub fn new(offsets: Vec<usize>) -> Segmentation {
+        assert!(offsets.first() == Some(&0) && offsets.windows(2).all(|o| o[0] <= o[1]),
+                "offsets do not divide a sequence");
+        Segmentation { offsets }
+    }
+
+    /// A segmentation of `n` tokens into units ending at each token for
+    /// which `ends` holds, and at the last token.
+    pub fn from_ends<F: FnMut(usize) -> bool>(n: usize, mut ends: F) -> Segmentation {
+        let mut offsets = vec!(0);
+        if n > 0 {
+            offsets.extend((0..n - 1).filter(|&i| ends(i)).map(|i| i + 1));
+            offsets.push(n);
+        }
+        Segmentation { offsets }
+    }
+
+    /// The number of units.
+    pub fn len(&self) -> usize { self.offsets.len() - 1 }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The number of tokens.
+    pub fn tokens(&self) -> usize { self.offsets[self.len()] }
+
+    /// The index of each unit's first token; the last entry is the number
+    /// of tokens.
+    pub fn offsets(&self) -> &[usize] { &self.offsets }
+
+    /// The tokens of a unit.
+    pub fn range(&self, unit: usize) -> Range<usize> { self.offsets[unit]..self.offsets[unit+1] }
+
+    /// The unit holding a token.
+    pub fn unit_of(&self, token: usize) -> usize {
+        assert!(token < self.tokens(), "token {} out of range", token);
+        self.offsets.partition_point(|&o| o <= token) - 1
+    }
+
+    /// Whether two tokens are in the same unit.
+    pub fn same_unit(&self, i: usize, j: usize) -> bool { self.unit_of(i) == self.unit_of(j) }
+
+    // The units whose first tokens are in a range of tokens.
+    fn units_starting_in(&self, tokens: Range<usize>) -> Range<usize> {
+        let starts = &self.offsets[..self.len()];
+        starts.partition_point(|&o| o < tokens.start)..starts.partition_point(|&o| o < tokens.end)
+    }
+
+    // This segmentation with each unit also divided where another's units
+    // start, dropping empty units.
+    fn refine(&self, coarser: &Segmentation) -> Segmentation {
+        let mut offsets: Vec<usize> = self.offsets.iter().chain(&coarser.offsets).cloned().collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        Segmentation { offsets }
+    }
+}
+
+#[test]
+fn test_segmentation() {
+    let s = Segmentation::from_ends(7, |i| i == 2 || i == 3);
+    assert_eq!(s.offsets(), &[0, 3, 4, 7]);
+    assert_eq!(s.len(), 3);
+    assert_eq!(s.range(1), 3..4);
+    assert_eq!(s.unit_of(0), 0);
+    assert_eq!(s.unit_of(3), 1);
+    assert_eq!(s.unit_of(6), 2);
+    assert!(s.same_unit(4, 6) && !s.same_unit(2, 3));
+    assert_eq!(Segmentation::from_ends(3, |_| true).offsets(), &[0, 1, 2, 3]);
+    assert_eq!(Segmentation::from_ends(0, |_| true).len(), 0);
+    // An empty unit holds no tokens.
+    let s = Segmentation::new(vec!(0, 2, 2, 5));
+    assert_eq!(s.unit_of(2), 2);
+    assert_eq!(s.units_starting_in(2..5), 1..3);
+}
+
+// ----------------------------------------
+
+/// A way of finding the ends of sentences among the tokens of a text.
+pub trait SentenceSplitter {
+    /// Whether the token at `i`, of the tokens of `text` at `spans`, ends a
+    /// sentence. The text between tokens may hold tokens left out of the
+    /// sequence, such as punctuation.
+    fn ends_sentence(&self, text: &str, spans: &[Span], i: usize) -> bool;
+}
+
+impl<F> SentenceSplitter for F where F: Fn(&str, &[Span], usize) -> bool {
+    fn ends_sentence(&self, text: &str, spans: &[Span], i: usize) -> bool { self(text, spans, i) }
+}
+
+/// A splitter ending sentences at ., ! and ?, possibly followed by closing
+/// quotes and brackets, except for the period of an abbreviation or a
+/// punctuation mark followed by a word in lower case.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct PunctuationSplitter;
+
+impl SentenceSplitter for PunctuationSplitter {
+    fn ends_sentence(&self, text: &str, spans: &[Span], i: usize) -> bool {
+        let token = &text[spans[i].start..spans[i].end];
+        let next = spans.get(i + 1).map_or(text.len(), |span| span.start);
+        // The punctuation follows a word, or is the run of punctuation that
+        // the token ends.
+        let start = if token.chars().any(char::is_alphanumeric) {
+            spans[i].end
+        } else {
+            text[..spans[i].end].char_indices().rev()
+                .take_while(|&(_,c)| !c.is_alphanumeric() && !c.is_whitespace())
+                .last()
+                .map_or(spans[i].end, |(k,_)| k)
+        };
+        let after = &text[start..next];
+        let mark = match after.find(['.', '!', '?']) {
+            Some(mark) => start + mark,
+            None => return false,
+        };
+        // Only closing punctuation may follow, and then a space, unlike the
+        // periods of 3.14.
+        let closing = |c: char| c.is_whitespace() || ".!?\"')]\u{2019}\u{201d}_*".contains(c);
+        if !text[mark..next].chars().all(closing) || (next < text.len() && !text[mark..next].contains(char::is_whitespace)) {
+            return false;
+        }
+        let word: &str = text[..mark].rsplit(|c: char| !c.is_alphabetic()).next().unwrap_or("");
+        if text[mark..].starts_with('.') && is_abbreviation(word) {
+            return false;
+        }
+        !text[next..].starts_with(char::is_lowercase)
+    }
+}
+
+#[test]
+fn test_punctuation_splitter() {
+    use word_sequence::WordSequence;
+    let sentences = |text: &str, is_word: fn(&str) -> bool| {
+        let ws = WordSequence::new(text, |s| s, is_word);
+        let ends = Segmentation::from_ends(ws.len(), |i| PunctuationSplitter.ends_sentence(text, &ws.spans, i));
+        (0..ends.len()).map(|s| ends.range(s).map(|i| ws[ws.words[i]]).collect::<Vec<_>>().join(" ")).collect::<Vec<_>>()
+    };
+    let text = "\"Tom!\" No answer. Mr. Jones saw 3.14 apples? yes. He left--  (Then he came.) End";
+    assert_eq!(sentences(text, |_| true), vec!(
+        "\" Tom ! \"", "No answer .", "Mr . Jones saw 3 . 14 apples ? yes .", "He left -- ( Then he came .)", "End"));
+    assert_eq!(sentences(text, |s| s.chars().any(char::is_alphanumeric)), vec!(
+        "Tom", "No answer", "Mr Jones saw 3 14 apples yes", "He left Then he came", "End"));
+}
+
+// ----------------------------------------
+
+/// The levels of the structure of a sequence, from the largest.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Level {
+    Document,
+    Paragraph,
+    Sentence,
+}
+
+/// The documents, paragraphs and sentences of a sequence of tokens, each
+/// dividing the one above: no paragraph crosses the end of a document, and
+/// no sentence the end of a paragraph.
+#[derive(Clone,Debug)]
+pub struct Structure {
+    pub documents: Segmentation,
+    pub paragraphs: Segmentation,
+    pub sentences: Segmentation,
+}
+
+impl Structure {
+    /// The structure given by three segmentations of the same tokens, each
+    /// level divided further wherever a level above it is.
+    pub fn new(documents: Segmentation, paragraphs: Segmentation, sentences: Segmentation) -> Structure {
+        assert!(documents.tokens() == paragraphs.tokens() && paragraphs.tokens() == sentences.tokens(),
+                "segmentations of different sequences");
+        let paragraphs = paragraphs.refine(&documents);
+        let sentences = sentences.refine(&paragraphs);
+        Structure { documents, paragraphs, sentences }
+    }
+
+    /// The structure of documents whose tokens are at `spans`, divided into
+    /// documents by `offsets` (as in a CorpusSequence), where `text(doc)`
+    /// is the text of a document. Paragraphs are separated by blank lines,
+    /// and sentences found by a splitter.
+    pub fn build<'a,D,S>(offsets: &[usize], spans: &[Span], text: D, splitter: &S) -> Structure
+        where D: Fn(usize) -> &'a str,
+              S: SentenceSplitter + ?Sized {
+        let documents = Segmentation::new(offsets.to_vec());
+        let mut paragraph_ends = vec!(false; spans.len());
+        let mut sentence_ends = vec!(false; spans.len());
+        for doc in 0..documents.len() {
+            let range = documents.range(doc);
+            let (text, spans) = (text(doc), &spans[range.clone()]);
+            for (i,pair) in spans.windows(2).enumerate() {
+                paragraph_ends[range.start + i] = is_blank(&text[pair[0].end..pair[1].start]);
+            }
+            for i in 0..spans.len() {
+                sentence_ends[range.start + i] = splitter.ends_sentence(text, spans, i);
+            }
+        }
+        let paragraphs = Segmentation::from_ends(spans.len(), |i| paragraph_ends[i]);
+        let sentences = Segmentation::from_ends(spans.len(), |i| sentence_ends[i]);
+        Structure::new(documents, paragraphs, sentences)
+    }
+
+    /// The offsets of the units of a level in documents divided by
+    /// `offsets`, as for `build` with a PunctuationSplitter. The documents
+    /// are their own units, and need no text.
+    pub fn unit_offsets<'a,D>(offsets: &[usize], spans: &[Span], text: D, level: Level) -> Vec<usize>
+        where D: Fn(usize) -> &'a str {
+        match level {
+            Level::Document => offsets.to_vec(),
+            level => Structure::build(offsets, spans, text, &PunctuationSplitter).layer(level).offsets().to_vec(),
+        }
+    }
+
+    pub fn layer(&self, level: Level) -> &Segmentation {
+        match level {
+            Level::Document => &self.documents,
+            Level::Paragraph => &self.paragraphs,
+            Level::Sentence => &self.sentences,
+        }
+    }
+
+    /// The unit of a level holding a token.
+    pub fn unit_of(&self, level: Level, token: usize) -> usize { self.layer(level).unit_of(token) }
+
+    /// The tokens of a unit of a level.
+    pub fn range(&self, level: Level, unit: usize) -> Range<usize> { self.layer(level).range(unit) }
+
+    pub fn document_of(&self, token: usize) -> usize { self.documents.unit_of(token) }
+    pub fn paragraph_of(&self, token: usize) -> usize { self.paragraphs.unit_of(token) }
+    pub fn sentence_of(&self, token: usize) -> usize { self.sentences.unit_of(token) }
+
+    /// The paragraphs of a document.
+    pub fn paragraphs_of(&self, doc: usize) -> Range<usize> {
+        self.paragraphs.units_starting_in(self.documents.range(doc))
+    }
+
+    /// The sentences of a paragraph.
+    pub fn sentences_of(&self, paragraph: usize) -> Range<usize> {
+        self.sentences.units_starting_in(self.paragraphs.range(paragraph))
+    }
+}
+
+// Whether the text between two tokens holds a blank line.
+fn is_blank(between: &str) -> bool {
+    let mut newline = false;
+    for c in between.chars() {
+        match c {
+            '\n' if newline => return true,
+            '\n' => newline = true,
+            c if !c.is_whitespace() => newline = false,
+            _ => { }
+        }
+    }
+    false
+}
+
+#[test]
+fn test_structure() {
+    use word_sequence::WordSequence;
+    let texts = ["One. Two\nlines.\n\n  Next para-\ngraph.\r\n \r\nLast", "", "Another document. Mr. Smith."];
+    let mut ws = WordSequence::empty();
+    let mut offsets = vec!(0);
+    for text in &texts {
+        ws.append(text, |s| s, |_| true);
+        offsets.push(ws.len());
+    }
+    let spans: Vec<Span> = ws.spans.clone();
+    let structure = Structure::build(&offsets, &spans, |doc| texts[doc], &PunctuationSplitter);
+    let words = |level: Level, unit: usize| structure.range(level, unit).map(|i| ws[ws.words[i]]).collect::<Vec<_>>().join(" ");
+    assert_eq!(structure.documents.len(), 3);
+    assert_eq!(structure.paragraphs.len(), 4);
+    assert_eq!(words(Level::Paragraph, 1), "Next para - graph .");
+    assert_eq!(words(Level::Paragraph, 3), "Another document . Mr . Smith .");
+    assert_eq!(structure.sentences.len(), 6);
+    assert_eq!(words(Level::Sentence, 1), "Two lines .");
+    assert_eq!(words(Level::Sentence, 3), "Last");
+    assert_eq!(words(Level::Sentence, 5), "Mr . Smith .");
+    assert_eq!(structure.paragraphs_of(0), 0..3);
+    assert_eq!(structure.paragraphs_of(1), 3..3);
+    assert_eq!(structure.paragraphs_of(2), 3..4);
+    assert_eq!(structure.sentences_of(0), 0..2);
+    let last = ws.len() - 1;
+    assert_eq!(structure.document_of(last), 2);
+    assert_eq!(structure.paragraph_of(last), 3);
+    assert_eq!(structure.sentence_of(last), 5);
+    assert_eq!(structure.unit_of(Level::Sentence, 2), 1);
+    assert_eq!(Structure::unit_offsets(&offsets, &spans, |doc| texts[doc], Level::Document), offsets);
+    assert_eq!(Structure::unit_offsets(&offsets, &spans, |doc| texts[doc], Level::Sentence), structure.sentences.offsets());
+}
+
+#[test]
+fn test_nesting() {
+    // Sentences are divided at paragraphs, and paragraphs at documents.
+    let structure = Structure::new(Segmentation::new(vec!(0, 3, 6)),
+                                   Segmentation::new(vec!(0, 2, 6)),
+                                   Segmentation::new(vec!(0, 6)));
+    assert_eq!(structure.paragraphs.offsets(), &[0, 2, 3, 6]);
+    assert_eq!(structure.sentences.offsets(), &[0, 2, 3, 6]);
+}
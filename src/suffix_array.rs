@@ -0,0 +1,229 @@
+use std::cmp::min;
+use std::io::Write;
+
+use binary::{checksum,invalid,put,put_u64s,u64_at};
+use error::{Error,Result};
+use text_source;
+use word_sequence::Word;
+
+/// A suffix array over a sequence of words: the starting positions of its
+/// suffixes in sorted order, with the length of the common prefix of each
+/// suffix and the one before it. Any n-gram's occurrences are adjacent in
+/// the array, so they can be found by binary search.
+#[derive(Debug)]
+pub struct SuffixArray<'a> {
+    words: &'a [Word],
+    suffixes: Vec<usize>,
+    lcp: Vec<usize>,
+}
+
+impl<'a> SuffixArray<'a> {
+    /// Build the suffix array of a sequence, by prefix doubling.
+    pub fn new(words: &'a [Word]) -> SuffixArray<'a> {
+        let n = words.len();
+        if n == 0 {
+            return SuffixArray { words, suffixes: vec!(), lcp: vec!() };
+        }
+        // Rank the suffixes by their first word; 0 is kept for the end of
+        // the sequence.
+        let mut ids: Vec<Word> = words.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut rank: Vec<usize> = words.iter().map(|w| unwrap!( ids.binary_search(w).ok() ) + 1).collect();
+        let mut suffixes: Vec<usize> = (0..n).collect();
+        let mut next = vec!(0; n);
+        let mut k = 1;
+        loop {
+            // Rank by the first 2k words, given the ranks by the first k.
+            let key = |i: usize| (rank[i], if i + k < n { rank[i+k] } else { 0 });
+            suffixes.sort_unstable_by_key(|&i| key(i));
+            next[suffixes[0]] = 1;
+            for j in 1..n {
+                next[suffixes[j]] = next[suffixes[j-1]] + (key(suffixes[j-1]) < key(suffixes[j])) as usize;
+            }
+            std::mem::swap(&mut rank, &mut next);
+            if rank[suffixes[n-1]] == n || k >= n { break; }
+            k *= 2;
+        }
+        let lcp = lcp(words, &suffixes);
+        SuffixArray { words, suffixes, lcp }
+    }
+
+    /// The number of suffixes, which is the length of the sequence.
+    pub fn len(&self) -> usize { self.suffixes.len() }
+    pub fn is_empty(&self) -> bool { self.suffixes.is_empty() }
+
+    /// The starting positions of the suffixes, in sorted order.
+    pub fn suffixes(&self) -> &[usize] { &self.suffixes }
+
+    /// The length of the prefix each suffix shares with the one before it.
+    pub fn lcp(&self) -> &[usize] { &self.lcp }
+
+    // The range of the array holding the suffixes that start with an n-gram.
+    fn range(&self, ngram: &[Word]) -> (usize,usize) {
+        let words = self.words;
+        let prefix = |s: usize| &words[s..min(s + ngram.len(), words.len())];
+        let lower = self.suffixes.partition_point(|&s| prefix(s) < ngram);
+        let upper = self.suffixes.partition_point(|&s| prefix(s) <= ngram);
+        (lower, upper)
+    }
+
+    /// The number of times an n-gram occurs.
+    pub fn count(&self, ngram: &[Word]) -> usize {
+        let (lower, upper) = self.range(ngram);
+        upper - lower
+    }
+
+    /// The positions at which an n-gram occurs, in order.
+    pub fn positions(&self, ngram: &[Word]) -> Vec<usize> {
+        let (lower, upper) = self.range(ngram);
+        let mut positions = self.suffixes[lower..upper].to_vec();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Every n-gram of length `n` occurring at least `min_count` times, with
+    /// its count, in suffix order.
+    pub fn repeated(&self, n: usize, min_count: usize) -> Vec<(&'a [Word],usize)> {
+        let mut repeated = Vec::new();
+        let mut i = 0;
+        while i < self.len() {
+            let s = self.suffixes[i];
+            let mut j = i + 1;
+            while j < self.len() && self.lcp[j] >= n { j += 1; }
+            if s + n <= self.words.len() && j - i >= min_count {
+                repeated.push((&self.words[s..s+n], j - i));
+            }
+            i = j;
+        }
+        repeated
+    }
+}
+
+// The longest common prefix of each suffix and the one before it, by
+// Kasai's algorithm.
+fn lcp(words: &[Word], suffixes: &[usize]) -> Vec<usize> {
+    let n = suffixes.len();
+    let mut rank = vec!(0; n);
+    for (i,&s) in suffixes.iter().enumerate() { rank[s] = i; }
+    let mut lcp = vec!(0; n);
+    let mut h: usize = 0;
+    for s in 0..n {
+        if rank[s] > 0 {
+            let t = suffixes[rank[s]-1];
+            while s + h < n && t + h < n && words[s+h] == words[t+h] { h += 1; }
+            lcp[rank[s]] = h;
+            h = h.saturating_sub(1);
+        } else {
+            h = 0;
+        }
+    }
+    lcp
+}
+
+#[test]
+fn test_suffix_array() {
+    // a b a b c a b
+    let words = [0, 1, 0, 1, 2, 0, 1];
+    let sa = SuffixArray::new(&words);
+    assert_eq!(sa.suffixes(), &[5, 0, 2, 6, 1, 3, 4]);
+    assert_eq!(sa.lcp(), &[0, 2, 2, 0, 1, 1, 0]);
+    assert_eq!(sa.count(&[0, 1]), 3);
+    assert_eq!(sa.positions(&[0, 1]), vec!(0, 2, 5));
+    assert_eq!(sa.positions(&[1, 0, 1]), vec!(1));
+    assert_eq!(sa.count(&[2, 1]), 0);
+    assert_eq!(sa.count(&[1, 2, 0, 1, 0]), 0);
+    assert_eq!(sa.repeated(2, 2), vec!((&[0, 1][..], 3)));
+    assert_eq!(sa.repeated(1, 3), vec!((&[0][..], 3), (&[1][..], 3)));
+    assert_eq!(sa.repeated(3, 2), vec!());
+    assert_eq!(SuffixArray::new(&[]).count(&[0]), 0);
+}
+
+#[test]
+fn test_against_scan() {
+    use word_sequence::WordSequence;
+    let corpus = unwrap!( ::corpus::Corpus::from_paths(&["data/training-1.txt"]).ok() );
+    let ws = WordSequence::new(&corpus.documents[0].text, |s| s, |_| true);
+    let sa = SuffixArray::new(&ws.words);
+    for ngram in ws.words.windows(3).step_by(97) {
+        let scanned: Vec<usize> = ws.words.windows(3).enumerate()
+            .filter(|&(_,w)| w == ngram)
+            .map(|(i,_)| i)
+            .collect();
+        assert_eq!(sa.positions(ngram), scanned);
+    }
+    let total: usize = sa.repeated(1, 1).iter().map(|&(_,c)| c).sum();
+    assert_eq!(total, ws.words.len());
+}
+
+// ----------------------------------------
+
+// A suffix array on disk: magic, version (u32), flags (u32), the number of
+// suffixes and a checksum of the words (u64s), then the suffixes and the
+// LCP array, as u64s.
+
+/// The first bytes of a suffix array file.
+pub const MAGIC: &[u8] = b"NLFSUF\0\0";
+/// The version of the format written by `write`.
+pub const VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 32;
+
+impl<'a> SuffixArray<'a> {
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        put(out, MAGIC)?;
+        put(out, &VERSION.to_le_bytes())?;
+        put(out, &0u32.to_le_bytes())?;
+        put(out, &(self.len() as u64).to_le_bytes())?;
+        put(out, &checksum(self.words).to_le_bytes())?;
+        put_u64s(out, self.suffixes.iter().cloned())?;
+        put_u64s(out, self.lcp.iter().cloned())?;
+        out.flush().map_err(Error::Write)
+    }
+
+    /// Read a suffix array written by `write` for a sequence of words.
+    pub fn from_bytes(bytes: &[u8], words: &'a [Word]) -> Result<SuffixArray<'a>> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return invalid("bad header");
+        }
+        let version = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        if version != VERSION {
+            return Err(Error::InvalidFormat(format!("unsupported version {}", version)));
+        }
+        let n = words.len();
+        if u64_at(bytes, 16) != n as u64 || u64_at(bytes, 24) != checksum(words) {
+            return invalid("suffix array does not match the words");
+        }
+        if bytes.len() != HEADER_LEN + 16 * n {
+            return invalid("truncated file");
+        }
+        let suffixes: Vec<usize> = (0..n).map(|i| u64_at(bytes, HEADER_LEN + 8 * i) as usize).collect();
+        let lcp: Vec<usize> = (0..n).map(|i| u64_at(bytes, HEADER_LEN + 8 * (n + i)) as usize).collect();
+        if suffixes.iter().any(|&s| s >= n) || lcp.iter().any(|&l| l > n) {
+            return invalid("bad suffix");
+        }
+        Ok(SuffixArray { words, suffixes, lcp })
+    }
+
+    /// Read a suffix array file for a sequence of words.
+    pub fn open(filename: &str, words: &'a [Word]) -> Result<SuffixArray<'a>> {
+        let source = text_source::open(filename)?;
+        SuffixArray::from_bytes(source.get_slice(), words)
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let words = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5];
+    let sa = SuffixArray::new(&words);
+    let mut bytes = Vec::new();
+    unwrap!( sa.write(&mut bytes).ok() );
+    let loaded = unwrap!( SuffixArray::from_bytes(&bytes, &words).ok() );
+    assert_eq!(loaded.suffixes(), sa.suffixes());
+    assert_eq!(loaded.lcp(), sa.lcp());
+    assert!(SuffixArray::from_bytes(&bytes, &words[1..]).is_err());
+    let mut other = words;
+    other.swap(0, 1);
+    assert!(SuffixArray::from_bytes(&bytes, &other).is_err());
+    assert!(SuffixArray::from_bytes(&bytes[..bytes.len()-1], &words).is_err());
+}
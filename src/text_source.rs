@@ -0,0 +1,127 @@
+use std;
+use std::fs::File;
+use std::io::{self,Read};
+
+use encoding::{self,Decoded,Encoding,ErrorPolicy};
+use error::{Error,Result};
+use mmap::MappedRegion;
+
+/// A body of text, available as a contiguous block of bytes.
+pub trait TextSource {
+    fn get_slice(&self) -> &[u8];
+
+    /// The text, if it is valid UTF-8.
+    fn get_str(&self) -> Result<&str> {
+        std::str::from_utf8(self.get_slice()).map_err(Error::from)
+    }
+
+    /// The text, decoded from whatever encoding it appears to be in.
+    fn decode(&self, policy: ErrorPolicy) -> Result<Decoded<'_>> {
+        encoding::decode(self.get_slice(), policy)
+    }
+
+    /// The text, decoded from an encoding if one is given, or else from
+    /// whatever encoding it appears to be in.
+    fn decode_as(&self, encoding: Option<Encoding>, policy: ErrorPolicy) -> Result<Decoded<'_>> {
+        match encoding {
+            Some(encoding) => encoding::decode_as(self.get_slice(), encoding, policy),
+            None => self.decode(policy),
+        }
+    }
+}
+
+impl TextSource for MappedRegion {
+    fn get_slice(&self) -> &[u8] { MappedRegion::get_slice(self) }
+}
+
+// In-memory text.
+
+impl TextSource for [u8] {
+    fn get_slice(&self) -> &[u8] { self }
+}
+
+impl TextSource for Vec<u8> {
+    fn get_slice(&self) -> &[u8] { self }
+}
+
+impl TextSource for str {
+    fn get_slice(&self) -> &[u8] { self.as_bytes() }
+}
+
+impl TextSource for String {
+    fn get_slice(&self) -> &[u8] { self.as_bytes() }
+}
+
+#[test]
+fn test_in_memory() {
+    let s = String::from("abc");
+    assert_eq!(s.get_slice(), b"abc");
+    assert_eq!(unwrap!( TextSource::get_str(&s).ok() ), "abc");
+    let v: Vec<u8> = vec!(b'a', 0xff);
+    assert!(v.get_str().is_err());
+    assert_eq!(unwrap!( v.decode(ErrorPolicy::Strict).ok() ).text, "a\u{ff}");
+    assert!(v.decode_as(Some(Encoding::Utf8), ErrorPolicy::Strict).is_err());
+    assert_eq!(unwrap!( v.decode_as(Some(Encoding::Utf8), ErrorPolicy::Replace).ok() ).text, "a\u{fffd}");
+}
+
+// -------------------------------------
+
+/// Text read completely into memory from a stream, for inputs such as pipes
+/// and standard input that cannot be mapped.
+pub struct BufferedText {
+    buf: Vec<u8>,
+}
+
+impl BufferedText {
+    pub fn read<R: Read>(mut reader: R) -> Result<BufferedText> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).map_err(Error::Read)?;
+        Ok( BufferedText { buf } )
+    }
+
+    pub fn stdin() -> Result<BufferedText> {
+        let stdin = io::stdin();
+        let lock = stdin.lock();
+        BufferedText::read(lock)
+    }
+}
+
+impl TextSource for BufferedText {
+    fn get_slice(&self) -> &[u8] { &self.buf }
+}
+
+#[test]
+fn test_buffered_text() {
+    let bt = unwrap!( BufferedText::read(&b"some text"[..]).ok() );
+    assert_eq!(unwrap!( bt.get_str().ok() ), "some text");
+}
+
+// -------------------------------------
+
+/// Open a named text source. The name `-` is standard input; regular,
+/// non-empty files are mapped into memory and anything else (pipes,
+/// devices, empty files) is read into a buffer.
+pub fn open(filename: &str) -> Result<Box<dyn TextSource>> {
+    if filename == "-" {
+        return Ok( Box::new(BufferedText::stdin()?) );
+    }
+    let file = File::open(filename).map_err(|e| Error::Open(filename.to_string(), e))?;
+    let metadata = file.metadata().map_err(Error::Stat)?;
+    if metadata.is_file() && metadata.len() > 0 {
+        Ok( Box::new(MappedRegion::mmap(filename)?) )
+    } else {
+        Ok( Box::new(BufferedText::read(file)?) )
+    }
+}
+
+#[test]
+fn test_open() {
+    let src = unwrap!( open("Cargo.toml").ok() );
+    assert_eq!(unwrap!( unwrap!( src.get_str().ok() ).lines().next() ), "[package]");
+    let empty = unwrap!( open("/dev/null").ok() );
+    assert_eq!(empty.get_slice().len(), 0);
+    match open("nonexistent") {
+        Err(Error::Open(_, _)) => { }
+        _ => panic!("open of nonexistent file did not fail"),
+    }
+}
@@ -0,0 +1,451 @@
+use std;
+use std::hash::Hash;
+use std::io::Write;
+
+use binary::{aligned,invalid,padding,put,put_u64s,u64_at,usizes};
+use corpus::{Corpus,CorpusSequence};
+use error::{Error,Result};
+use text_source::{self,TextSource};
+use tokenizer;
+use vocabulary::{Vocabulary,UNKNOWN};
+use word_sequence::{CharClass,Span,Word,WordSequence};
+
+// A tokenized corpus on disk. Every number is a little-endian u64 and every
+// section starts on an 8-byte boundary, so that the id, span and offset
+// arrays can be used in place once the file is mapped.
+//
+//   magic, version (u32), flags (u32): the tokenizer, as one more than its
+//       index in tokenizer::NAMES, or 0 if not recorded
+//   number of types, tokens and documents; the unknown word's id; the
+//       lengths of the string and text sections
+//   words       [tokens]       token ids
+//   spans       [2 * tokens]   byte spans, relative to each document's text
+//   doc_tokens  [docs + 1]     index of each document's first token
+//   doc_text    [docs + 1]     offset of each document's text
+//   counts      [types]        number of occurrences of each type
+//   type_ends   [types]        end of each type in the string section
+//   name_ends   [docs]         end of each document name in the string section
+//   classes     [types]        one byte per type, padded
+//   strings                    the types, then the document names, padded
+//   text                       the document texts
+
+/// The first bytes of a tokenized file.
+pub const MAGIC: &[u8] = b"NLFTOK\0\0";
+/// The version of the format written by `write`.
+pub const VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 64;
+
+/// Whether a block of bytes looks like a tokenized file.
+pub fn is_tokenized(bytes: &[u8]) -> bool { bytes.starts_with(MAGIC) }
+
+const CLASSES: [CharClass; 7] = [
+    CharClass::Alphabetic, CharClass::Numeric, CharClass::Whitespace, CharClass::Control,
+    CharClass::Other, CharClass::Ideographic, CharClass::Emoji,
+];
+
+fn class_code(class: CharClass) -> u8 {
+    unwrap!( CLASSES.iter().position(|&c| c == class) ) as u8
+}
+
+// ----------------------------------------
+
+/// Write a tokenized corpus: its documents and the sequence produced by
+/// tokenizing them with the tokenizer of a name, as given to
+/// `tokenizer::named`. Other names are not recorded.
+pub fn write<W,T>(out: &mut W, corpus: &Corpus, cs: &CorpusSequence<T>, tokenizer: &str) -> Result<()>
+    where W: Write, T: Hash + Eq + Clone + AsRef<str> {
+    assert_eq!(corpus.len(), cs.len(), "sequence does not belong to corpus");
+    let vocabulary = &cs.sequence.vocabulary;
+    let mut strings = Vec::new();
+    let mut type_ends = Vec::with_capacity(vocabulary.len());
+    for (_,t) in vocabulary.iter() {
+        strings.extend_from_slice(t.as_ref().as_bytes());
+        type_ends.push(strings.len());
+    }
+    let mut name_ends = Vec::with_capacity(corpus.len());
+    for document in &corpus.documents {
+        strings.extend_from_slice(document.name.as_bytes());
+        name_ends.push(strings.len());
+    }
+    let mut doc_text = vec!(0);
+    for document in &corpus.documents {
+        let end = doc_text[doc_text.len()-1] + document.text.len();
+        doc_text.push(end);
+    }
+    let classes: Vec<u8> = (0..vocabulary.len())
+        .map(|w| class_code(unwrap!( vocabulary.class_of(w) )))
+        .collect();
+
+    let text_len = corpus.documents.iter().map(|d| d.text.len()).sum();
+
+    put(out, MAGIC)?;
+    put(out, &VERSION.to_le_bytes())?;
+    let flags = tokenizer::NAMES.iter().position(|&name| name == tokenizer).map_or(0, |i| i + 1) as u32;
+    put(out, &flags.to_le_bytes())?;
+    put_u64s(out, vec!(vocabulary.len(), cs.sequence.words.len(), corpus.len(),
+                       vocabulary.unknown(), strings.len(), text_len))?;
+    put_u64s(out, cs.sequence.words.iter().cloned())?;
+    put_u64s(out, cs.sequence.spans.iter().flat_map(|s| [s.start, s.end]))?;
+    put_u64s(out, cs.offsets.iter().cloned())?;
+    put_u64s(out, doc_text)?;
+    put_u64s(out, vocabulary.counts().iter().cloned())?;
+    put_u64s(out, type_ends)?;
+    put_u64s(out, name_ends)?;
+    put(out, &classes)?;
+    put(out, &[0; 8][..padding(classes.len())])?;
+    put(out, &strings)?;
+    put(out, &[0; 8][..padding(strings.len())])?;
+    for document in &corpus.documents {
+        put(out, document.text.as_bytes())?;
+    }
+    out.flush().map_err(Error::Write)
+}
+
+// ----------------------------------------
+
+// The sizes and positions of the sections of a tokenized file.
+#[derive(Clone,Copy,Debug)]
+struct Layout {
+    types: usize,
+    tokens: usize,
+    docs: usize,
+    unknown: Word,
+    tokenizer: Option<&'static str>,
+    words: usize,
+    spans: usize,
+    doc_tokens: usize,
+    doc_text: usize,
+    counts: usize,
+    type_ends: usize,
+    name_ends: usize,
+    classes: usize,
+    strings: usize,
+    strings_len: usize,
+    text: usize,
+    text_len: usize,
+}
+
+impl Layout {
+    fn read(bytes: &[u8]) -> Result<Layout> {
+        if bytes.len() < HEADER_LEN || !is_tokenized(bytes) {
+            return invalid("bad header");
+        }
+        let version = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        if version != VERSION {
+            return Err(Error::InvalidFormat(format!("unsupported version {}", version)));
+        }
+        let tokenizer = match u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize {
+            0 => None,
+            i if i <= tokenizer::NAMES.len() => Some(tokenizer::NAMES[i-1]),
+            _ => return invalid("bad tokenizer"),
+        };
+        let field = |i: usize| u64_at(bytes, 16 + 8 * i);
+        if (0..6).any(|i| i != 3 && field(i) > bytes.len() as u64) {
+            return invalid("bad header");
+        }
+        let (types, tokens, docs) = (field(0) as usize, field(1) as usize, field(2) as usize);
+        let (strings_len, text_len) = (field(4) as usize, field(5) as usize);
+        let unknown = match field(3) {
+            u64::MAX => UNKNOWN,
+            w if w < types as u64 => w as usize,
+            _ => return invalid("bad unknown word"),
+        };
+        let words = HEADER_LEN;
+        let spans = words + 8 * tokens;
+        let doc_tokens = spans + 16 * tokens;
+        let doc_text = doc_tokens + 8 * (docs + 1);
+        let counts = doc_text + 8 * (docs + 1);
+        let type_ends = counts + 8 * types;
+        let name_ends = type_ends + 8 * types;
+        let classes = name_ends + 8 * docs;
+        let strings = classes + types + padding(types);
+        let text = strings + strings_len + padding(strings_len);
+        if text + text_len != bytes.len() {
+            return invalid("truncated file");
+        }
+        Ok(Layout {
+            types, tokens, docs, unknown, tokenizer, words, spans, doc_tokens, doc_text, counts,
+            type_ends, name_ends, classes, strings, strings_len, text, text_len,
+        })
+    }
+}
+
+/// A tokenized file, loaded for use in place: the token ids, spans and
+/// document offsets are views into the file rather than copies.
+pub struct TokenizedCorpus {
+    source: Box<dyn TextSource>,
+    layout: Layout,
+}
+
+impl TokenizedCorpus {
+    /// Open a tokenized file, mapping it into memory if possible.
+    pub fn open(filename: &str) -> Result<TokenizedCorpus> {
+        TokenizedCorpus::from_source(text_source::open(filename)?)
+    }
+
+    /// Use the contents of a source as a tokenized file, after checking it
+    /// is consistent.
+    pub fn from_source(source: Box<dyn TextSource>) -> Result<TokenizedCorpus> {
+        let source = aligned(source)?;
+        let layout = Layout::read(source.get_slice())?;
+        let tc = TokenizedCorpus { source, layout };
+        tc.validate()?;
+        Ok(tc)
+    }
+
+    // Check everything the accessors rely on: offsets are in order and in
+    // bounds, strings are UTF-8 and split at character boundaries, and ids
+    // name types.
+    fn validate(&self) -> Result<()> {
+        let l = &self.layout;
+        let bytes = self.source.get_slice();
+        let strings = std::str::from_utf8(&bytes[l.strings..l.strings+l.strings_len])
+            .map_err(|_| Error::InvalidFormat("invalid UTF-8 in strings".to_string()))?;
+        let text = std::str::from_utf8(&bytes[l.text..l.text+l.text_len])
+            .map_err(|_| Error::InvalidFormat("invalid UTF-8 in text".to_string()))?;
+        let ends = self.usizes(l.type_ends, l.types).iter().chain(self.usizes(l.name_ends, l.docs));
+        let mut start = 0;
+        for &end in ends {
+            if end < start || end > strings.len() || !strings.is_char_boundary(end) {
+                return invalid("bad string offsets");
+            }
+            start = end;
+        }
+        if bytes[l.classes..l.classes+l.types].iter().any(|&c| c as usize >= CLASSES.len()) {
+            return invalid("bad class");
+        }
+        if self.words().iter().any(|&w| w >= l.types && w != UNKNOWN) {
+            return invalid("bad word id");
+        }
+        let (doc_tokens, doc_text) = (self.offsets(), self.usizes(l.doc_text, l.docs + 1));
+        if doc_tokens[0] != 0 || doc_tokens[l.docs] != l.tokens
+            || doc_text[0] != 0 || doc_text[l.docs] != l.text_len {
+            return invalid("bad document offsets");
+        }
+        for doc in 0..l.docs {
+            if doc_tokens[doc] > doc_tokens[doc+1] || doc_text[doc] > doc_text[doc+1]
+                || !text.is_char_boundary(doc_text[doc]) {
+                return invalid("bad document offsets");
+            }
+            let text = &text[doc_text[doc]..doc_text[doc+1]];
+            for span in &self.spans()[doc_tokens[doc]..doc_tokens[doc+1]] {
+                if span.start > span.end || span.end > text.len()
+                    || !text.is_char_boundary(span.start) || !text.is_char_boundary(span.end) {
+                    return invalid("bad span");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn usizes(&self, offset: usize, n: usize) -> &[usize] { usizes(self.source.get_slice(), offset, n) }
+
+    fn str_at(&self, start: usize, end: usize) -> &str {
+        let bytes = &self.source.get_slice()[start..end];
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+    }
+
+    /// The number of documents.
+    pub fn len(&self) -> usize { self.layout.docs }
+    pub fn is_empty(&self) -> bool { self.layout.docs == 0 }
+
+    /// The name of the tokenizer the file was written with, if recorded.
+    pub fn tokenizer(&self) -> Option<&'static str> { self.layout.tokenizer }
+
+    /// The token ids of every document.
+    pub fn words(&self) -> &[Word] { self.usizes(self.layout.words, self.layout.tokens) }
+
+    /// The span of every token, relative to its document's text.
+    pub fn spans(&self) -> &[Span] {
+        let bytes = &self.source.get_slice()[self.layout.spans..self.layout.doc_tokens];
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const Span, self.layout.tokens) }
+    }
+
+    /// The index of each document's first token; the last entry is the total
+    /// number of tokens.
+    pub fn offsets(&self) -> &[usize] { self.usizes(self.layout.doc_tokens, self.layout.docs + 1) }
+
+    /// The token ids of one document.
+    pub fn document(&self, doc: usize) -> &[Word] {
+        let offsets = self.offsets();
+        &self.words()[offsets[doc]..offsets[doc+1]]
+    }
+
+    /// The spans of one document's tokens.
+    pub fn document_spans(&self, doc: usize) -> &[Span] {
+        let offsets = self.offsets();
+        &self.spans()[offsets[doc]..offsets[doc+1]]
+    }
+
+    pub fn document_name(&self, doc: usize) -> &str {
+        let l = &self.layout;
+        let ends = self.usizes(l.name_ends, l.docs);
+        let start = if doc > 0 { ends[doc-1] } else if l.types > 0 { self.type_end(l.types - 1) } else { 0 };
+        self.str_at(l.strings + start, l.strings + ends[doc])
+    }
+
+    pub fn document_text(&self, doc: usize) -> &str {
+        let l = &self.layout;
+        let offsets = self.usizes(l.doc_text, l.docs + 1);
+        self.str_at(l.text + offsets[doc], l.text + offsets[doc+1])
+    }
+
+    fn type_end(&self, w: Word) -> usize { self.usizes(self.layout.type_ends, self.layout.types)[w] }
+
+    /// The string of a word type.
+    pub fn word(&self, w: Word) -> &str {
+        let start = if w > 0 { self.type_end(w - 1) } else { 0 };
+        self.str_at(self.layout.strings + start, self.layout.strings + self.type_end(w))
+    }
+
+    pub fn class_of(&self, w: Word) -> CharClass {
+        CLASSES[self.source.get_slice()[self.layout.classes + w] as usize]
+    }
+
+    /// The vocabulary the file was written with, borrowing its strings.
+    pub fn vocabulary(&self) -> Vocabulary<&str> {
+        let counts = self.usizes(self.layout.counts, self.layout.types);
+        let mut vocabulary = Vocabulary::new();
+        for (w,&count) in counts.iter().enumerate() {
+            vocabulary.add_count(self.word(w), self.class_of(w), count);
+        }
+        if self.layout.unknown != UNKNOWN {
+            vocabulary.set_unknown(self.word(self.layout.unknown), self.class_of(self.layout.unknown));
+        }
+        vocabulary
+    }
+
+    /// Append every document's tokens to a WordSequence, keyed by `to_t`
+    /// and keeping only those satisfying `is_word`, as if the original
+    /// texts were being tokenized. Each type is converted once. Returns the
+    /// index in `ws.words` of each document's first token, followed by the
+    /// end of the last.
+    pub fn append_to<'a,T,F,P>(&'a self, ws: &mut WordSequence<T>, to_t: F, is_word: P) -> Vec<usize>
+        where T: Hash + Eq + Clone,
+              F: Fn(&'a str) -> T,
+              P: Fn(&'a str) -> bool {
+        let keys: Vec<Option<T>> = (0..self.layout.types)
+            .map(|w| if is_word(self.word(w)) { Some(to_t(self.word(w))) } else { None })
+            .collect();
+        let (words, spans) = (self.words(), self.spans());
+        let mut offsets = vec!(ws.words.len());
+        for doc in 0..self.len() {
+            ws.texts.push(ws.words.len());
+            for i in self.offsets()[doc]..self.offsets()[doc+1] {
+                let key = match words[i] {
+                    UNKNOWN => None,
+                    w => keys[w].clone().map(|k| (k, self.class_of(w))),
+                };
+                if let Some((key, class)) = key {
+                    let w = ws.vocabulary.add(key, class);
+                    ws.words.push(w);
+                    ws.spans.push(spans[i]);
+                }
+            }
+            offsets.push(ws.words.len());
+        }
+        offsets
+    }
+
+    /// The tokens of the file as a CorpusSequence, keyed by `to_t` and
+    /// keeping only those satisfying `is_word`.
+    pub fn corpus_sequence<'a,T,F,P>(&'a self, to_t: F, is_word: P) -> CorpusSequence<T>
+        where T: Hash + Eq + Clone,
+              F: Fn(&'a str) -> T,
+              P: Fn(&'a str) -> bool {
+        let mut sequence = WordSequence::empty();
+        let offsets = self.append_to(&mut sequence, to_t, is_word);
+        CorpusSequence { sequence, offsets }
+    }
+}
+
+#[cfg(test)]
+fn test_corpus() -> Corpus {
+    let mut corpus = Corpus::new();
+    corpus.add_document("one", "The cat, the hat.".to_string());
+    corpus.add_document("empty", String::new());
+    corpus.add_document("two", "Caf\u{e9} cat".to_string());
+    corpus
+}
+
+#[test]
+fn test_round_trip() {
+    let corpus = test_corpus();
+    let cs = corpus.tokenize(|s| s, |_| true);
+    let mut bytes = Vec::new();
+    unwrap!( write(&mut bytes, &corpus, &cs, "run").ok() );
+    assert!(is_tokenized(&bytes));
+    let tc = unwrap!( TokenizedCorpus::from_source(Box::new(bytes)).ok() );
+    assert_eq!(tc.len(), 3);
+    assert_eq!(tc.tokenizer(), Some("run"));
+    assert_eq!(tc.words(), cs.words());
+    assert_eq!(tc.spans(), &cs.sequence.spans[..]);
+    assert_eq!(tc.offsets(), &cs.offsets[..]);
+    assert_eq!(tc.document_name(1), "empty");
+    assert_eq!(tc.document_name(2), "two");
+    assert_eq!(tc.document_text(2), "Caf\u{e9} cat");
+    let span = tc.document_spans(2)[0];
+    assert_eq!(&tc.document_text(2)[span.start..span.end], "Caf\u{e9}");
+    let vocabulary = tc.vocabulary();
+    assert_eq!(vocabulary.len(), cs.sequence.vocabulary.len());
+    assert_eq!(vocabulary.to_word(&"cat"), cs.sequence.vocabulary.to_word(&"cat"));
+    assert_eq!(vocabulary.count(unwrap!( vocabulary.to_word(&"cat") )), 2);
+    assert_eq!(vocabulary.class_of(unwrap!( vocabulary.to_word(&",") )), Some(CharClass::Other));
+}
+
+#[test]
+fn test_corpus_sequence() {
+    use case_string::CaseStr;
+    let corpus = test_corpus();
+    let cs = corpus.tokenize(|s| s, |_| true);
+    let mut bytes = Vec::new();
+    unwrap!( write(&mut bytes, &corpus, &cs, "run").ok() );
+    let tc = unwrap!( TokenizedCorpus::from_source(Box::new(bytes)).ok() );
+    let folded = tc.corpus_sequence(CaseStr::from, |s| s.chars().all(char::is_alphabetic));
+    assert_eq!(folded.offsets, vec!(0,4,4,6));
+    assert_eq!(folded.document(0), &[0,1,0,2]);
+    assert_eq!(folded.document(2), &[3,1]);
+    assert_eq!(folded.sequence.texts, vec!(0,4,4));
+}
+
+#[test]
+fn test_invalid() {
+    let corpus = test_corpus();
+    let cs = corpus.tokenize(|s| s, |_| true);
+    let mut bytes = Vec::new();
+    unwrap!( write(&mut bytes, &corpus, &cs, "run").ok() );
+    let mut truncated = bytes.clone();
+    truncated.pop();
+    assert!(TokenizedCorpus::from_source(Box::new(truncated)).is_err());
+    let mut bad_version = bytes.clone();
+    bad_version[8] = 99;
+    match TokenizedCorpus::from_source(Box::new(bad_version)) {
+        Err(Error::InvalidFormat(ref m)) => assert_eq!(m, "unsupported version 99"),
+        _ => panic!("unexpected result"),
+    }
+    let mut bad_tokenizer = bytes.clone();
+    bad_tokenizer[12] = 99;
+    assert!(TokenizedCorpus::from_source(Box::new(bad_tokenizer)).is_err());
+    let mut unrecorded = Vec::new();
+    unwrap!( write(&mut unrecorded, &corpus, &cs, "custom").ok() );
+    assert_eq!(unwrap!( TokenizedCorpus::from_source(Box::new(unrecorded)).ok() ).tokenizer(), None);
+    let mut bad_id = bytes.clone();
+    bad_id[HEADER_LEN] = 100;
+    assert!(TokenizedCorpus::from_source(Box::new(bad_id)).is_err());
+    assert!(TokenizedCorpus::from_source(Box::new(b"The cat".to_vec())).is_err());
+}
+
+#[test]
+fn test_unaligned() {
+    struct Offset(Vec<u8>);
+    impl TextSource for Offset {
+        fn get_slice(&self) -> &[u8] { &self.0[1..] }
+    }
+    let corpus = test_corpus();
+    let cs = corpus.tokenize(|s| s, |_| true);
+    let mut bytes = vec!(0);
+    unwrap!( write(&mut bytes, &corpus, &cs, "run").ok() );
+    let tc = unwrap!( TokenizedCorpus::from_source(Box::new(Offset(bytes))).ok() );
+    assert_eq!(tc.words(), cs.words());
+}
@@ -0,0 +1,337 @@
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use error::{Error,Result};
+use ptb::PtbTokenizer;
+use word_sequence::{CharClass,Span};
+
+/// A token found by a Tokenizer: its position in the text and the class of
+/// its first character. A tokenizer that rewrites tokens (for example,
+/// replacing `(` with `-LRB-`) gives the replacement in `form`.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub struct Token {
+    pub class: CharClass,
+    pub span: Span,
+    pub form: Option<&'static str>,
+}
+
+impl Token {
+    pub fn new(class: CharClass, start: usize, end: usize) -> Token {
+        Token { class, span: Span::new(start, end), form: None }
+    }
+
+    pub fn with_form(class: CharClass, start: usize, end: usize, form: &'static str) -> Token {
+        Token { class, span: Span::new(start, end), form: Some(form) }
+    }
+
+    /// The token's text: its replacement form, or else its span of the source.
+    pub fn text<'a>(&self, text: &'a str) -> &'a str {
+        self.form.unwrap_or(&text[self.span.start..self.span.end])
+    }
+}
+
+/// Splits text into tokens. Whitespace is never a token.
+pub trait Tokenizer {
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Token> + 'a>;
+}
+
+/// The names of the tokenizers that `named` knows.
+pub const NAMES: [&str; 3] = ["run", "english", "ptb"];
+
+/// The tokenizer with a name: "run", "english" or "ptb".
+pub fn named(name: &str) -> Option<Box<dyn Tokenizer + Sync>> {
+    match name {
+        "run"     => Some(Box::new(RunTokenizer)),
+        "english" => Some(Box::new(EnglishTokenizer)),
+        "ptb"     => Some(Box::new(PtbTokenizer)),
+        _         => None,
+    }
+}
+
+// ----------------------------------------
+
+/// The original tokenizer: a token is a run of grapheme clusters of one
+/// CharClass, except that a double quote always starts a new token and
+/// ideographs and emoji are tokens on their own.
+///
+/// ```text
+/// this is a test; 1, 2, three
+/// aaaawaawawaaaaownownowaaaaa
+/// 0   45 7891   111111222
+///           0   456789012
+///
+/// [0,4), [5,7), [8,9), [10,14), [14,15)...
+/// ```
+#[derive(Clone,Copy,Debug,Default)]
+pub struct RunTokenizer;
+
+impl Tokenizer for RunTokenizer {
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> {
+        Box::new(Runs { text, pos: 0 })
+    }
+}
+
+struct Runs<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let rest = &self.text[self.pos..];
+            let mut graphemes = rest.grapheme_indices(true);
+            let (_,first) = graphemes.next()?;
+            let class = CharClass::classify_grapheme(first);
+            let len = if class.is_singleton() {
+                first.len()
+            } else {
+                graphemes
+                    .find(|&(_,g)| CharClass::classify_grapheme(g) != class || g == "\"")
+                    .map(|(i,_)| i)
+                    .unwrap_or(rest.len())
+            };
+            let start = self.pos;
+            self.pos += len;
+            if class != CharClass::Whitespace {
+                return Some(Token::new(class, start, self.pos));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn token_strs<'a, K: Tokenizer>(tokenizer: &K, text: &'a str) -> Vec<&'a str> {
+    tokenizer.tokens(text).map(|t| t.text(text)).collect()
+}
+
+#[test]
+fn test_run_tokenizer() {
+    assert_eq!(token_strs(&RunTokenizer, "this is a test; 1, 2, three"),
+               vec!("this", "is", "a", "test", ";", "1", ",", "2", ",", "three"));
+    assert_eq!(token_strs(&RunTokenizer, "\"What's\"--\""),
+               vec!("\"", "What", "'", "s", "\"--", "\""));
+    assert_eq!(RunTokenizer.tokens("  3.14").next(), Some(Token::new(CharClass::Numeric, 2, 3)));
+    // Combining marks stay with their base; ideographs and emoji stand alone.
+    assert_eq!(token_strs(&RunTokenizer, "na\u{308}ive \u{4e2d}\u{6587}."),
+               vec!("na\u{308}ive", "\u{4e2d}", "\u{6587}", "."));
+    assert_eq!(token_strs(&RunTokenizer, "ok\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{1f1fa}\u{1f1f8}"),
+               vec!("ok", "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}", "\u{1f1fa}\u{1f1f8}"));
+}
+
+// ----------------------------------------
+
+/// A rule-based tokenizer for English. It keeps together contractions
+/// (`What's`, `don't`), hyphenated compounds (`well-known`), numbers with
+/// decimal points and digit separators (`3.14`, `1,000`), ordinals (`21st`),
+/// URLs and e-mail addresses. Other punctuation is split into single
+/// characters, except that runs of one character (`--`, `...`) stay whole.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct EnglishTokenizer;
+
+impl Tokenizer for EnglishTokenizer {
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> {
+        Box::new(EnglishTokens { text, pos: 0 })
+    }
+}
+
+struct EnglishTokens<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for EnglishTokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let rest = &self.text[self.pos..];
+        let skip = rest.len() - rest.trim_start().len();
+        self.pos += skip;
+        let rest = &rest[skip..];
+        let first = rest.chars().next()?;
+        let grapheme = unwrap!( rest.graphemes(true).next() );
+        let (class, len) = if CharClass::classify_grapheme(grapheme).is_singleton() {
+            (CharClass::classify_grapheme(grapheme), grapheme.len())
+        } else if let Some(len) = url_len(rest).or_else(|| email_len(rest)) {
+            (CharClass::Alphabetic, len)
+        } else if first.is_numeric() {
+            (CharClass::Numeric, number_len(rest))
+        } else if first.is_alphanumeric() {
+            (CharClass::classify(first), word_len(rest))
+        } else {
+            let len = rest.chars().take_while(|&ch| ch == first).map(char::len_utf8).sum();
+            (CharClass::classify(first), len)
+        };
+        let start = self.pos;
+        self.pos += len;
+        Some(Token::new(class, start, self.pos))
+    }
+}
+
+fn is_apostrophe(ch: char) -> bool { ch == '\'' || ch == '\u{2019}' }
+
+// Whether a grapheme cluster can be part of a word.
+fn is_word_grapheme(g: &str) -> bool {
+    matches!(CharClass::classify_grapheme(g), CharClass::Alphabetic | CharClass::Numeric)
+}
+
+// The length of a word: alphanumeric runs joined by single apostrophes or
+// hyphens. Combining marks stay with the letters they follow.
+fn word_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut graphemes = s.grapheme_indices(true).peekable();
+    while let Some((i,g)) = graphemes.next() {
+        if is_word_grapheme(g) {
+            len = i + g.len();
+        } else if g.chars().all(|ch| is_apostrophe(ch) || ch == '-') && g.chars().count() == 1 {
+            match graphemes.peek() {
+                Some(&(_,next)) if is_word_grapheme(next) => { }
+                _ => break,
+            }
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+// The length of a number: digits, with single internal '.' or ',' separators,
+// and an optional ordinal suffix.
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut len = 0;
+    while len < bytes.len() {
+        let separator = (bytes[len] == b'.' || bytes[len] == b',')
+            && len > 0 && len + 1 < bytes.len() && bytes[len+1].is_ascii_digit();
+        if !(bytes[len].is_ascii_digit() || separator) { break; }
+        len += 1;
+    }
+    if len == 0 {
+        // A non-ASCII numeral.
+        return s.chars().take_while(|ch| ch.is_numeric()).map(char::len_utf8).sum();
+    }
+    let suffix = &s[len..];
+    for ord in &["st", "nd", "rd", "th"] {
+        if suffix.len() >= 2 && suffix[..2].eq_ignore_ascii_case(ord)
+            && !suffix[2..].starts_with(|ch: char| ch.is_alphanumeric()) {
+            return len + 2;
+        }
+    }
+    len
+}
+
+// Trailing punctuation that ends a sentence rather than a URL or address.
+fn trim_trailing_punctuation(s: &str) -> usize {
+    s.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']).len()
+}
+
+fn url_len(s: &str) -> Option<usize> {
+    let starts_with = |prefix: &str| s.len() >= prefix.len() && s.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes());
+    if !(starts_with("http://") || starts_with("https://") || starts_with("www.")) {
+        return None;
+    }
+    let end = s.find(|ch: char| ch.is_whitespace() || ch == '<' || ch == '>').unwrap_or(s.len());
+    Some(trim_trailing_punctuation(&s[..end]))
+}
+
+// The longest local part and domain an address may have, which also bound
+// the scan at each token start.
+const MAX_LOCAL: usize = 64;
+const MAX_DOMAIN: usize = 255;
+
+fn email_len(s: &str) -> Option<usize> {
+    let is_local = |ch: char| ch.is_alphanumeric() || "._%+-".contains(ch);
+    let is_domain = |ch: char| ch.is_alphanumeric() || ch == '.' || ch == '-';
+    let (at,_) = s.char_indices().take(MAX_LOCAL + 1).find(|&(_,ch)| !is_local(ch))?;
+    if at == 0 || !s[at..].starts_with('@') { return None; }
+    let rest = &s[at+1..];
+    let domain_len = match rest.char_indices().take(MAX_DOMAIN + 1).find(|&(_,ch)| !is_domain(ch)) {
+        Some((i,_)) => i,
+        None if rest.chars().nth(MAX_DOMAIN).is_none() => rest.len(),
+        None => return None,
+    };
+    let domain = &s[at+1..at+1+trim_trailing_punctuation(&s[at+1..at+1+domain_len])];
+    match domain.rfind('.') {
+        Some(dot) if dot > 0 && domain.len() - dot > 2 => Some(at + 1 + domain.len()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_english_tokenizer() {
+    assert_eq!(token_strs(&EnglishTokenizer, "\"What's gone with that boy, I wonder?\""),
+               vec!("\"", "What's", "gone", "with", "that", "boy", ",", "I", "wonder", "?", "\""));
+    assert_eq!(token_strs(&EnglishTokenizer, "a well-known fact--pi is 3.14, not 1,000."),
+               vec!("a", "well-known", "fact", "--", "pi", "is", "3.14", ",", "not", "1,000", "."));
+    assert_eq!(token_strs(&EnglishTokenizer, "the 21st and 2nd; 'quoted' ..."),
+               vec!("the", "21st", "and", "2nd", ";", "'", "quoted", "'", "..."));
+    assert_eq!(token_strs(&EnglishTokenizer, "See http://www.gutenberg.net. Mail gbnewby@pglaf.org!"),
+               vec!("See", "http://www.gutenberg.net", ".", "Mail", "gbnewby@pglaf.org", "!"));
+    assert_eq!(token_strs(&EnglishTokenizer, "the cafe\u{301}'s \u{4e2d}\u{6587} \u{1f44d}\u{1f3fd}!"),
+               vec!("the", "cafe\u{301}'s", "\u{4e2d}", "\u{6587}", "\u{1f44d}\u{1f3fd}", "!"));
+    assert_eq!(token_strs(&EnglishTokenizer, "WWW.caf\u{e9}.fr HTTP\u{e9}"),
+               vec!("WWW.caf\u{e9}.fr", "HTTP\u{e9}"));
+}
+
+#[test]
+fn test_long_runs() {
+    // Each token start looks only a bounded way ahead for a URL or address.
+    let text = "\u{e9}".repeat(20000);
+    assert_eq!(EnglishTokenizer.tokens(&text).count(), 1);
+    let text = "a.".repeat(20000);
+    assert_eq!(EnglishTokenizer.tokens(&text).count(), 40000);
+    let text = format!("{}@example.com {}@example.com", "a".repeat(64), "a".repeat(65));
+    let tokens = token_strs(&EnglishTokenizer, &text);
+    assert_eq!(tokens[0], &text[..76]);
+    assert_eq!(tokens[1], "a".repeat(65));
+}
+
+// ----------------------------------------
+
+/// A tokenizer driven by regular expressions: every match of the pattern is
+/// a token and anything between matches is skipped.
+#[derive(Clone,Debug)]
+pub struct RegexTokenizer {
+    re: Regex,
+}
+
+impl RegexTokenizer {
+    pub fn new(pattern: &str) -> Result<RegexTokenizer> {
+        match Regex::new(pattern) {
+            Ok(re) => Ok(RegexTokenizer { re }),
+            Err(e) => Err(Error::InvalidPattern(e.to_string())),
+        }
+    }
+
+    /// A tokenizer matching any of several patterns, preferring earlier ones.
+    pub fn from_patterns<S: AsRef<str>>(patterns: &[S]) -> Result<RegexTokenizer> {
+        let alternatives: Vec<String> = patterns.iter()
+            .map(|p| format!("(?:{})", p.as_ref()))
+            .collect();
+        RegexTokenizer::new(&alternatives.join("|"))
+    }
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn tokens<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> {
+        Box::new(self.re.find_iter(text)
+                 .filter(|m| !m.as_str().trim().is_empty())
+                 .map(|m| {
+                     let class = CharClass::classify(unwrap!( m.as_str().chars().next() ));
+                     Token::new(class, m.start(), m.end())
+                 }))
+    }
+}
+
+#[test]
+fn test_regex_tokenizer() {
+    let tok = unwrap!( RegexTokenizer::from_patterns(&[r"\d+(?:\.\d+)?", r"\w+(?:'\w+)*", r"[^\w\s]+"]).ok() );
+    assert_eq!(token_strs(&tok, "What's 3.14?!"), vec!("What's", "3.14", "?!"));
+    assert_eq!(tok.tokens("What's").next().map(|t| t.class), Some(CharClass::Alphabetic));
+    match RegexTokenizer::new("(") {
+        Err(Error::InvalidPattern(_)) => { }
+        _ => panic!("invalid pattern accepted"),
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Index;
+
+use word_sequence::{CharClass,Word};
+
+/// The id returned for words that are not in a vocabulary, unless the
+/// vocabulary has been given an unknown-word symbol of its own.
+pub const UNKNOWN: Word = Word::MAX;
+
+/// A mapping between word types and dense ids `0..len()`, with the class of
+/// each type and the number of times it has been counted. A frozen
+/// vocabulary gains no new types; new words get the unknown-word id.
+#[derive(Debug,Clone)]
+pub struct Vocabulary<T: Hash + Eq + Clone> {
+    to_word: HashMap<T,Word>,
+    from_word: Vec<T>,
+    classes: Vec<CharClass>,
+    counts: Vec<usize>,
+    unknown: Word,
+    frozen: bool,
+}
+
+impl<T: Hash + Eq + Clone> Default for Vocabulary<T> {
+    fn default() -> Vocabulary<T> { Vocabulary::new() }
+}
+
+impl<T: Hash + Eq + Clone> Vocabulary<T> {
+    pub fn new() -> Vocabulary<T> {
+        Vocabulary {
+            to_word: HashMap::new(),
+            from_word: Vec::new(),
+            classes: Vec::new(),
+            counts: Vec::new(),
+            unknown: UNKNOWN,
+            frozen: false,
+        }
+    }
+
+    /// The number of word types.
+    pub fn len(&self) -> usize { self.from_word.len() }
+    pub fn is_empty(&self) -> bool { self.from_word.is_empty() }
+
+    /// The id of a word, adding it if it is new, without counting it.
+    pub fn insert(&mut self, word: T, class: CharClass) -> Word {
+        if let Some(&w) = self.to_word.get(&word) {
+            return w;
+        }
+        if self.frozen {
+            return self.unknown;
+        }
+        let w = self.from_word.len();
+        self.to_word.insert(word.clone(), w);
+        self.from_word.push(word);
+        self.classes.push(class);
+        self.counts.push(0);
+        w
+    }
+
+    /// Count one occurrence of a word, adding it if it is new.
+    pub fn add(&mut self, word: T, class: CharClass) -> Word {
+        self.add_count(word, class, 1)
+    }
+
+    /// Count `n` occurrences of a word, adding it if it is new.
+    pub fn add_count(&mut self, word: T, class: CharClass, n: usize) -> Word {
+        let w = self.insert(word, class);
+        if let Some(c) = self.counts.get_mut(w) {
+            *c += n;
+        }
+        w
+    }
+
+    /// Stop adding new word types.
+    pub fn freeze(&mut self) { self.frozen = true; }
+    pub fn is_frozen(&self) -> bool { self.frozen }
+
+    pub fn to_word(&self, t: &T) -> Option<Word> { self.to_word.get(t).cloned() }
+
+    /// The id of a word, or the unknown-word id.
+    pub fn to_word_default(&self, t: &T) -> Word { self.to_word(t).unwrap_or(self.unknown) }
+
+    pub fn from_word(&self, word: Word) -> Option<&T> { self.from_word.get(word) }
+
+    pub fn class_of(&self, word: Word) -> Option<CharClass> { self.classes.get(word).cloned() }
+
+    /// The number of times a word has been counted.
+    pub fn count(&self, word: Word) -> usize { self.counts.get(word).cloned().unwrap_or(0) }
+    pub fn counts(&self) -> &[usize] { &self.counts }
+
+    /// The id used for unknown words.
+    pub fn unknown(&self) -> Word { self.unknown }
+
+    /// Add a symbol to stand for unknown words and use its id for them.
+    pub fn set_unknown(&mut self, symbol: T, class: CharClass) -> Word {
+        let frozen = self.frozen;
+        self.frozen = false;
+        self.unknown = self.insert(symbol, class);
+        self.frozen = frozen;
+        self.unknown
+    }
+
+    /// Add the types and counts of another vocabulary, in its id order, so
+    /// that merging the vocabularies of consecutive pieces of a text gives
+    /// the ids of the whole. Returns the new id of each of `other`'s ids.
+    pub fn merge(&mut self, other: &Vocabulary<T>) -> Vec<Word> {
+        other.iter()
+            .map(|(w,t)| self.add_count(t.clone(), other.classes[w], other.counts[w]))
+            .collect()
+    }
+
+    /// The word types, in id order.
+    pub fn iter(&self) -> impl Iterator<Item=(Word,&T)> {
+        self.from_word.iter().enumerate()
+    }
+
+    /// Renumber the ids so that more frequent words have smaller ids; ties
+    /// keep their existing order. Returns the new id of each old id.
+    pub fn renumber_by_frequency(&mut self) -> Vec<Word> {
+        let mut order: Vec<Word> = (0..self.len()).collect();
+        order.sort_by(|&l,&r| self.counts[r].cmp(&self.counts[l]).then(l.cmp(&r)));
+        let mut mapping = vec!(0; self.len());
+        for (new,&old) in order.iter().enumerate() {
+            mapping[old] = new;
+        }
+        self.from_word = order.iter().map(|&old| self.from_word[old].clone()).collect();
+        self.classes = order.iter().map(|&old| self.classes[old]).collect();
+        self.counts = order.iter().map(|&old| self.counts[old]).collect();
+        for w in self.to_word.values_mut() {
+            *w = mapping[*w];
+        }
+        if self.unknown != UNKNOWN {
+            self.unknown = mapping[self.unknown];
+        }
+        mapping
+    }
+
+    /// Remove the word types for which `keep(word, count)` is false and make
+    /// `symbol` the unknown word, adding it if need be. The pruned types'
+    /// counts go to the unknown word. Surviving types keep their relative
+    /// order. Returns the new id of each old id; pruned ids map to the
+    /// unknown word.
+    pub fn prune<P>(&mut self, mut keep: P, symbol: T, class: CharClass) -> Vec<Word>
+        where P: FnMut(&T,usize) -> bool {
+        let kept: Vec<bool> = self.from_word.iter().zip(self.counts.iter())
+            .map(|(t,&c)| keep(t, c))
+            .collect();
+        self.retain(&kept, symbol, class)
+    }
+
+    /// Keep only the types counted at least `min_count` times.
+    pub fn prune_min_count(&mut self, min_count: usize, symbol: T, class: CharClass) -> Vec<Word> {
+        self.prune(|_,c| c >= min_count, symbol, class)
+    }
+
+    /// Keep only the `n` most frequent types, ties going to the smaller id,
+    /// plus the unknown word.
+    pub fn prune_top_n(&mut self, n: usize, symbol: T, class: CharClass) -> Vec<Word> {
+        let mut order: Vec<Word> = (0..self.len()).filter(|&w| self.from_word[w] != symbol).collect();
+        order.sort_by(|&l,&r| self.counts[r].cmp(&self.counts[l]).then(l.cmp(&r)));
+        let mut kept = vec!(false; self.len());
+        for &w in order.iter().take(n) {
+            kept[w] = true;
+        }
+        self.retain(&kept, symbol, class)
+    }
+
+    fn retain(&mut self, kept: &[bool], symbol: T, class: CharClass) -> Vec<Word> {
+        let kept: Vec<bool> = (0..self.len()).map(|w| kept[w] || self.from_word[w] == symbol).collect();
+        let mut pruned = Vocabulary::new();
+        let mut mapping = vec!(UNKNOWN; self.len());
+        for w in (0..self.len()).filter(|&w| kept[w]) {
+            mapping[w] = pruned.insert(self.from_word[w].clone(), self.classes[w]);
+            pruned.counts[mapping[w]] = self.counts[w];
+        }
+        let unknown = pruned.set_unknown(symbol, class);
+        for w in (0..self.len()).filter(|&w| !kept[w]) {
+            mapping[w] = unknown;
+            pruned.counts[unknown] += self.counts[w];
+        }
+        pruned.frozen = self.frozen;
+        *self = pruned;
+        mapping
+    }
+}
+
+impl<T: Hash + Eq + Clone> Index<Word> for Vocabulary<T> {
+    type Output = T;
+    fn index(&self, idx: Word) -> &T {
+        match self.from_word(idx) {
+            Some(v) => v,
+            None => panic!("unknown word index {}", idx),
+        }
+    }
+}
+
+#[test]
+fn test_vocabulary() {
+    let mut v = Vocabulary::new();
+    assert_eq!(v.add("a", CharClass::Alphabetic), 0);
+    assert_eq!(v.add("b", CharClass::Alphabetic), 1);
+    assert_eq!(v.add("a", CharClass::Alphabetic), 0);
+    assert_eq!(v.insert(",", CharClass::Other), 2);
+    assert_eq!(v.len(), 3);
+    assert_eq!(v[1], "b");
+    assert_eq!(v.count(0), 2);
+    assert_eq!(v.count(2), 0);
+    assert_eq!(v.class_of(2), Some(CharClass::Other));
+    assert_eq!(v.to_word_default(&"c"), UNKNOWN);
+    let unk = v.set_unknown("<unk>", CharClass::Other);
+    assert_eq!(unk, 3);
+    assert_eq!(v.to_word_default(&"c"), 3);
+}
+
+#[test]
+fn test_renumber_by_frequency() {
+    let mut v = Vocabulary::new();
+    for w in &["x", "y", "z", "y", "z", "z"] {
+        v.add(*w, CharClass::Alphabetic);
+    }
+    v.set_unknown("<unk>", CharClass::Other);
+    let mapping = v.renumber_by_frequency();
+    assert_eq!(mapping, vec!(2,1,0,3));
+    assert_eq!(v[0], "z");
+    assert_eq!(v.to_word(&"x"), Some(2));
+    assert_eq!(v.counts(), &[3,2,1,0]);
+    assert_eq!(v.unknown(), 3);
+}
+
+#[test]
+fn test_frozen() {
+    let mut v = Vocabulary::new();
+    v.add("a", CharClass::Alphabetic);
+    v.freeze();
+    assert_eq!(v.add("a", CharClass::Alphabetic), 0);
+    assert_eq!(v.add("b", CharClass::Alphabetic), UNKNOWN);
+    assert_eq!(v.len(), 1);
+    let unk = v.set_unknown("<unk>", CharClass::Other);
+    assert_eq!(v.add("c", CharClass::Alphabetic), unk);
+    assert_eq!(v.count(unk), 1);
+    assert_eq!(v.count(0), 2);
+    assert!(v.is_frozen());
+}
+
+#[test]
+fn test_prune() {
+    let mut v = Vocabulary::new();
+    for w in &["x", "y", "z", "y", "z", "z", "w"] {
+        v.add(*w, CharClass::Alphabetic);
+    }
+    let mapping = v.clone().prune_min_count(2, "<unk>", CharClass::Other);
+    assert_eq!(mapping, vec!(2,0,1,2));
+    let mapping = v.prune_top_n(1, "<unk>", CharClass::Other);
+    assert_eq!(mapping, vec!(1,1,0,1));
+    assert_eq!(v.len(), 2);
+    assert_eq!(v[1], "<unk>");
+    assert_eq!(v.unknown(), 1);
+    assert_eq!(v.counts(), &[3,4]);
+    assert_eq!(v.to_word_default(&"x"), 1);
+}
+
+#[test]
+fn test_merge() {
+    let mut whole = Vocabulary::new();
+    let mut first = Vocabulary::new();
+    let mut second = Vocabulary::new();
+    for (i,w) in ["a", "b", "a", "c", "b", "d"].iter().enumerate() {
+        whole.add(*w, CharClass::Alphabetic);
+        if i < 3 { first.add(*w, CharClass::Alphabetic); } else { second.add(*w, CharClass::Alphabetic); }
+    }
+    assert_eq!(first.merge(&second), vec!(2,1,3));
+    assert_eq!(first.iter().collect::<Vec<_>>(), whole.iter().collect::<Vec<_>>());
+    assert_eq!(first.counts(), whole.counts());
+}
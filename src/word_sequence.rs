@@ -1,24 +1,61 @@
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::ops::Index;
+use std::ops::{Index,Range};
+use std::str;
 
-#[derive(PartialEq,Eq,Debug)]
+use error::Result;
+use tokenizer::{RunTokenizer,Tokenizer};
+use vocabulary::Vocabulary;
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
 pub enum CharClass {
     Alphabetic,
     Numeric,
     Whitespace,
     Control,
     Other,
+    /// CJK ideographs, each of which is a word on its own.
+    Ideographic,
+    /// Emoji, including modified and joined sequences.
+    Emoji,
 }
 
 impl CharClass {
     pub fn classify(ch: char) -> CharClass {
-        if ch.is_alphabetic() { CharClass::Alphabetic }
+        if is_ideographic(ch) { CharClass::Ideographic }
+        else if ch.is_alphabetic() { CharClass::Alphabetic }
         else if ch.is_numeric() { CharClass::Numeric }
         else if ch.is_whitespace() { CharClass::Whitespace }
         else if ch.is_control() { CharClass::Control }
+        else if is_emoji(ch) { CharClass::Emoji }
         else { CharClass::Other }
     }
+
+    /// The class of a grapheme cluster is the class of its first character,
+    /// so combining marks and joined sequences take the class of their base.
+    pub fn classify_grapheme(g: &str) -> CharClass {
+        g.chars().next().map(CharClass::classify).unwrap_or(CharClass::Whitespace)
+    }
+
+    /// Whether each grapheme of this class is a separate token, rather than
+    /// part of a run.
+    pub fn is_singleton(self) -> bool {
+        self == CharClass::Ideographic || self == CharClass::Emoji
+    }
+}
+
+fn is_ideographic(ch: char) -> bool {
+    matches!(ch as u32,
+             0x3005..=0x3007 | 0x3021..=0x3029 |
+             0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xf900..=0xfaff |
+             0x20000..=0x2fa1f | 0x30000..=0x323af)
+}
+
+fn is_emoji(ch: char) -> bool {
+    matches!(ch as u32,
+             0x231a..=0x231b | 0x23e9..=0x23fa | 0x2600..=0x27bf |
+             0x2b1b..=0x2b1c | 0x2b50 | 0x2b55 |
+             0x1f000..=0x1faff)
 }
 
 #[test]
@@ -27,21 +64,41 @@ fn test_char_class() {
     assert_eq!(CharClass::classify('1'), CharClass::Numeric);
     assert_eq!(CharClass::classify(' '), CharClass::Whitespace);
     assert_eq!(CharClass::classify(';'), CharClass::Other);
+    assert_eq!(CharClass::classify('\u{4e2d}'), CharClass::Ideographic);
+    assert_eq!(CharClass::classify('\u{3042}'), CharClass::Alphabetic);
+    assert_eq!(CharClass::classify('\u{1f600}'), CharClass::Emoji);
+    assert_eq!(CharClass::classify_grapheme("e\u{301}"), CharClass::Alphabetic);
 }
 
 // ----------------------------------------
 
 pub type Word = usize;
-pub type ToWord<T> = HashMap<T,Word>;
-pub type FromWord<T> = HashMap<Word,T>;
-pub type ClassMap = HashMap<Word,CharClass>;
 
+/// The byte range [start,end) of a token in its source text. The layout is
+/// fixed so that spans can be viewed in place in a tokenized file.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+#[repr(C)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span { Span { start, end } }
+    pub fn len(&self) -> usize { self.end - self.start }
+    pub fn is_empty(&self) -> bool { self.start == self.end }
+}
+
+/// A sequence of tokens. `spans` runs parallel to `words`, giving each
+/// token's position in the text it was read from. Each `append` adds a text
+/// of its own, whose spans start again from zero; `texts` holds the index of
+/// each text's first token.
 #[derive(Debug)]
 pub struct WordSequence<T: Eq + Hash + Clone> {
-    pub to_word: ToWord<T>,
-    pub from_word: FromWord<T>,
-    pub class_of_word: ClassMap,
+    pub vocabulary: Vocabulary<T>,
     pub words: Vec<Word>,
+    pub spans: Vec<Span>,
+    pub texts: Vec<usize>,
 }
 
 impl<T: Hash + Eq + Clone> WordSequence<T> {
@@ -51,30 +108,182 @@ impl<T: Hash + Eq + Clone> WordSequence<T> {
         initialize_word_sequence(text, to_t, is_word)
     }
 
-    pub fn insert_word(&mut self, word:T, class: CharClass) -> Word {
-        if self.to_word.get(&word).is_none() {
-            let w = self.from_word.len();
-            self.from_word.insert(w, word.clone());
-            self.to_word.insert(word.clone(), w);
-            self.class_of_word.insert(w, class);
+    /// Build a WordSequence from raw bytes, which must be valid UTF-8.
+    pub fn from_utf8<'l,F,P>(bytes: &'l [u8], to_t: F, is_word: P) -> Result<WordSequence<T>>
+        where F: Fn(&'l str) -> T,
+              P: Fn(&'l str) -> bool {
+        let text = str::from_utf8(bytes)?;
+        Ok( initialize_word_sequence(text, to_t, is_word) )
+    }
+
+    /// An empty WordSequence, to be filled by `append`.
+    pub fn empty() -> WordSequence<T> {
+        WordSequence::from_vocabulary(Vocabulary::new())
+    }
+
+    /// An empty WordSequence whose ids come from an existing vocabulary, so
+    /// that they agree with those of other sequences built from it. If the
+    /// vocabulary is frozen, words not in it get its unknown-word id.
+    pub fn from_vocabulary(vocabulary: Vocabulary<T>) -> WordSequence<T> {
+        WordSequence {
+            vocabulary,
+            words: Vec::new(),
+            spans: Vec::new(),
+            texts: Vec::new(),
+        }
+    }
+
+    /// Build a WordSequence using a given tokenizer rather than the default
+    /// RunTokenizer.
+    pub fn with_tokenizer<'l,K,F,P>(tokenizer: &K, text: &'l str, to_t: F, is_word: P) -> WordSequence<T>
+        where K: Tokenizer + ?Sized,
+              F: Fn(&'l str) -> T,
+              P: Fn(&'l str) -> bool {
+        let mut ws = WordSequence::empty();
+        append_words(&mut ws, tokenizer, text, to_t, is_word);
+        ws
+    }
+
+    /// Tokenize more text onto the end of the sequence, adding any new words
+    /// to the existing vocabulary.
+    pub fn append<'l,F,P>(&mut self, text: &'l str, to_t: F, is_word: P)
+        where F: Fn(&'l str) -> T,
+              P: Fn(&'l str) -> bool {
+        append_words(self, &RunTokenizer, text, to_t, is_word)
+    }
+
+    /// Like `append`, using a given tokenizer.
+    pub fn append_with<'l,K,F,P>(&mut self, tokenizer: &K, text: &'l str, to_t: F, is_word: P)
+        where K: Tokenizer + ?Sized,
+              F: Fn(&'l str) -> T,
+              P: Fn(&'l str) -> bool {
+        append_words(self, tokenizer, text, to_t, is_word)
+    }
+
+    /// Append the tokens of another sequence, built from text that starts at
+    /// `offset` in this sequence's last text, merging its vocabulary into
+    /// this one's. Any further texts of the other sequence are added after
+    /// it, and an empty sequence starts a text.
+    pub fn append_sequence(&mut self, other: &WordSequence<T>, offset: usize) {
+        let mapping = self.vocabulary.merge(&other.vocabulary);
+        let unknown = self.vocabulary.unknown();
+        if self.texts.is_empty() {
+            self.texts.push(self.words.len());
         }
-        self.to_word[&word]
+        let base = self.words.len();
+        self.texts.extend(other.texts.iter().skip(1).map(|&t| base + t));
+        self.words.extend(other.words.iter().map(|&w| mapping.get(w).cloned().unwrap_or(unknown)));
+        let first = other.texts.get(1).cloned().unwrap_or(other.len());
+        self.spans.extend(other.spans[..first].iter().map(|s| Span::new(s.start + offset, s.end + offset)));
+        self.spans.extend_from_slice(&other.spans[first..]);
+    }
+
+    /// Add a word to the vocabulary, without adding it to the sequence.
+    pub fn insert_word(&mut self, word:T, class: CharClass) -> Word {
+        self.vocabulary.insert(word, class)
     }
 
-    pub fn from_word(&self, word: &Word) -> Option<&T> { self.from_word.get(word) }
+    pub fn from_word(&self, word: &Word) -> Option<&T> { self.vocabulary.from_word(*word) }
     pub fn from_word_default<'l>(&'l self, word: &Word, d: &'l T) -> &'l T {
-        self.from_word.get(&word).unwrap_or(d)
+        self.from_word(word).unwrap_or(d)
     }
 
-    pub fn to_word(&self, t: &T) -> Option<Word> { self.to_word.get(t).cloned() }
-    pub fn to_word_default(&self, t: &T) -> Word {
-        match self.to_word(t) {
-            Some(w) => w,
-            None => self.from_word.len(),
+    pub fn to_word(&self, t: &T) -> Option<Word> { self.vocabulary.to_word(t) }
+
+    /// The id of a word, or the vocabulary's unknown-word id.
+    pub fn to_word_default(&self, t: &T) -> Word { self.vocabulary.to_word_default(t) }
+
+    pub fn class_of(&self, word: Word) -> Option<CharClass> { self.vocabulary.class_of(word) }
+
+    /// Count the surface forms of the words, where `text(i)` is the text
+    /// of the i'th text appended. The unknown word has none.
+    pub fn surface_forms<'a,D>(&self, text: D) -> SurfaceForms<'a>
+        where D: Fn(usize) -> &'a str {
+        let mut forms = vec!(HashMap::new(); self.vocabulary.len());
+        let unknown = self.vocabulary.unknown();
+        for t in 0..self.texts.len() {
+            let text = text(t);
+            for i in self.text_tokens(t).filter(|&i| self.words[i] != unknown) {
+                if let Some(forms) = forms.get_mut(self.words[i]) {
+                    let (span, seen) = (self.spans[i], forms.len());
+                    forms.entry(&text[span.start..span.end]).or_insert((0, seen)).0 += 1;
+                }
+            }
+        }
+        SurfaceForms { forms }
+    }
+
+    /// Renumber the vocabulary by descending frequency and rewrite the
+    /// sequence to match.
+    pub fn renumber_by_frequency(&mut self) {
+        let mapping = self.vocabulary.renumber_by_frequency();
+        self.remap(&mapping);
+    }
+
+    /// Remove the word types for which `keep(word, count)` is false,
+    /// replacing their tokens with `unknown`, which is classed as Other.
+    pub fn prune<P: FnMut(&T,usize) -> bool>(&mut self, keep: P, unknown: T) {
+        let mapping = self.vocabulary.prune(keep, unknown, CharClass::Other);
+        self.remap(&mapping);
+    }
+
+    /// Replace the words occurring fewer than `min_count` times with `unknown`.
+    pub fn prune_min_count(&mut self, min_count: usize, unknown: T) {
+        let mapping = self.vocabulary.prune_min_count(min_count, unknown, CharClass::Other);
+        self.remap(&mapping);
+    }
+
+    /// Replace all but the `n` most frequent words with `unknown`.
+    pub fn prune_top_n(&mut self, n: usize, unknown: T) {
+        let mapping = self.vocabulary.prune_top_n(n, unknown, CharClass::Other);
+        self.remap(&mapping);
+    }
+
+    // Tokens outside the old vocabulary, as those a frozen one without an
+    // unknown word left as UNKNOWN, become the new unknown word.
+    fn remap(&mut self, mapping: &[Word]) {
+        let unknown = self.vocabulary.unknown();
+        for w in self.words.iter_mut() {
+            *w = mapping.get(*w).cloned().unwrap_or(unknown);
         }
     }
 
     pub fn len(&self) -> usize { self.words.len() }
+    pub fn is_empty(&self) -> bool { self.words.is_empty() }
+
+    /// The source span of the token at an index.
+    pub fn span(&self, idx: usize) -> Span { self.spans[idx] }
+
+    /// The indices of the tokens of a text.
+    pub fn text_tokens(&self, text: usize) -> Range<usize> {
+        self.texts[text]..self.texts.get(text + 1).cloned().unwrap_or(self.words.len())
+    }
+
+    /// The index of the token containing a byte offset in a text, if any.
+    pub fn token_at(&self, text: usize, offset: usize) -> Option<usize> {
+        if text >= self.texts.len() {
+            return None;
+        }
+        let tokens = self.text_tokens(text);
+        let spans = &self.spans[tokens.clone()];
+        let idx = match spans.binary_search_by(|sp| sp.start.cmp(&offset)) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        if offset < spans[idx].end { Some(tokens.start + idx) } else { None }
+    }
+
+    /// The line and column of the start of the token at an index, given the
+    /// lines of its text.
+    pub fn position(&self, idx: usize, lines: &LineIndex) -> (usize,usize) {
+        lines.line_col(self.spans[idx].start)
+    }
+
+    /// The index of the token at a line and column of a text, if any.
+    pub fn token_at_position(&self, text: usize, lines: &LineIndex, line: usize, column: usize) -> Option<usize> {
+        lines.offset(line, column).and_then(|offset| self.token_at(text, offset))
+    }
 }
 
 impl<T: Hash + Eq + Clone> Index<Word> for WordSequence<T> {
@@ -89,91 +298,154 @@ impl<T: Hash + Eq + Clone> Index<Word> for WordSequence<T> {
 
 // ----------------------------------------
 
-// this is a test; 1, 2, three
-// aaaawaawawaaaaownownowaaaaa
-// 0   45 7891   111111222
-//           0   456789012
-//
-// [0,4), [5,7), [8,9), [10,14), [14,15)...
+/// The surface forms of the words of a sequence: for a key that folds case
+/// or stems, the strings of the text it stood for, with their counts.
+#[derive(Debug)]
+pub struct SurfaceForms<'a> {
+    // For each word, its forms, with their counts and the order in which
+    // they were first seen.
+    forms: Vec<HashMap<&'a str,(usize,usize)>>,
+}
+
+impl<'a> SurfaceForms<'a> {
+    /// The forms of a word, with their counts, in the order first seen.
+    pub fn forms(&self, word: Word) -> Vec<(&'a str,usize)> {
+        let mut forms: Vec<_> = self.forms.get(word).into_iter().flatten()
+            .map(|(&form,&(count,seen))| (seen, form, count))
+            .collect();
+        forms.sort_unstable();
+        forms.into_iter().map(|(_,form,count)| (form, count)).collect()
+    }
+
+    /// The most frequent form of a word, ties going to the first seen;
+    /// None if it has no forms.
+    pub fn canonical(&self, word: Word) -> Option<&'a str> {
+        self.forms.get(word)?.iter()
+            .max_by(|l,r| (l.1).0.cmp(&(r.1).0).then((r.1).1.cmp(&(l.1).1)))
+            .map(|(&form,_)| form)
+    }
+}
+
+/// The starting offsets of the lines in a text, for converting between byte
+/// offsets and (line, column) positions. Lines and columns count from zero;
+/// columns count characters.
+#[derive(Debug)]
+pub struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> LineIndex<'a> {
+        let mut starts = vec!(0);
+        starts.extend(text.match_indices('\n').map(|(i,_)| i + 1));
+        LineIndex { text, starts }
+    }
+
+    pub fn lines(&self) -> usize { self.starts.len() }
+
+    /// The line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize,usize) {
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        (line, self.text[self.starts[line]..offset].chars().count())
+    }
+
+    /// The byte offset of a line and column, if the position is in the text.
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line+1).cloned().unwrap_or(self.text.len());
+        self.text[start..end].char_indices()
+            .nth(column)
+            .map(|(i,_)| start + i)
+    }
+}
+
+// ----------------------------------------
+
 fn initialize_word_sequence<'l,T,F,P>(text: &'l str, trans: F, pred: P) -> WordSequence<T>
     where T: Hash + Eq + Clone,
           F: Fn(&'l str) -> T,
           P: Fn(&'l str) -> bool {
-    let mut from_word  = FromWord::new();
-    let mut to_word = ToWord::new();
-    let mut classes = ClassMap::new();
-    let mut words = Vec::new();
-    let mut word_start = 0;
-    let mut last_cls = CharClass::Whitespace;
-    for (i,ch) in text.char_indices() {
-        if i == 0 {
-            word_start = i;
-            last_cls = CharClass::classify(ch);
-        } else if last_cls != CharClass::classify(ch) || ch == '"' {
-            if last_cls != CharClass::Whitespace && pred(&text[word_start..i]) {
-                updates(trans(&text[word_start..i]),
-                        last_cls,
-                        &mut words,
-                        &mut from_word,
-                        &mut to_word,
-                        &mut classes);
-            }
-            word_start = i;
-            last_cls = CharClass::classify(ch);
+    WordSequence::with_tokenizer(&RunTokenizer, text, trans, pred)
+}
+
+fn append_words<'l,T,K,F,P>(ws: &mut WordSequence<T>, tokenizer: &K, text: &'l str, trans: F, pred: P)
+    where T: Hash + Eq + Clone,
+          K: Tokenizer + ?Sized,
+          F: Fn(&'l str) -> T,
+          P: Fn(&'l str) -> bool {
+    ws.texts.push(ws.words.len());
+    for token in tokenizer.tokens(text) {
+        let word = token.text(text);
+        if pred(word) {
+            updates(trans(word), token.class, token.span, ws);
         }
     }
-    if last_cls != CharClass::Whitespace && pred(&text[word_start..]) {
-        updates(trans(&text[word_start..]),
-                last_cls,
-                &mut words,
-                &mut from_word,
-                &mut to_word,
-                &mut classes);
-    }
-    WordSequence {
-        to_word: to_word,
-        from_word: from_word,
-        class_of_word: classes,
-        words: words,
-    }
-}
-
-fn updates<T: Hash + Eq + Clone>(word: T,
-                                 class: CharClass,
-                                 words: &mut Vec<usize>,
-                                 from_word: &mut FromWord<T>,
-                                 to_word: &mut ToWord<T>,
-                                 classes: &mut HashMap<usize,CharClass>) {
-    let v = to_word.entry(word.clone()).or_insert_with(|| {
-        let n = from_word.len();
-        from_word.insert(n, word.clone());
-        classes.insert(n, class);
-        n
-    });
-    words.push(*v);
+}
+
+fn updates<T: Hash + Eq + Clone>(word: T, class: CharClass, span: Span, ws: &mut WordSequence<T>) {
+    let w = ws.vocabulary.add(word, class);
+    ws.words.push(w);
+    ws.spans.push(span);
 }
 
 #[test]
 fn test_initialize_word_sequence() {
     let ws = initialize_word_sequence("abc 123 ", |s| s, |_| true);
-    assert_eq!(ws.from_word.get(&0), Some(&"abc"));
-    assert_eq!(ws.from_word.get(&1), Some(&"123"));
-    assert_eq!(ws.from_word.get(&2), None);
+    assert_eq!(ws.from_word(&0), Some(&"abc"));
+    assert_eq!(ws.from_word(&1), Some(&"123"));
+    assert_eq!(ws.from_word(&2), None);
     assert_eq!(ws.words,       vec!(0,1));
 }
 
+#[test]
+fn test_spans() {
+    let text = "this is a test; 1, 2, three";
+    let ws = initialize_word_sequence(text, |s| s, |_| true);
+    assert_eq!(ws.spans.len(), ws.words.len());
+    assert_eq!(ws.span(0), Span::new(0,4));
+    assert_eq!(ws.span(4), Span::new(14,15));
+    assert_eq!(ws.span(9), Span::new(22,27));
+    for (i,sp) in ws.spans.iter().enumerate() {
+        assert_eq!(&text[sp.start..sp.end], ws[ws.words[i]]);
+    }
+    assert_eq!(ws.token_at(0, 0), Some(0));
+    assert_eq!(ws.token_at(0, 3), Some(0));
+    assert_eq!(ws.token_at(0, 4), None);
+    assert_eq!(ws.token_at(0, 14), Some(4));
+    assert_eq!(ws.token_at(0, 26), Some(9));
+    assert_eq!(ws.token_at(0, 27), None);
+}
+
+#[test]
+fn test_positions() {
+    let text = "one two\nthree\n\n  four";
+    let ws = WordSequence::new(text, |s| s, |_| true);
+    let lines = LineIndex::new(text);
+    assert_eq!(ws.position(0, &lines), (0,0));
+    assert_eq!(ws.position(1, &lines), (0,4));
+    assert_eq!(ws.position(2, &lines), (1,0));
+    assert_eq!(ws.position(3, &lines), (3,2));
+    assert_eq!(ws.token_at_position(0, &lines, 3, 4), Some(3));
+    assert_eq!(ws.token_at_position(0, &lines, 2, 0), None);
+    assert_eq!(ws.token_at_position(0, &lines, 9, 0), None);
+}
+
 #[test]
 fn test_initialize_word_sequence_2() {
     let ws = initialize_word_sequence("this is a test; 1, 2, three", |s| s, |_| true);
-    assert_eq!(ws.from_word.get(&0), Some(&"this"));
-    assert_eq!(ws.from_word.get(&1), Some(&"is"));
-    assert_eq!(ws.from_word.get(&2), Some(&"a"));
-    assert_eq!(ws.from_word.get(&3), Some(&"test"));
-    assert_eq!(ws.from_word.get(&4), Some(&";"));
-    assert_eq!(ws.from_word.get(&5), Some(&"1"));
-    assert_eq!(ws.from_word.get(&6), Some(&","));
-    assert_eq!(ws.from_word.get(&7), Some(&"2"));
-    assert_eq!(ws.from_word.get(&8), Some(&"three"));
+    assert_eq!(ws.from_word(&0), Some(&"this"));
+    assert_eq!(ws.from_word(&1), Some(&"is"));
+    assert_eq!(ws.from_word(&2), Some(&"a"));
+    assert_eq!(ws.from_word(&3), Some(&"test"));
+    assert_eq!(ws.from_word(&4), Some(&";"));
+    assert_eq!(ws.from_word(&5), Some(&"1"));
+    assert_eq!(ws.from_word(&6), Some(&","));
+    assert_eq!(ws.from_word(&7), Some(&"2"));
+    assert_eq!(ws.from_word(&8), Some(&"three"));
     assert_eq!(ws.words,       vec!(0,1,2,3,4,5,6,7,6,8));
 }
 
@@ -189,3 +461,132 @@ fn test_without_case() {
     let ws = WordSequence::new("This is a test. Is only a test.", CaseStr::from, |_| true);
     assert_eq!(ws.words, vec!(0,1,2,3,4,1,5,2,3,4));
 }
+
+#[test]
+fn test_from_utf8() {
+    use error::Error;
+    let ws: WordSequence<&str> = unwrap!( WordSequence::from_utf8(b"abc 123", |s| s, |_| true).ok() );
+    assert_eq!(ws.words, vec!(0,1));
+    match WordSequence::from_utf8(b"abc \xff", |s: &str| s, |_| true) {
+        Err(Error::InvalidUtf8(4)) => { }
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn test_append() {
+    let mut ws = WordSequence::new("a b", |s| s, |_| true);
+    ws.append("b c", |s| s, |_| true);
+    assert_eq!(ws.words, vec!(0,1,1,2));
+    assert_eq!(ws.from_word(&2), Some(&"c"));
+    assert_eq!(ws.texts, vec!(0,2));
+    assert_eq!(ws.text_tokens(1), 2..4);
+    assert_eq!(ws.token_at(0, 2), Some(1));
+    assert_eq!(ws.token_at(1, 0), Some(2));
+    assert_eq!(ws.token_at(1, 2), Some(3));
+    assert_eq!(ws.token_at(2, 0), None);
+    let lines = LineIndex::new("b c");
+    assert_eq!(ws.token_at_position(1, &lines, 0, 2), Some(3));
+    let mut pieces = WordSequence::empty();
+    pieces.append_sequence(&WordSequence::new("a b", |s| s, |_| true), 0);
+    pieces.append_sequence(&ws, 4);
+    assert_eq!(pieces.texts, vec!(0,4));
+    assert_eq!(pieces.token_at(0, 6), Some(3));
+    assert_eq!(pieces.token_at(1, 0), Some(4));
+}
+
+#[test]
+fn test_with_tokenizer() {
+    use tokenizer::EnglishTokenizer;
+    let ws = WordSequence::with_tokenizer(&EnglishTokenizer, "What's 3.14? What's", |s| s, |_| true);
+    assert_eq!(ws.words, vec!(0,1,2,0));
+    assert_eq!(ws[0], "What's");
+    assert_eq!(ws.class_of(1), Some(CharClass::Numeric));
+}
+
+#[test]
+fn test_multilingual() {
+    let ws = WordSequence::new("caf\u{65}\u{301} \u{4e2d}\u{6587}\u{5b57} \u{1f44d}\u{1f3fd}\u{1f44d}\u{1f3fd}",
+                               |s| s, |_| true);
+    assert_eq!(ws.words, vec!(0,1,2,3,4,4));
+    assert_eq!(ws[0], "caf\u{65}\u{301}");
+    assert_eq!(ws[1], "\u{4e2d}");
+    assert_eq!(ws[4], "\u{1f44d}\u{1f3fd}");
+    assert_eq!(ws.class_of(4), Some(CharClass::Emoji));
+}
+
+#[test]
+fn test_renumber_by_frequency() {
+    let mut ws = WordSequence::new("a b b c c c", |s| s, |_| true);
+    ws.renumber_by_frequency();
+    assert_eq!(ws.words, vec!(2,1,1,0,0,0));
+    assert_eq!(ws[0], "c");
+    assert_eq!(ws.vocabulary.count(0), 3);
+    assert_eq!(ws.to_word_default(&"d"), ::vocabulary::UNKNOWN);
+}
+
+#[test]
+fn test_surface_forms() {
+    use case_string::CaseStr;
+    let texts = ["Tom Sawyer. TOM! tom, Tom", "tom TOM", "sawyer Becky"];
+    let is_word = |s: &str| s.chars().all(char::is_alphabetic);
+    let mut ws = WordSequence::new(texts[0], CaseStr::from, is_word);
+    let tom = unwrap!( ws.to_word(&CaseStr::from("tom")) );
+    assert_eq!(ws.surface_forms(|t| texts[t]).canonical(tom), Some("Tom"));
+    ws.append(texts[1], CaseStr::from, is_word);
+    ws.append(texts[2], CaseStr::from, is_word);
+    let forms = ws.surface_forms(|t| texts[t]);
+    // A tie goes to the form seen first.
+    assert_eq!(forms.forms(tom), vec!(("Tom", 2), ("TOM", 2), ("tom", 2)));
+    assert_eq!(forms.canonical(tom), Some("Tom"));
+    assert_eq!(forms.canonical(1), Some("Sawyer"));
+    ws.prune_min_count(2, CaseStr::from("<unk>"));
+    let forms = ws.surface_forms(|t| texts[t]);
+    assert_eq!(forms.canonical(1), Some("Sawyer"));
+    assert_eq!(forms.canonical(ws.vocabulary.unknown()), None);
+}
+
+#[test]
+fn test_shared_vocabulary() {
+    let training = WordSequence::new("the cat sat", |s| s, |_| true);
+    let mut shared = WordSequence::from_vocabulary(training.vocabulary.clone());
+    shared.append("the dog sat", |s| s, |_| true);
+    assert_eq!(shared.words, vec!(0,3,2));
+    let mut vocabulary = training.vocabulary;
+    vocabulary.set_unknown("<unk>", CharClass::Other);
+    vocabulary.freeze();
+    let mut frozen = WordSequence::from_vocabulary(vocabulary);
+    frozen.append("the dog sat", |s| s, |_| true);
+    assert_eq!(frozen.words, vec!(0,3,2));
+    assert_eq!(frozen[3], "<unk>");
+    assert_eq!(frozen.vocabulary.len(), 4);
+}
+
+#[test]
+fn test_prune() {
+    let mut ws = WordSequence::new("a b b c c c", |s| s, |_| true);
+    ws.prune_min_count(2, "<unk>");
+    assert_eq!(ws.words, vec!(2,0,0,1,1,1));
+    assert_eq!(ws[2], "<unk>");
+    assert_eq!(ws.vocabulary.count(2), 1);
+    let mut ws = WordSequence::new("a b b c c c", |s| s, |_| true);
+    ws.prune_top_n(1, "<unk>");
+    assert_eq!(ws.words, vec!(1,1,1,0,0,0));
+    let mut ws = WordSequence::new("a bb ccc", |s| s, |_| true);
+    ws.prune(|w,_| w.len() > 1, "?");
+    assert_eq!(ws.words, vec!(2,0,1));
+    assert_eq!(ws.to_word_default(&"a"), 2);
+    let training = WordSequence::new("the cat sat", |s| s, |_| true);
+    let mut vocabulary = training.vocabulary;
+    vocabulary.freeze();
+    let mut frozen = WordSequence::from_vocabulary(vocabulary.clone());
+    frozen.append("the dog sat", |s| s, |_| true);
+    assert_eq!(frozen.words, vec!(0,::vocabulary::UNKNOWN,2));
+    frozen.prune_min_count(2, "<unk>");
+    assert_eq!(frozen.words, vec!(0,2,1));
+    assert_eq!(frozen[2], "<unk>");
+    let mut frozen = WordSequence::from_vocabulary(vocabulary);
+    frozen.append("the dog sat", |s| s, |_| true);
+    frozen.renumber_by_frequency();
+    assert_eq!(frozen.words[1], ::vocabulary::UNKNOWN);
+}